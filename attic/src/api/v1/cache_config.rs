@@ -2,6 +2,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use super::webhook::WebhookEvent;
+use crate::cache::CacheName;
 use crate::signing::NixKeypair;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,6 +33,28 @@ pub struct CreateCacheRequest {
     /// The list serves as a hint to clients to avoid uploading
     /// store paths signed with such keys.
     pub upstream_cache_key_names: Vec<String>,
+
+    /// A list of upstream binary caches to pull through.
+    ///
+    /// When a store path doesn't exist in the cache, the upstreams
+    /// are consulted in order and the first hit is served.
+    #[serde(default)]
+    pub upstream_urls: Vec<String>,
+
+    /// Whether to ingest NARs fetched from upstream caches.
+    ///
+    /// Ingested NARs are uploaded to the cache so subsequent
+    /// requests are served locally.
+    #[serde(default)]
+    pub ingest_upstream: bool,
+
+    /// A list of member caches to layer.
+    ///
+    /// A cache with members is virtual. When a store path doesn't
+    /// exist in the cache itself, the members that the client can
+    /// pull from are consulted in order and the first hit is served.
+    #[serde(default)]
+    pub member_caches: Vec<CacheName>,
 }
 
 /// Configuration of a cache.
@@ -61,10 +85,24 @@ pub struct CacheConfig {
 
     /// The public key of the cache, in the canonical format used by Nix.
     ///
-    /// This is read-only and may not be available.
+    /// This is the public key of the current keypair. This is
+    /// read-only and may not be available.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub public_key: Option<String>,
 
+    /// All public keys of the cache, including those of previous keypairs.
+    ///
+    /// This is read-only and may not be available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_keys: Option<Vec<CachePublicKey>>,
+
+    /// Public keys of previous keypairs to revoke.
+    ///
+    /// Revoked keypairs are no longer used to sign objects. The
+    /// current keypair cannot be revoked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoke_public_keys: Option<Vec<String>>,
+
     /// Whether the cache is public or not.
     ///
     /// Anonymous clients are implicitly granted the "pull"
@@ -92,9 +130,64 @@ pub struct CacheConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub upstream_cache_key_names: Option<Vec<String>>,
 
+    /// A list of upstream binary caches to pull through.
+    ///
+    /// When a store path doesn't exist in the cache, the upstreams
+    /// are consulted in order and the first hit is served.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_urls: Option<Vec<String>>,
+
+    /// Whether to ingest NARs fetched from upstream caches.
+    ///
+    /// Ingested NARs are uploaded to the cache so subsequent
+    /// requests are served locally.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingest_upstream: Option<bool>,
+
+    /// A list of member caches to layer.
+    ///
+    /// A cache with members is virtual. When a store path doesn't
+    /// exist in the cache itself, the members that the client can
+    /// pull from are consulted in order and the first hit is served.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub member_caches: Option<Vec<CacheName>>,
+
     /// The retention period of the cache.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retention_period: Option<RetentionPeriodConfig>,
+
+    /// The maximum total NAR size of objects in the cache, in bytes.
+    ///
+    /// Uploads that would exceed the quota are rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_bytes: Option<QuotaConfig>,
+
+    /// The maximum number of objects in the cache.
+    ///
+    /// Uploads that would exceed the quota are rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_objects: Option<QuotaConfig>,
+
+    /// Whether to validate uploaded NARs.
+    ///
+    /// NARs must be well-formed, and the declared references of
+    /// uploaded paths must include all store paths found in the NAR.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validate_nars: Option<bool>,
+
+    /// The chunking parameters of the server.
+    ///
+    /// Clients can use them to chunk NARs locally and only upload
+    /// chunks the server doesn't have. This is read-only and is not
+    /// available if chunking is disabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunking: Option<ChunkingParameters>,
+
+    /// A list of webhooks notified of events in the cache.
+    ///
+    /// Secrets are never returned by the server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhooks: Option<Vec<WebhookConfig>>,
 }
 
 /// Configuaration of a keypair.
@@ -105,6 +198,86 @@ pub enum KeypairConfig {
 
     /// Use a client-specified keypair.
     Keypair(NixKeypair),
+
+    /// Use a keypair held by an external signer.
+    ///
+    /// This is the name of a signer configured on the server.
+    /// The secret key is never stored in the database.
+    External(String),
+}
+
+/// A public key of a cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachePublicKey {
+    /// The public key, in the canonical format used by Nix.
+    pub public_key: String,
+
+    /// The state of the keypair.
+    pub state: KeypairState,
+}
+
+/// State of a keypair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeypairState {
+    /// The keypair is the current keypair of the cache.
+    Active,
+
+    /// The keypair has been replaced by a new keypair.
+    ///
+    /// Objects are still signed with the keypair so clients
+    /// have time to trust the new keypair.
+    Retiring,
+
+    /// The keypair has been revoked.
+    ///
+    /// Objects are no longer signed with the keypair and clients
+    /// should stop trusting it.
+    Revoked,
+}
+
+/// Chunking parameters of a server.
+///
+/// See `ChunkingConfig` in the server configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkingParameters {
+    /// The minimum NAR size to trigger chunking.
+    pub nar_size_threshold: usize,
+
+    /// The preferred minimum size of a chunk, in bytes.
+    pub min_size: usize,
+
+    /// The preferred average size of a chunk, in bytes.
+    pub avg_size: usize,
+
+    /// The preferred maximum size of a chunk, in bytes.
+    pub max_size: usize,
+}
+
+/// Configuration of a quota.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuotaConfig {
+    /// No limit.
+    Unlimited,
+
+    /// Specify a limit.
+    Limit(u64),
+}
+
+/// Configuration of a webhook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// The URL notifications are sent to.
+    pub url: String,
+
+    /// The secret used to sign notifications.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+
+    /// The events to send notifications for.
+    ///
+    /// If empty, notifications are sent for all events.
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
 }
 
 /// Configuration of retention period.
@@ -119,6 +292,13 @@ pub enum RetentionPeriodConfig {
     Period(u32),
 }
 
+impl WebhookConfig {
+    /// Returns whether notifications are sent for an event.
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
 impl CacheConfig {
     pub fn blank() -> Self {
         Self {
@@ -126,11 +306,21 @@ impl CacheConfig {
             substituter_endpoint: None,
             api_endpoint: None,
             public_key: None,
+            public_keys: None,
+            revoke_public_keys: None,
             is_public: None,
             store_dir: None,
             priority: None,
             upstream_cache_key_names: None,
+            upstream_urls: None,
+            ingest_upstream: None,
+            member_caches: None,
             retention_period: None,
+            quota_bytes: None,
+            quota_objects: None,
+            validate_nars: None,
+            chunking: None,
+            webhooks: None,
         }
     }
 }
@@ -0,0 +1,59 @@
+//! Cache event stream.
+//!
+//! Newly pushed store paths in a cache are streamed to subscribers
+//! as [Server-Sent Events](https://html.spec.whatwg.org/multipage/server-sent-events.html).
+//! The name of each SSE event is the type of the event, and the data
+//! is the JSON-encoded event.
+//!
+//! Only events that happen while subscribed are streamed. If the
+//! subscriber falls behind, events are dropped and a `lagged` event
+//! is sent so it can catch up by other means (e.g., `get-missing-paths`).
+//!
+//! - `GET /_api/v1/cache/:cache/events`: Requires "pull" permission.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::nix_store::StorePathHash;
+
+/// Name of the event sent when a store path is pushed.
+pub const EVENT_PATH_PUSHED: &str = "path-pushed";
+
+/// Name of the event sent when events were dropped.
+pub const EVENT_LAGGED: &str = "lagged";
+
+/// A store path was pushed to the cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathPushedEvent {
+    /// The hash portion of the store path.
+    pub store_path_hash: StorePathHash,
+
+    /// The full store path, including the store directory.
+    pub store_path: String,
+
+    /// Other store paths this object directly references.
+    ///
+    /// These are only the base names of the paths.
+    pub references: Vec<String>,
+
+    /// The system this derivation is built for.
+    pub system: Option<String>,
+
+    /// The hash of the NAR, in the format used by Nix.
+    pub nar_hash: String,
+
+    /// The size of the NAR, in bytes.
+    pub nar_size: u64,
+
+    /// Timestamp when the store path was pushed.
+    pub pushed_at: DateTime<Utc>,
+}
+
+/// Events were dropped because the subscriber fell behind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaggedEvent {
+    /// The number of events dropped.
+    ///
+    /// This may include events in other caches.
+    pub missed_events: u64,
+}
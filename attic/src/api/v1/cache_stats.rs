@@ -0,0 +1,35 @@
+//! cache-stats v1
+//!
+//! `GET /_api/v1/cache/:cache/stats`
+//!
+//! Requires "pull" permission.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Storage statistics of a cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// The number of objects in the cache.
+    pub object_count: u64,
+
+    /// The total size of the NARs of all objects, uncompressed.
+    ///
+    /// Objects sharing a NAR are counted separately.
+    pub nar_size: u64,
+
+    /// The total size of the chunks that back the objects, as stored.
+    ///
+    /// Each chunk is counted once, even if it's part of multiple
+    /// NARs in the cache.
+    pub stored_size: u64,
+
+    /// The part of `stored_size` that is also used by other caches.
+    ///
+    /// Chunks are deduplicated across caches, so these bytes
+    /// would not be freed if the cache were destroyed.
+    pub shared_size: u64,
+
+    /// Timestamp when the last object was pushed.
+    pub last_pushed_at: Option<DateTime<Utc>>,
+}
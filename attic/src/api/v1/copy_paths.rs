@@ -0,0 +1,41 @@
+//! copy-paths v1
+//!
+//! `POST /_api/v1/copy-paths`
+//!
+//! Requires "pull" permission on the source cache and "push"
+//! permission on the destination cache.
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheName;
+use crate::nix_store::StorePathHash;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CopyPathsRequest {
+    /// The name of the cache to copy from.
+    pub source_cache: CacheName,
+
+    /// The name of the cache to copy to.
+    pub destination_cache: CacheName,
+
+    /// The list of store paths to copy.
+    pub store_path_hashes: Vec<StorePathHash>,
+
+    /// Whether to also copy the closures of the paths.
+    ///
+    /// Only paths in the source cache are followed.
+    #[serde(default)]
+    pub closure: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CopyPathsResponse {
+    /// The full store paths that were copied.
+    pub copied_paths: Vec<String>,
+
+    /// The full store paths that were already in the destination cache.
+    pub skipped_paths: Vec<String>,
+
+    /// A list of requested paths that are not in the source cache.
+    pub missing_paths: Vec<StorePathHash>,
+}
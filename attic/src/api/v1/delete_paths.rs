@@ -0,0 +1,39 @@
+//! delete-paths v1
+//!
+//! `POST /_api/v1/delete-paths`
+//!
+//! Requires "delete" permission.
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheName;
+use crate::nix_store::StorePathHash;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletePathsRequest {
+    /// The name of the cache.
+    pub cache: CacheName,
+
+    /// The list of store paths to delete.
+    pub store_path_hashes: Vec<StorePathHash>,
+
+    /// Whether to also delete the closures of the paths.
+    ///
+    /// Only paths in the cache are followed. Paths in the closures
+    /// are deleted even if other paths in the cache refer to them.
+    #[serde(default)]
+    pub closure: bool,
+
+    /// Whether to only return the paths that would be deleted.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletePathsResponse {
+    /// The full store paths that were deleted.
+    pub deleted_paths: Vec<String>,
+
+    /// A list of requested paths that are not in the cache.
+    pub missing_paths: Vec<StorePathHash>,
+}
@@ -0,0 +1,28 @@
+//! get-missing-chunks v1
+//!
+//! `POST /_api/v1/get-missing-chunks`
+//!
+//! Requires "push" permission.
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheName;
+use crate::hash::Hash;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetMissingChunksRequest {
+    /// The name of the cache.
+    ///
+    /// The cache determines which chunks are usable if the server
+    /// requires proof of possession.
+    pub cache: CacheName,
+
+    /// The list of uncompressed chunk hashes.
+    pub chunk_hashes: Vec<Hash>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetMissingChunksResponse {
+    /// A list of chunks that need to be uploaded.
+    pub missing_chunk_hashes: Vec<Hash>,
+}
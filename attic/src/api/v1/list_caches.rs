@@ -0,0 +1,57 @@
+//! list-caches v1
+//!
+//! `GET /_api/v1/caches`
+//!
+//! Returns all caches that the client can discover, which are
+//! caches that the token grants any permission to and public
+//! caches.
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheName;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListCachesResponse {
+    /// The caches, sorted by name.
+    pub caches: Vec<CacheInfo>,
+}
+
+/// A cache visible to the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheInfo {
+    /// The name of the cache.
+    pub name: CacheName,
+
+    /// Whether the cache is public.
+    pub is_public: bool,
+
+    /// The permissions the client has on the cache.
+    ///
+    /// This includes the implicit permissions on public caches.
+    pub permission: CachePermissions,
+}
+
+/// Permissions on a cache.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachePermissions {
+    /// Can pull objects from the cache.
+    pub pull: bool,
+
+    /// Can push objects to the cache.
+    pub push: bool,
+
+    /// Can delete objects from the cache.
+    pub delete: bool,
+
+    /// Can create the cache itself.
+    pub create_cache: bool,
+
+    /// Can reconfigure the cache.
+    pub configure_cache: bool,
+
+    /// Can configure retention/quota settings.
+    pub configure_cache_retention: bool,
+
+    /// Can destroy the cache itself.
+    pub destroy_cache: bool,
+}
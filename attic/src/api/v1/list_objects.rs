@@ -0,0 +1,87 @@
+//! list-objects v1
+//!
+//! `GET /_api/v1/cache/:cache/objects`
+//!
+//! Requires "pull" permission.
+//!
+//! Objects are returned from the most recently created. The filters
+//! are passed as query parameters, and all of them are optional.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::hash::Hash;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListObjectsQuery {
+    /// The cursor returned with the previous page.
+    pub cursor: Option<String>,
+
+    /// The maximum number of objects to return.
+    ///
+    /// The server may return fewer objects.
+    pub limit: Option<usize>,
+
+    /// A substring of the store path.
+    pub name: Option<String>,
+
+    /// The uploader of the objects.
+    pub created_by: Option<String>,
+
+    /// Only return objects created at or after this time.
+    pub created_after: Option<DateTime<Utc>>,
+
+    /// Only return objects created before this time.
+    pub created_before: Option<DateTime<Utc>>,
+
+    /// Only return objects last accessed at or after this time.
+    pub accessed_after: Option<DateTime<Utc>>,
+
+    /// Only return objects last accessed before this time.
+    ///
+    /// Objects that have never been accessed are included.
+    pub accessed_before: Option<DateTime<Utc>>,
+
+    /// The system the objects are built for.
+    pub system: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListObjectsResponse {
+    /// The objects in this page.
+    pub objects: Vec<ObjectInfo>,
+
+    /// The cursor to retrieve the next page with.
+    ///
+    /// This is absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// An object in a cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectInfo {
+    /// The full store path, including the store directory.
+    pub store_path: String,
+
+    /// The hash of the NAR.
+    pub nar_hash: Hash,
+
+    /// The size of the NAR.
+    pub nar_size: u64,
+
+    /// The system this derivation is built for.
+    pub system: Option<String>,
+
+    /// The derivation that produced this object.
+    pub deriver: Option<String>,
+
+    /// Timestamp when the object was created.
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the object was last accessed.
+    pub last_accessed_at: Option<DateTime<Utc>>,
+
+    /// The uploader of the object.
+    pub created_by: Option<String>,
+}
@@ -1,3 +1,17 @@
 pub mod cache_config;
+pub mod cache_events;
+pub mod cache_stats;
+pub mod copy_paths;
+pub mod delete_paths;
+pub mod get_missing_chunks;
 pub mod get_missing_paths;
+pub mod list_caches;
+pub mod list_objects;
+pub mod narinfo_batch;
+pub mod pin;
+pub mod possession_challenge;
+pub mod tag;
+pub mod upload_log;
 pub mod upload_path;
+pub mod upload_realisation;
+pub mod webhook;
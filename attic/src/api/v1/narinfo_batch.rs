@@ -0,0 +1,80 @@
+//! narinfo-batch v1
+//!
+//! `POST /_api/v1/narinfo-batch`
+//!
+//! Requires "pull" permission.
+//!
+//! At most [`MAX_STORE_PATH_HASHES`] paths can be queried in a
+//! single request.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheName;
+use crate::hash::Hash;
+use crate::nix_store::StorePathHash;
+
+/// The maximum number of paths in a request.
+pub const MAX_STORE_PATH_HASHES: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NarInfoBatchRequest {
+    /// The name of the cache.
+    pub cache: CacheName,
+
+    /// The list of store paths.
+    pub store_path_hashes: Vec<StorePathHash>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NarInfoBatchResponse {
+    /// NAR information of paths that are in the cache.
+    pub narinfos: HashMap<StorePathHash, NarInfo>,
+
+    /// A list of paths that are not in the cache.
+    pub missing_paths: Vec<StorePathHash>,
+}
+
+/// NAR information of a path.
+///
+/// This contains the same information as the `.narinfo`
+/// served by the binary cache, including the signatures.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NarInfo {
+    /// The full store path, including the store directory.
+    pub store_path: String,
+
+    /// The URL of the NAR, relative to the binary cache.
+    pub url: String,
+
+    /// The compression of the NAR file.
+    pub compression: String,
+
+    /// The hash of the compressed NAR file, if known.
+    pub file_hash: Option<Hash>,
+
+    /// The size of the compressed NAR file, if known.
+    pub file_size: Option<usize>,
+
+    /// The hash of the NAR.
+    pub nar_hash: Hash,
+
+    /// The size of the NAR.
+    pub nar_size: usize,
+
+    /// Other store paths this object directly refereces.
+    pub references: Vec<String>,
+
+    /// The system this derivation is built for.
+    pub system: Option<String>,
+
+    /// The derivation that produced this object.
+    pub deriver: Option<String>,
+
+    /// The signatures of this object.
+    pub sigs: Vec<String>,
+
+    /// The CA field of this object.
+    pub ca: Option<String>,
+}
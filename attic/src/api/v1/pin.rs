@@ -0,0 +1,89 @@
+//! Pin endpoints.
+//!
+//! Pinned store paths and their closures in the cache are exempt
+//! from time-based garbage collection until the pins expire.
+//!
+//! - `POST /_api/v1/pin-paths`: Requires "configure cache retention" permission.
+//! - `POST /_api/v1/unpin-paths`: Requires "configure cache retention" permission.
+//! - `GET /_api/v1/cache/:cache/pins`: Requires "pull" permission.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheName;
+use crate::nix_store::StorePathHash;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinPathsRequest {
+    /// The name of the cache.
+    pub cache: CacheName,
+
+    /// The list of store paths to pin.
+    ///
+    /// Existing pins of the paths are replaced.
+    pub store_path_hashes: Vec<StorePathHash>,
+
+    /// A name for the pins, like a release version.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Timestamp when the pins expire.
+    ///
+    /// If unset, the pins never expire.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinPathsResponse {
+    /// The full store paths that were pinned.
+    pub pinned_paths: Vec<String>,
+
+    /// A list of requested paths that are not in the cache.
+    pub missing_paths: Vec<StorePathHash>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnpinPathsRequest {
+    /// The name of the cache.
+    pub cache: CacheName,
+
+    /// The list of store paths to unpin.
+    #[serde(default)]
+    pub store_path_hashes: Vec<StorePathHash>,
+
+    /// Also unpin all paths pinned with this name.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnpinPathsResponse {
+    /// The full store paths that were unpinned.
+    pub unpinned_paths: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListPinsResponse {
+    /// The pins in the cache, sorted by store path.
+    pub pins: Vec<PinInfo>,
+}
+
+/// A pinned store path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinInfo {
+    /// The full store path, including the store directory.
+    pub store_path: String,
+
+    /// The name of the pin.
+    pub name: Option<String>,
+
+    /// Timestamp when the pin is created.
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp when the pin expires.
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// The user who created the pin.
+    pub created_by: Option<String>,
+}
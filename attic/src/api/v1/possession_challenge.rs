@@ -0,0 +1,157 @@
+//! get-possession-challenge v1
+//!
+//! `POST /_api/v1/get-possession-challenge`
+//!
+//! Requires "push" permission.
+//!
+//! If the server requires proof of possession, uploading a NAR that
+//! already exists in the global cache means sending the entire NAR
+//! so the server can verify it. Instead, the client can answer a
+//! challenge consisting of a nonce and a few byte ranges of the NAR.
+//! The response is the SHA-256 hash of the nonce followed by the
+//! contents of the ranges in order.
+//!
+//! The response is sent to `upload-path` in the `X-Attic-Possession-Proof`
+//! header with an empty body. A challenge is issued regardless of
+//! whether the server has the NAR. If the server doesn't have the NAR
+//! or the response is incorrect, the upload fails with
+//! `PossessionProofRejected` and the client should upload the NAR
+//! instead. A challenge can only be used once.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cache::CacheName;
+use crate::hash::Hash;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPossessionChallengeRequest {
+    /// The name of the cache to upload to.
+    pub cache: CacheName,
+
+    /// The hash of the NAR.
+    pub nar_hash: Hash,
+
+    /// The size of the NAR.
+    pub nar_size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPossessionChallengeResponse {
+    /// The challenge.
+    ///
+    /// This is `None` if the server doesn't require proof of
+    /// possession, in which case the NAR can be uploaded directly.
+    pub challenge: Option<PossessionChallenge>,
+}
+
+/// A challenge to prove possession of a NAR.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PossessionChallenge {
+    /// A random nonce identifying the challenge.
+    pub nonce: String,
+
+    /// The byte ranges of the NAR to hash.
+    pub ranges: Vec<ByteRange>,
+
+    /// Timestamp when the challenge expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A byte range of a NAR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteRange {
+    /// Offset of the first byte.
+    pub offset: u64,
+
+    /// Number of bytes.
+    pub length: u64,
+}
+
+/// A proof of possession of a NAR.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PossessionProof {
+    /// The nonce of the challenge.
+    pub nonce: String,
+
+    /// The response to the challenge.
+    pub response: Hash,
+}
+
+/// Collects the contents of byte ranges from a NAR.
+///
+/// Feed it the NAR with [`RangeCollector::update`], then call
+/// [`RangeCollector::finish`] to obtain the response to a challenge.
+#[derive(Debug)]
+pub struct RangeCollector {
+    ranges: Vec<ByteRange>,
+    buffers: Vec<Vec<u8>>,
+
+    /// Number of bytes of the ranges that haven't been collected.
+    remaining: u64,
+}
+
+impl ByteRange {
+    /// Returns the offset after the last byte.
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+}
+
+impl RangeCollector {
+    pub fn new(ranges: &[ByteRange]) -> Self {
+        Self {
+            ranges: ranges.to_vec(),
+            buffers: ranges
+                .iter()
+                .map(|range| vec![0; range.length as usize])
+                .collect(),
+            remaining: ranges.iter().map(|range| range.length).sum(),
+        }
+    }
+
+    /// Returns whether a span of the NAR overlaps with any of the ranges.
+    pub fn overlaps(&self, offset: u64, length: u64) -> bool {
+        self.ranges
+            .iter()
+            .any(|range| range.offset < offset + length && offset < range.end())
+    }
+
+    /// Consumes a slice of the NAR at some offset.
+    ///
+    /// Each byte of the NAR must only be consumed once.
+    pub fn update(&mut self, offset: u64, data: &[u8]) {
+        let end = offset + data.len() as u64;
+
+        for (range, buffer) in self.ranges.iter().zip(self.buffers.iter_mut()) {
+            let start = range.offset.max(offset);
+            let stop = range.end().min(end);
+
+            if start >= stop {
+                continue;
+            }
+
+            buffer[(start - range.offset) as usize..(stop - range.offset) as usize]
+                .copy_from_slice(&data[(start - offset) as usize..(stop - offset) as usize]);
+            self.remaining -= stop - start;
+        }
+    }
+
+    /// Returns whether all ranges have been collected.
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Returns the response to the challenge with a nonce.
+    pub fn finish(self, nonce: &str) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update(nonce.as_bytes());
+
+        for buffer in self.buffers {
+            hasher.update(&buffer);
+        }
+
+        Hash::Sha256(hasher.finalize().into())
+    }
+}
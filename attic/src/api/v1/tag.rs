@@ -0,0 +1,46 @@
+//! Tag endpoints.
+//!
+//! Tags are named, mutable pointers to store paths in a cache, like
+//! `myapp/latest`. The current targets of tags and their closures in
+//! the cache are exempt from time-based garbage collection.
+//!
+//! - `GET /_api/v1/cache/:cache/tags/*tag`: Requires "pull" permission.
+//! - `PUT /_api/v1/cache/:cache/tags/*tag`: Requires "push" permission.
+//! - `GET /_api/v1/cache/:cache/tag-history/*tag`: Requires "pull" permission.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::nix_store::StorePathHash;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetTagRequest {
+    /// The store path to point the tag at.
+    ///
+    /// The path must be in the cache.
+    pub store_path_hash: StorePathHash,
+}
+
+/// A target of a tag.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagInfo {
+    /// The name of the tag.
+    pub name: String,
+
+    /// The full store path, including the store directory.
+    pub store_path: String,
+
+    /// Timestamp when the tag was set to the store path.
+    pub created_at: DateTime<Utc>,
+
+    /// The user who set the tag to the store path.
+    pub created_by: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagHistoryResponse {
+    /// The targets of the tag, from the most recent.
+    ///
+    /// The first entry is the current target.
+    pub history: Vec<TagInfo>,
+}
@@ -0,0 +1,30 @@
+//! upload-log v1
+//!
+//! `PUT /_api/v1/upload-log`
+//!
+//! Requires "push" permission.
+//!
+//! The body is the build log, optionally compressed with the
+//! method indicated in `Content-Encoding`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheName;
+
+/// Header containing the upload info.
+pub const ATTIC_BUILD_LOG_INFO: &str = "X-Attic-Build-Log-Info";
+
+/// Build log information associated with a upload.
+///
+/// Regardless of client compression, the server will always decompress
+/// the log before applying the server-configured compression again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadLogInfo {
+    /// The name of the binary cache to upload to.
+    pub cache: CacheName,
+
+    /// The base name of the derivation that produced the log.
+    ///
+    /// For example, `xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx-hello-2.12.1.drv`.
+    pub drv_path: String,
+}
@@ -11,6 +11,11 @@ pub const ATTIC_NAR_INFO: &str = "X-Attic-Nar-Info";
 /// Header containing the size of the upload info at the beginning of the body.
 pub const ATTIC_NAR_INFO_PREAMBLE_SIZE: &str = "X-Attic-Nar-Info-Preamble-Size";
 
+/// Header containing a proof of possession of the NAR.
+///
+/// See [`super::possession_challenge`].
+pub const ATTIC_POSSESSION_PROOF: &str = "X-Attic-Possession-Proof";
+
 /// NAR information associated with a upload.
 ///
 /// There are two ways for the client to supply the NAR information:
@@ -25,7 +30,7 @@ pub const ATTIC_NAR_INFO_PREAMBLE_SIZE: &str = "X-Attic-Nar-Info-Preamble-Size";
 /// Regardless of client compression, the server will always decompress
 /// the NAR to validate the NAR hash before applying the server-configured
 /// compression again.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UploadPathNarInfo {
     /// The name of the binary cache to upload to.
     pub cache: CacheName,
@@ -64,6 +69,44 @@ pub struct UploadPathNarInfo {
     pub nar_size: usize,
 }
 
+/// Upload information of a NAR uploaded as a list of chunks.
+///
+/// `PUT /_api/v1/upload-chunked-path`
+///
+/// The upload information must be at the beginning of the PUT body,
+/// with the `X-Attic-Nar-Info-Preamble-Size` header set to the size
+/// of the JSON. It's followed by the uncompressed data of the chunks
+/// with `included` set, in order.
+///
+/// Chunks that aren't included must already exist on the server. The
+/// client can find out which chunks are missing with
+/// `get-missing-chunks`. The server always verifies the NAR hash
+/// against the assembled chunks.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadChunkedPathInfo {
+    /// NAR information.
+    pub nar_info: UploadPathNarInfo,
+
+    /// The chunks making up the NAR, in order.
+    pub chunks: Vec<NarChunk>,
+}
+
+/// A chunk of a NAR.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NarChunk {
+    /// The hash of the uncompressed chunk.
+    pub hash: Hash,
+
+    /// The size of the uncompressed chunk.
+    pub size: usize,
+
+    /// Whether the data of the chunk is included in the body.
+    ///
+    /// A chunk that appears multiple times in a NAR only needs
+    /// to be included once.
+    pub included: bool,
+}
+
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UploadPathResult {
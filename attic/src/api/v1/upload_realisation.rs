@@ -0,0 +1,23 @@
+//! upload-realisation v1
+//!
+//! `PUT /_api/v1/upload-realisation`
+//!
+//! Requires "push" permission.
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheName;
+use crate::realisation::Realisation;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadRealisationRequest {
+    /// The name of the cache.
+    pub cache: CacheName,
+
+    /// The realisations to upload.
+    ///
+    /// Existing realisations of the same derivation outputs are
+    /// replaced, unless they point to the same store path in which
+    /// case the signatures are merged.
+    pub realisations: Vec<Realisation>,
+}
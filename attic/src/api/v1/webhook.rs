@@ -0,0 +1,120 @@
+//! Webhook notifications.
+//!
+//! Caches can be configured with webhooks that are notified of
+//! events in the cache. Each notification is an HTTP `POST` request
+//! with a JSON [`WebhookPayload`] as the body.
+//!
+//! If the webhook has a secret, the request carries the HMAC-SHA256
+//! of the body keyed with the secret in the `X-Attic-Signature` header,
+//! as `sha256=<hex>`. Receivers should verify it before trusting the
+//! payload.
+//!
+//! Notifications are retried until the receiver responds with a
+//! successful status code, so receivers should use the delivery ID
+//! in the `X-Attic-Delivery` header to ignore duplicates.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheName;
+
+/// Header containing the signature of the payload.
+pub const ATTIC_SIGNATURE: &str = "X-Attic-Signature";
+
+/// Header containing the event type.
+pub const ATTIC_EVENT: &str = "X-Attic-Event";
+
+/// Header containing the unique ID of the delivery.
+pub const ATTIC_DELIVERY: &str = "X-Attic-Delivery";
+
+/// Type of an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookEvent {
+    /// A store path was pushed to the cache.
+    PathPushed,
+
+    /// The cache was reconfigured.
+    CacheConfigured,
+
+    /// The cache was destroyed.
+    CacheDestroyed,
+
+    /// Objects were deleted by garbage collection.
+    GarbageCollected,
+}
+
+/// A webhook notification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    /// The cache the event happened in.
+    pub cache: CacheName,
+
+    /// Timestamp when the event happened.
+    pub timestamp: DateTime<Utc>,
+
+    /// The event.
+    #[serde(flatten)]
+    pub data: WebhookEventData,
+}
+
+/// An event and its details.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum WebhookEventData {
+    /// A store path was pushed to the cache.
+    PathPushed {
+        /// The full store path, including the store directory.
+        store_path: String,
+
+        /// The hash of the NAR, in the format used by Nix.
+        nar_hash: String,
+
+        /// The size of the NAR, in bytes.
+        nar_size: u64,
+
+        /// The user who pushed the store path.
+        pushed_by: Option<String>,
+    },
+
+    /// The cache was reconfigured.
+    CacheConfigured {
+        /// The user who reconfigured the cache.
+        configured_by: Option<String>,
+    },
+
+    /// The cache was destroyed.
+    CacheDestroyed {
+        /// The user who destroyed the cache.
+        destroyed_by: Option<String>,
+    },
+
+    /// Objects were deleted by garbage collection.
+    GarbageCollected {
+        /// The number of objects deleted.
+        objects_deleted: u64,
+    },
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PathPushed => "path-pushed",
+            Self::CacheConfigured => "cache-configured",
+            Self::CacheDestroyed => "cache-destroyed",
+            Self::GarbageCollected => "garbage-collected",
+        }
+    }
+}
+
+impl WebhookEventData {
+    /// Returns the type of the event.
+    pub fn event(&self) -> WebhookEvent {
+        match self {
+            Self::PathPushed { .. } => WebhookEvent::PathPushed,
+            Self::CacheConfigured { .. } => WebhookEvent::CacheConfigured,
+            Self::CacheDestroyed { .. } => WebhookEvent::CacheDestroyed,
+            Self::GarbageCollected { .. } => WebhookEvent::GarbageCollected,
+        }
+    }
+}
@@ -26,12 +26,21 @@ pub enum AtticError {
     /// Invalid cache name "{name}"
     InvalidCacheName { name: String },
 
+    /// Invalid derivation output "{id}": {reason}
+    InvalidDrvOutput { id: String, reason: &'static str },
+
+    /// Invalid realisation: {reason}
+    InvalidRealisation { reason: String },
+
     /// Signing error: {0}
     SigningError(super::signing::Error),
 
     /// Hashing error: {0}
     HashError(super::hash::Error),
 
+    /// NAR error: {0}
+    NarError(super::nar::Error),
+
     /// I/O error: {error}.
     IoError { error: io::Error },
 
@@ -46,8 +55,11 @@ impl AtticError {
             Self::InvalidStorePathName { .. } => "InvalidStorePathName",
             Self::InvalidStorePathHash { .. } => "InvalidStorePathHash",
             Self::InvalidCacheName { .. } => "InvalidCacheName",
+            Self::InvalidDrvOutput { .. } => "InvalidDrvOutput",
+            Self::InvalidRealisation { .. } => "InvalidRealisation",
             Self::SigningError(_) => "SigningError",
             Self::HashError(_) => "HashError",
+            Self::NarError(_) => "NarError",
             Self::IoError { .. } => "IoError",
             Self::CxxError { .. } => "CxxError",
         }
@@ -82,3 +94,9 @@ impl From<super::hash::Error> for AtticError {
         Self::HashError(error)
     }
 }
+
+impl From<super::nar::Error> for AtticError {
+    fn from(error: super::nar::Error) -> Self {
+        Self::NarError(error)
+    }
+}
@@ -22,7 +22,9 @@ pub mod chunking;
 pub mod error;
 pub mod hash;
 pub mod mime;
+pub mod nar;
 pub mod nix_store;
+pub mod realisation;
 pub mod signing;
 #[cfg(feature = "stream")]
 pub mod stream;
@@ -8,3 +8,12 @@ pub const NARINFO: &str = "text/x-nix-narinfo";
 
 /// .nar
 pub const NAR: &str = "application/x-nix-nar";
+
+/// .ls
+pub const NAR_LISTING: &str = "application/json";
+
+/// .doi
+pub const REALISATION: &str = "application/json";
+
+/// log/{drvPath}
+pub const BUILD_LOG: &str = "text/plain; charset=utf-8";
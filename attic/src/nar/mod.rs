@@ -0,0 +1,632 @@
+//! Nix Archives.
+//!
+//! A NAR is a sequence of length-prefixed strings padded to 8 bytes.
+//! Here we implement an incremental parser that consumes a NAR as it
+//! is being streamed and produces a file listing in the same JSON format
+//! that Nix serves as `{storePathHash}.ls` in binary caches:
+//!
+//! ```text
+//! {"version":1,"root":{"type":"directory","entries":{"bin":{"type":"directory","entries":{...}}}}}
+//! ```
+//!
+//! File contents are never buffered, so arbitrarily large NARs can
+//! be parsed with a small, constant amount of memory on top of the
+//! listing itself.
+//!
+//! The NAR can also be scanned for references to other store paths
+//! with [`ReferenceScanner`].
+
+mod references;
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+use std::mem;
+
+use displaydoc::Display;
+use serde::{Deserialize, Serialize};
+
+pub use references::ReferenceScanner;
+
+#[cfg(feature = "tokio")]
+pub use references::ReferenceScanStream;
+
+#[cfg(feature = "tokio")]
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "tokio")]
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::OnceCell,
+};
+
+/// The magic string at the beginning of every NAR.
+const NAR_VERSION_MAGIC: &[u8] = b"nix-archive-1";
+
+/// The version of the listing format we produce.
+const LISTING_VERSION: u32 = 1;
+
+/// The maximum length of a string that isn't file contents.
+///
+/// This applies to tokens, entry names and symlink targets.
+const MAX_STRING_LENGTH: u64 = 64 * 1024;
+
+/// The maximum nesting depth of directories.
+///
+/// Listings are serialized and dropped recursively, so deeper
+/// archives could overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+/// A NAR listing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NarListing {
+    /// Version of the listing format.
+    pub version: u32,
+
+    /// The root entry.
+    pub root: NarListingEntry,
+}
+
+/// An entry in a NAR listing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NarListingEntry {
+    /// A regular file.
+    Regular {
+        /// Size of the file.
+        size: u64,
+
+        /// Whether the file is executable.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        executable: bool,
+
+        /// Offset of the file contents in the NAR.
+        #[serde(rename = "narOffset")]
+        nar_offset: u64,
+    },
+
+    /// A symbolic link.
+    Symlink {
+        /// Target of the symbolic link.
+        target: String,
+    },
+
+    /// A directory.
+    Directory {
+        /// Entries in the directory.
+        entries: BTreeMap<String, NarListingEntry>,
+    },
+}
+
+/// A NAR parsing error.
+#[derive(Debug, Clone, Display)]
+pub enum Error {
+    /// Unexpected token "{token}", expected {expected}.
+    UnexpectedToken {
+        token: String,
+        expected: &'static str,
+    },
+
+    /// String of length {0} exceeds the maximum length.
+    StringTooLong(u64),
+
+    /// Directories are nested deeper than the maximum depth.
+    TooDeep,
+
+    /// Padding contains non-zero bytes.
+    NonZeroPadding,
+
+    /// Invalid directory entry name "{0}".
+    InvalidEntryName(String),
+
+    /// Directory entry "{0}" is out of order or duplicated.
+    UnsortedEntry(String),
+
+    /// Unexpected data after the end of the archive.
+    TrailingData,
+
+    /// Unexpected end of the archive.
+    UnexpectedEof,
+}
+
+/// An incremental NAR parser.
+///
+/// Feed it data with [`NarParser::update`], then call
+/// [`NarParser::finish`] to obtain the listing.
+#[derive(Debug)]
+pub struct NarParser {
+    /// Number of bytes consumed so far.
+    offset: u64,
+
+    /// The state of the tokenizer.
+    lexer: Lexer,
+
+    /// The grammar state.
+    state: State,
+
+    /// Directories we are currently inside of.
+    stack: Vec<Directory>,
+
+    /// The root entry, available when the parsing is complete.
+    root: Option<NarListingEntry>,
+}
+
+/// The state of the tokenizer.
+#[derive(Debug)]
+enum Lexer {
+    /// Reading the little-endian length of the next string.
+    Length { buf: [u8; 8], filled: usize },
+
+    /// Reading a string.
+    String {
+        buf: Vec<u8>,
+        remaining: u64,
+        padding: u64,
+    },
+
+    /// Skipping over file contents.
+    Contents { remaining: u64, padding: u64 },
+}
+
+/// The grammar state.
+#[derive(Debug)]
+enum State {
+    /// Expecting the magic string.
+    Magic,
+
+    /// Expecting `(`.
+    NodeOpen,
+
+    /// Expecting `type`.
+    NodeType,
+
+    /// Expecting the type of the node.
+    NodeTypeValue,
+
+    /// Expecting `executable` or `contents`.
+    RegularAttribute,
+
+    /// Expecting the empty value of `executable`.
+    RegularExecutableValue,
+
+    /// Expecting `contents` after `executable`.
+    RegularContentsAfterExecutable,
+
+    /// Expecting the file contents.
+    RegularContents { executable: bool },
+
+    /// Expecting `)` to close the file.
+    RegularClose {
+        size: u64,
+        executable: bool,
+        nar_offset: u64,
+    },
+
+    /// Expecting `target`.
+    SymlinkTarget,
+
+    /// Expecting the target of the symlink.
+    SymlinkTargetValue,
+
+    /// Expecting `)` to close the symlink.
+    SymlinkClose { target: String },
+
+    /// Expecting `entry` or `)`.
+    DirectoryEntry,
+
+    /// Expecting `(` to open the entry.
+    EntryOpen,
+
+    /// Expecting `name`.
+    EntryName,
+
+    /// Expecting the name of the entry.
+    EntryNameValue,
+
+    /// Expecting `node`.
+    EntryNode,
+
+    /// Expecting `)` to close the entry.
+    EntryClose,
+
+    /// The archive has been fully parsed.
+    Done,
+}
+
+/// A directory being parsed.
+#[derive(Debug, Default)]
+struct Directory {
+    /// Entries that have been parsed.
+    entries: BTreeMap<String, NarListingEntry>,
+
+    /// Name of the entry being parsed.
+    current_name: Option<String>,
+
+    /// Raw name of the last entry, for enforcing the ordering.
+    last_name: Option<Vec<u8>>,
+}
+
+/// Stream filter that builds a listing of the NAR being read.
+///
+/// The listing is finalized when EOF is reached. Parsing errors do
+/// not interrupt the stream, but are reported in the result instead.
+#[cfg(feature = "tokio")]
+pub struct NarListingStream<R: AsyncRead + Unpin> {
+    inner: R,
+    parser: Option<NarParser>,
+    finalized: Arc<OnceCell<Result<NarListing, Error>>>,
+}
+
+impl NarListing {
+    /// Parses a complete NAR in memory.
+    pub fn from_nar(nar: &[u8]) -> Result<Self, Error> {
+        let mut parser = NarParser::new();
+        parser.update(nar)?;
+        parser.finish()
+    }
+}
+
+impl NarParser {
+    pub fn new() -> Self {
+        Self {
+            offset: 0,
+            lexer: Lexer::new(),
+            state: State::Magic,
+            stack: Vec::new(),
+            root: None,
+        }
+    }
+
+    /// Consumes a slice of the NAR.
+    pub fn update(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        loop {
+            match &mut self.lexer {
+                Lexer::Length { buf, filled } => {
+                    if data.is_empty() {
+                        break;
+                    }
+
+                    if matches!(self.state, State::Done) {
+                        return Err(Error::TrailingData);
+                    }
+
+                    let n = (buf.len() - *filled).min(data.len());
+                    buf[*filled..*filled + n].copy_from_slice(&data[..n]);
+                    *filled += n;
+                    data = &data[n..];
+                    self.offset += n as u64;
+
+                    if *filled == buf.len() {
+                        let len = u64::from_le_bytes(*buf);
+                        let padding = padding_len(len);
+
+                        if let State::RegularContents { executable } = self.state {
+                            self.state = State::RegularClose {
+                                size: len,
+                                executable,
+                                nar_offset: self.offset,
+                            };
+                            self.lexer = Lexer::Contents {
+                                remaining: len,
+                                padding,
+                            };
+                        } else {
+                            if len > MAX_STRING_LENGTH {
+                                return Err(Error::StringTooLong(len));
+                            }
+
+                            self.lexer = Lexer::String {
+                                buf: Vec::with_capacity(len as usize),
+                                remaining: len,
+                                padding,
+                            };
+                        }
+                    }
+                }
+                Lexer::String {
+                    buf,
+                    remaining,
+                    padding,
+                } => {
+                    if *remaining > 0 {
+                        if data.is_empty() {
+                            break;
+                        }
+
+                        let n = (*remaining).min(data.len() as u64) as usize;
+                        buf.extend_from_slice(&data[..n]);
+                        *remaining -= n as u64;
+                        data = &data[n..];
+                        self.offset += n as u64;
+                    } else if *padding > 0 {
+                        if data.is_empty() {
+                            break;
+                        }
+
+                        let n = consume_padding(padding, data)?;
+                        data = &data[n..];
+                        self.offset += n as u64;
+                    } else {
+                        let token = mem::take(buf);
+                        self.lexer = Lexer::new();
+                        self.handle_token(token)?;
+                    }
+                }
+                Lexer::Contents { remaining, padding } => {
+                    if *remaining > 0 {
+                        if data.is_empty() {
+                            break;
+                        }
+
+                        let n = (*remaining).min(data.len() as u64) as usize;
+                        *remaining -= n as u64;
+                        data = &data[n..];
+                        self.offset += n as u64;
+                    } else if *padding > 0 {
+                        if data.is_empty() {
+                            break;
+                        }
+
+                        let n = consume_padding(padding, data)?;
+                        data = &data[n..];
+                        self.offset += n as u64;
+                    } else {
+                        self.lexer = Lexer::new();
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finishes parsing and returns the listing.
+    pub fn finish(self) -> Result<NarListing, Error> {
+        match (self.state, self.lexer, self.root) {
+            (State::Done, Lexer::Length { filled: 0, .. }, Some(root)) => Ok(NarListing {
+                version: LISTING_VERSION,
+                root,
+            }),
+            _ => Err(Error::UnexpectedEof),
+        }
+    }
+
+    /// Handles a complete token.
+    fn handle_token(&mut self, token: Vec<u8>) -> Result<(), Error> {
+        let state = mem::replace(&mut self.state, State::Done);
+
+        self.state = match state {
+            State::Magic => {
+                expect(&token, NAR_VERSION_MAGIC, "the NAR magic")?;
+                State::NodeOpen
+            }
+            State::NodeOpen => {
+                expect(&token, b"(", "\"(\"")?;
+                State::NodeType
+            }
+            State::NodeType => {
+                expect(&token, b"type", "\"type\"")?;
+                State::NodeTypeValue
+            }
+            State::NodeTypeValue => match token.as_slice() {
+                b"regular" => State::RegularAttribute,
+                b"symlink" => State::SymlinkTarget,
+                b"directory" => {
+                    if self.stack.len() >= MAX_DEPTH {
+                        return Err(Error::TooDeep);
+                    }
+
+                    self.stack.push(Directory::default());
+                    State::DirectoryEntry
+                }
+                _ => return Err(unexpected(&token, "a node type")),
+            },
+            State::RegularAttribute => match token.as_slice() {
+                b"executable" => State::RegularExecutableValue,
+                b"contents" => State::RegularContents { executable: false },
+                _ => return Err(unexpected(&token, "\"executable\" or \"contents\"")),
+            },
+            State::RegularExecutableValue => {
+                expect(&token, b"", "an empty string")?;
+                State::RegularContentsAfterExecutable
+            }
+            State::RegularContentsAfterExecutable => {
+                expect(&token, b"contents", "\"contents\"")?;
+                State::RegularContents { executable: true }
+            }
+            State::RegularClose {
+                size,
+                executable,
+                nar_offset,
+            } => {
+                expect(&token, b")", "\")\"")?;
+                self.finish_node(NarListingEntry::Regular {
+                    size,
+                    executable,
+                    nar_offset,
+                })
+            }
+            State::SymlinkTarget => {
+                expect(&token, b"target", "\"target\"")?;
+                State::SymlinkTargetValue
+            }
+            State::SymlinkTargetValue => State::SymlinkClose {
+                target: String::from_utf8_lossy(&token).into_owned(),
+            },
+            State::SymlinkClose { target } => {
+                expect(&token, b")", "\")\"")?;
+                self.finish_node(NarListingEntry::Symlink { target })
+            }
+            State::DirectoryEntry => match token.as_slice() {
+                b"entry" => State::EntryOpen,
+                b")" => {
+                    let directory = self.stack.pop().expect("Directory stack is empty");
+                    self.finish_node(NarListingEntry::Directory {
+                        entries: directory.entries,
+                    })
+                }
+                _ => return Err(unexpected(&token, "\"entry\" or \")\"")),
+            },
+            State::EntryOpen => {
+                expect(&token, b"(", "\"(\"")?;
+                State::EntryName
+            }
+            State::EntryName => {
+                expect(&token, b"name", "\"name\"")?;
+                State::EntryNameValue
+            }
+            State::EntryNameValue => {
+                let name = String::from_utf8_lossy(&token).into_owned();
+
+                if token.is_empty()
+                    || token == b"."
+                    || token == b".."
+                    || token.contains(&b'/')
+                    || token.contains(&0)
+                {
+                    return Err(Error::InvalidEntryName(name));
+                }
+
+                let directory = self.stack.last_mut().expect("Directory stack is empty");
+                if let Some(last_name) = &directory.last_name {
+                    if token <= *last_name {
+                        return Err(Error::UnsortedEntry(name));
+                    }
+                }
+
+                directory.last_name = Some(token);
+                directory.current_name = Some(name);
+                State::EntryNode
+            }
+            State::EntryNode => {
+                expect(&token, b"node", "\"node\"")?;
+                State::NodeOpen
+            }
+            State::EntryClose => {
+                expect(&token, b")", "\")\"")?;
+                State::DirectoryEntry
+            }
+            State::RegularContents { .. } => {
+                unreachable!("File contents are handled by the lexer");
+            }
+            State::Done => return Err(Error::TrailingData),
+        };
+
+        Ok(())
+    }
+
+    /// Attaches a complete node to its parent and returns the next state.
+    fn finish_node(&mut self, entry: NarListingEntry) -> State {
+        if let Some(directory) = self.stack.last_mut() {
+            let name = directory
+                .current_name
+                .take()
+                .expect("Directory entry has no name");
+            directory.entries.insert(name, entry);
+            State::EntryClose
+        } else {
+            self.root = Some(entry);
+            State::Done
+        }
+    }
+}
+
+impl Default for NarParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lexer {
+    fn new() -> Self {
+        Self::Length {
+            buf: [0; 8],
+            filled: 0,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + Unpin> NarListingStream<R> {
+    pub fn new(inner: R) -> (Self, Arc<OnceCell<Result<NarListing, Error>>>) {
+        let finalized = Arc::new(OnceCell::new());
+
+        (
+            Self {
+                inner,
+                parser: Some(NarParser::new()),
+                finalized: finalized.clone(),
+            },
+            finalized,
+        )
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + Unpin> AsyncRead for NarListingStream<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<tokio::io::Result<()>> {
+        let old_filled = buf.filled().len();
+        let r = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read_len = buf.filled().len() - old_filled;
+
+        if let Poll::Ready(Ok(())) = r {
+            if read_len == 0 {
+                // EOF
+                if let Some(parser) = self.parser.take() {
+                    let _ = self.finalized.set(parser.finish());
+                }
+            } else if let Some(parser) = self.parser.as_mut() {
+                let filled = buf.filled();
+                if let Err(e) = parser.update(&filled[filled.len() - read_len..]) {
+                    // Stop parsing but let the data through
+                    self.parser = None;
+                    let _ = self.finalized.set(Err(e));
+                }
+            }
+        }
+
+        r
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Returns the number of padding bytes following a string of some length.
+fn padding_len(len: u64) -> u64 {
+    (8 - len % 8) % 8
+}
+
+/// Consumes padding bytes, returning the number of bytes consumed.
+fn consume_padding(padding: &mut u64, data: &[u8]) -> Result<usize, Error> {
+    let n = (*padding).min(data.len() as u64) as usize;
+
+    if data[..n].iter().any(|b| *b != 0) {
+        return Err(Error::NonZeroPadding);
+    }
+
+    *padding -= n as u64;
+    Ok(n)
+}
+
+fn expect(token: &[u8], expected: &[u8], description: &'static str) -> Result<(), Error> {
+    if token == expected {
+        Ok(())
+    } else {
+        Err(unexpected(token, description))
+    }
+}
+
+fn unexpected(token: &[u8], expected: &'static str) -> Error {
+    Error::UnexpectedToken {
+        token: String::from_utf8_lossy(token).into_owned(),
+        expected,
+    }
+}
@@ -0,0 +1,159 @@
+//! Reference scanning.
+//!
+//! A store path references another store path if the NAR contains
+//! the path of the other store path. Like Nix, we scan the entire
+//! NAR serialization, including file names and symlink targets.
+//!
+//! Unlike Nix, we don't know the candidate hashes in advance, so
+//! we look for anything that looks like a path in the store directory
+//! followed by a store path hash. References that don't appear with
+//! the store directory in front (e.g., in UTF-16 strings) are not found.
+
+use std::collections::BTreeSet;
+
+use regex::bytes::Regex;
+
+use crate::nix_store::{STORE_PATH_HASH_LEN, STORE_PATH_HASH_REGEX_FRAGMENT};
+
+#[cfg(feature = "tokio")]
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "tokio")]
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::OnceCell,
+};
+
+/// An incremental scanner for references to store paths.
+///
+/// Feed it data with [`ReferenceScanner::update`], then call
+/// [`ReferenceScanner::finish`] to obtain the store path hashes found.
+#[derive(Debug)]
+pub struct ReferenceScanner {
+    /// Regex matching a store path, capturing the hash.
+    regex: Regex,
+
+    /// Length of a match.
+    match_len: usize,
+
+    /// The end of the data consumed so far.
+    ///
+    /// This is shorter than a match, so a match that starts here
+    /// is completed by the next slice.
+    tail: Vec<u8>,
+
+    /// Store path hashes found so far.
+    hashes: BTreeSet<String>,
+}
+
+/// Stream filter that scans the NAR being read for references.
+///
+/// The store path hashes found are finalized when EOF is reached.
+#[cfg(feature = "tokio")]
+pub struct ReferenceScanStream<R: AsyncRead + Unpin> {
+    inner: R,
+    scanner: Option<ReferenceScanner>,
+    finalized: Arc<OnceCell<BTreeSet<String>>>,
+}
+
+impl ReferenceScanner {
+    /// Creates a scanner for store paths in a store directory.
+    ///
+    /// The store directory must be an absolute path like `/nix/store`.
+    pub fn new(store_dir: &str) -> Self {
+        let store_dir = store_dir.trim_end_matches('/');
+        let regex = Regex::new(&format!(
+            "{}/({})",
+            regex::escape(store_dir),
+            STORE_PATH_HASH_REGEX_FRAGMENT
+        ))
+        .unwrap();
+
+        Self {
+            regex,
+            match_len: store_dir.len() + 1 + STORE_PATH_HASH_LEN,
+            tail: Vec::new(),
+            hashes: BTreeSet::new(),
+        }
+    }
+
+    /// Consumes a slice of the NAR.
+    pub fn update(&mut self, data: &[u8]) {
+        let tail_len = self.match_len - 1;
+
+        // Matches straddling the previous slice and this one
+        let mut boundary = std::mem::take(&mut self.tail);
+        let boundary_start = boundary.len();
+        boundary.extend_from_slice(&data[..data.len().min(tail_len)]);
+        if boundary_start > 0 {
+            self.scan(&boundary);
+        }
+
+        self.scan(data);
+
+        self.tail = if data.len() >= tail_len {
+            data[data.len() - tail_len..].to_vec()
+        } else {
+            boundary[boundary.len().saturating_sub(tail_len)..].to_vec()
+        };
+    }
+
+    /// Finishes scanning and returns the store path hashes found.
+    pub fn finish(self) -> BTreeSet<String> {
+        self.hashes
+    }
+
+    fn scan(&mut self, data: &[u8]) {
+        for captures in self.regex.captures_iter(data) {
+            let hash = String::from_utf8_lossy(&captures[1]).into_owned();
+            self.hashes.insert(hash);
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + Unpin> ReferenceScanStream<R> {
+    pub fn new(inner: R, store_dir: &str) -> (Self, Arc<OnceCell<BTreeSet<String>>>) {
+        let finalized = Arc::new(OnceCell::new());
+
+        (
+            Self {
+                inner,
+                scanner: Some(ReferenceScanner::new(store_dir)),
+                finalized: finalized.clone(),
+            },
+            finalized,
+        )
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + Unpin> AsyncRead for ReferenceScanStream<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<tokio::io::Result<()>> {
+        let old_filled = buf.filled().len();
+        let r = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read_len = buf.filled().len() - old_filled;
+
+        if let Poll::Ready(Ok(())) = r {
+            if read_len == 0 {
+                // EOF
+                if let Some(scanner) = self.scanner.take() {
+                    let _ = self.finalized.set(scanner.finish());
+                }
+            } else if let Some(scanner) = self.scanner.as_mut() {
+                let filled = buf.filled();
+                scanner.update(&filled[filled.len() - read_len..]);
+            }
+        }
+
+        r
+    }
+}
@@ -0,0 +1,262 @@
+use super::*;
+
+use std::collections::BTreeSet;
+
+/// A simple NAR writer for constructing test archives.
+#[derive(Default)]
+struct NarWriter {
+    buf: Vec<u8>,
+}
+
+impl NarWriter {
+    fn string(&mut self, s: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        self.buf.extend_from_slice(s);
+        self.buf
+            .resize(self.buf.len() + padding_len(s.len() as u64) as usize, 0);
+        self
+    }
+
+    fn strings(&mut self, strings: &[&[u8]]) -> &mut Self {
+        for s in strings {
+            self.string(s);
+        }
+        self
+    }
+}
+
+/// Returns a NAR with a directory, an executable, a file and a symlink.
+fn get_tree_nar() -> Vec<u8> {
+    let mut w = NarWriter::default();
+    w.strings(&[b"nix-archive-1", b"(", b"type", b"directory"])
+        .strings(&[b"entry", b"(", b"name", b"bin", b"node"])
+        .strings(&[b"(", b"type", b"directory"])
+        .strings(&[b"entry", b"(", b"name", b"hello", b"node"])
+        .strings(&[b"(", b"type", b"regular", b"executable", b"", b"contents"])
+        .string(b"#!/bin/sh\necho hello\n")
+        .strings(&[b")", b")"])
+        .strings(&[b")", b")"])
+        .strings(&[b"entry", b"(", b"name", b"lib", b"node"])
+        .strings(&[b"(", b"type", b"symlink", b"target", b"/nix/store/foo"])
+        .strings(&[b")", b")"])
+        .strings(&[b"entry", b"(", b"name", b"readme", b"node"])
+        .strings(&[b"(", b"type", b"regular", b"contents", b""])
+        .strings(&[b")", b")"])
+        .string(b")");
+    w.buf
+}
+
+#[test]
+fn test_single_file() {
+    let nar = include_bytes!(
+        "../nix_store/tests/nar/nm1w9sdm6j6icmhd2q3260hl1w9zj6li-attic-test-no-deps.nar"
+    );
+
+    let listing = NarListing::from_nar(nar).unwrap();
+    let expected = NarListing {
+        version: 1,
+        root: NarListingEntry::Regular {
+            size: 28,
+            executable: false,
+            nar_offset: 96,
+        },
+    };
+
+    assert_eq!(expected, listing);
+    assert_eq!(
+        r#"{"version":1,"root":{"type":"regular","size":28,"narOffset":96}}"#,
+        serde_json::to_string(&listing).unwrap()
+    );
+}
+
+#[test]
+fn test_tree() {
+    let nar = get_tree_nar();
+    let listing = NarListing::from_nar(&nar).unwrap();
+
+    let json = serde_json::to_value(&listing).unwrap();
+    let root = &json["root"];
+
+    assert_eq!("directory", root["type"]);
+    assert_eq!("symlink", root["entries"]["lib"]["type"]);
+    assert_eq!("/nix/store/foo", root["entries"]["lib"]["target"]);
+
+    let hello = &root["entries"]["bin"]["entries"]["hello"];
+    assert_eq!("regular", hello["type"]);
+    assert_eq!(true, hello["executable"]);
+    assert_eq!(21, hello["size"]);
+
+    let offset = hello["narOffset"].as_u64().unwrap() as usize;
+    assert_eq!(b"#!/bin/sh\necho hello\n", &nar[offset..offset + 21]);
+
+    let readme = &root["entries"]["readme"];
+    assert_eq!(0, readme["size"]);
+    assert!(readme.get("executable").is_none());
+}
+
+#[test]
+fn test_byte_by_byte() {
+    let nar = get_tree_nar();
+
+    let mut parser = NarParser::new();
+    for byte in nar.iter() {
+        parser.update(std::slice::from_ref(byte)).unwrap();
+    }
+
+    let listing = parser.finish().unwrap();
+    assert_eq!(NarListing::from_nar(&nar).unwrap(), listing);
+}
+
+#[test]
+fn test_invalid() {
+    let nar = get_tree_nar();
+
+    // truncated
+    assert!(matches!(
+        NarListing::from_nar(&nar[..nar.len() - 8]),
+        Err(Error::UnexpectedEof)
+    ));
+
+    // trailing data
+    let mut trailing = nar.clone();
+    trailing.extend_from_slice(&[0; 8]);
+    assert!(matches!(
+        NarListing::from_nar(&trailing),
+        Err(Error::TrailingData)
+    ));
+
+    // bad magic
+    let mut w = NarWriter::default();
+    w.strings(&[
+        b"nix-archive-2",
+        b"(",
+        b"type",
+        b"regular",
+        b"contents",
+        b"",
+        b")",
+    ]);
+    assert!(matches!(
+        NarListing::from_nar(&w.buf),
+        Err(Error::UnexpectedToken { .. })
+    ));
+
+    // unsorted entries
+    let mut w = NarWriter::default();
+    w.strings(&[b"nix-archive-1", b"(", b"type", b"directory"])
+        .strings(&[b"entry", b"(", b"name", b"b", b"node"])
+        .strings(&[b"(", b"type", b"regular", b"contents", b"", b")", b")"])
+        .strings(&[b"entry", b"(", b"name", b"a", b"node"])
+        .strings(&[b"(", b"type", b"regular", b"contents", b"", b")", b")"])
+        .string(b")");
+    assert!(matches!(
+        NarListing::from_nar(&w.buf),
+        Err(Error::UnsortedEntry(_))
+    ));
+
+    // invalid entry name
+    let mut w = NarWriter::default();
+    w.strings(&[b"nix-archive-1", b"(", b"type", b"directory"])
+        .strings(&[b"entry", b"(", b"name", b"..", b"node"])
+        .strings(&[b"(", b"type", b"regular", b"contents", b"", b")", b")"])
+        .string(b")");
+    assert!(matches!(
+        NarListing::from_nar(&w.buf),
+        Err(Error::InvalidEntryName(_))
+    ));
+}
+
+/// Returns a NAR with directories nested to some depth.
+fn get_nested_nar(depth: usize) -> Vec<u8> {
+    let mut w = NarWriter::default();
+    w.strings(&[b"nix-archive-1", b"(", b"type", b"directory"]);
+    for _ in 1..depth {
+        w.strings(&[b"entry", b"(", b"name", b"d", b"node"])
+            .strings(&[b"(", b"type", b"directory"]);
+    }
+    for _ in 1..depth {
+        w.strings(&[b")", b")"]);
+    }
+    w.string(b")");
+    w.buf
+}
+
+#[test]
+fn test_max_depth() {
+    let listing = NarListing::from_nar(&get_nested_nar(MAX_DEPTH)).unwrap();
+    serde_json::to_string(&listing).unwrap();
+
+    assert!(matches!(
+        NarListing::from_nar(&get_nested_nar(MAX_DEPTH + 1)),
+        Err(Error::TooDeep)
+    ));
+    assert!(matches!(
+        NarListing::from_nar(&get_nested_nar(100_000)),
+        Err(Error::TooDeep)
+    ));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_stream() {
+    use tokio::io::AsyncReadExt;
+
+    let nar = get_tree_nar();
+    let (mut stream, finalized) = NarListingStream::new(nar.as_slice());
+
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await.unwrap();
+
+    assert_eq!(nar, buf);
+
+    let listing = finalized
+        .get()
+        .expect("Listing wasn't finalized")
+        .as_ref()
+        .unwrap();
+    assert_eq!(&NarListing::from_nar(&nar).unwrap(), listing);
+}
+
+#[test]
+fn test_references() {
+    let a = "ia70ss13m22znbl8khrf2hq72qmh5drr";
+    let b = "nm1w9sdm6j6icmhd2q3260hl1w9zj6li";
+
+    let mut w = NarWriter::default();
+    w.strings(&[b"nix-archive-1", b"(", b"type", b"directory"])
+        .strings(&[b"entry", b"(", b"name", b"bin", b"node"])
+        .strings(&[b"(", b"type", b"regular", b"contents"])
+        .string(format!("#!/nix/store/{a}-bash/bin/sh\n/opt/store/{b}-x\n").as_bytes())
+        .strings(&[b")", b")"])
+        .strings(&[b"entry", b"(", b"name", b"lib", b"node"])
+        .strings(&[b"(", b"type", b"symlink", b"target"])
+        .string(format!("/nix/store/{b}-lib/lib").as_bytes())
+        .strings(&[b")", b")"])
+        .strings(&[b"entry", b"(", b"name", b"not-a-reference", b"node"])
+        .strings(&[b"(", b"type", b"symlink", b"target"])
+        .string(b"/nix/store/eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee-invalid")
+        .strings(&[b")", b")"])
+        .string(b")");
+    let nar = w.buf;
+    NarListing::from_nar(&nar).unwrap();
+
+    let expected: BTreeSet<String> = [a.to_string(), b.to_string()].into();
+
+    let mut scanner = ReferenceScanner::new("/nix/store");
+    scanner.update(&nar);
+    assert_eq!(expected, scanner.finish());
+
+    // references straddling slices of all sizes
+    for size in 1..64 {
+        let mut scanner = ReferenceScanner::new("/nix/store");
+        for slice in nar.chunks(size) {
+            scanner.update(slice);
+        }
+        assert_eq!(expected, scanner.finish(), "slice size {}", size);
+    }
+
+    // other store directory
+    let mut scanner = ReferenceScanner::new("/opt/store/");
+    scanner.update(&nar);
+    assert_eq!(BTreeSet::from([b.to_string()]), scanner.finish());
+}
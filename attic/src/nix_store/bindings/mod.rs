@@ -226,6 +226,23 @@ mod ffi {
             sender: Box<AsyncWriteSender>,
         ) -> Result<()>;
 
+        /// Returns the JSON representations of the realisations pointing to a path.
+        ///
+        /// Only realisations of the outputs of the deriver of the path
+        /// are returned.
+        fn query_realisations(
+            self: Pin<&mut CNixStore>,
+            store_path: &[u8],
+        ) -> Result<UniquePtr<CxxVector<CxxString>>>;
+
+        /// Returns the build log of a derivation.
+        ///
+        /// A null pointer is returned if there is no log.
+        fn query_build_log(
+            self: Pin<&mut CNixStore>,
+            store_path: &[u8],
+        ) -> Result<UniquePtr<CxxString>>;
+
         /// Obtains a handle to the Nix store.
         fn open_nix_store() -> Result<UniquePtr<CNixStore>>;
 
@@ -250,5 +267,8 @@ mod ffi {
 
         /// Returns the CA field of the store path.
         fn ca(self: Pin<&mut CPathInfo>) -> String;
+
+        /// Returns the deriver of the store path.
+        fn deriver(self: Pin<&mut CPathInfo>) -> String;
     }
 }
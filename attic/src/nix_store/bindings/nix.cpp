@@ -87,6 +87,14 @@ RString CPathInfo::ca() {
 	}
 }
 
+RString CPathInfo::deriver() {
+	if (this->pi->deriver) {
+		return RString(std::string(this->pi->deriver->to_string()));
+	} else {
+		return RString("");
+	}
+}
+
 // =========
 // CNixStore
 // =========
@@ -152,6 +160,40 @@ void CNixStore::nar_from_path(RVec<unsigned char> base_name, RBox<AsyncWriteSend
 	sink.eof();
 }
 
+std::unique_ptr<std::vector<std::string>> CNixStore::query_realisations(RBasePathSlice base_name) {
+	auto store_path = store_path_from_rust(base_name);
+	std::vector<std::string> result;
+
+	// Realisations are keyed by derivation outputs, so we need
+	// the deriver to find the ones pointing to this path
+	auto info = this->store->queryPathInfo(store_path);
+	if (!info->deriver || !this->store->isValidPath(*info->deriver)) {
+		return std::make_unique<std::vector<std::string>>(result);
+	}
+
+	auto drv = this->store->readDerivation(*info->deriver);
+	for (auto && [output_name, drv_hash] : nix::staticOutputHashes(*this->store, drv)) {
+		auto realisation = this->store->queryRealisation(nix::DrvOutput { drv_hash, output_name });
+		if (realisation && realisation->outPath == store_path) {
+			result.push_back(realisation->toJSON().dump());
+		}
+	}
+
+	return std::make_unique<std::vector<std::string>>(result);
+}
+
+std::unique_ptr<std::string> CNixStore::query_build_log(RBasePathSlice base_name) {
+	auto store_path = store_path_from_rust(base_name);
+	auto & log_store = nix::require<nix::LogStore>(*this->store);
+
+	auto log = log_store.getBuildLog(store_path);
+	if (!log) {
+		return nullptr;
+	}
+
+	return std::make_unique<std::string>(*log);
+}
+
 std::unique_ptr<CNixStore> open_nix_store() {
 	return std::make_unique<CNixStore>();
 }
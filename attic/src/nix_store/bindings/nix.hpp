@@ -14,13 +14,17 @@
 #include <mutex>
 #include <set>
 #include <nix/store-api.hh>
+#include <nix/derivations.hh>
+#include <nix/realisation.hh>
 #include <nix/local-store.hh>
+#include <nix/log-store.hh>
 #include <nix/remote-store.hh>
 #include <nix/uds-remote-store.hh>
 #include <nix/hash.hh>
 #include <nix/path.hh>
 #include <nix/serialise.hh>
 #include <nix/shared.hh>
+#include <nlohmann/json.hpp>
 #include <rust/cxx.h>
 
 template<class T> using RVec = rust::Vec<T>;
@@ -52,6 +56,7 @@ public:
 	std::unique_ptr<std::vector<std::string>> sigs();
 	std::unique_ptr<std::vector<std::string>> references();
 	RString ca();
+	RString deriver();
 };
 
 class CNixStore {
@@ -72,6 +77,8 @@ public:
 		bool include_outputs,
 		bool include_derivers);
 	void nar_from_path(RVec<unsigned char> base_name, RBox<AsyncWriteSender> sender);
+	std::unique_ptr<std::vector<std::string>> query_realisations(RBasePathSlice base_name);
+	std::unique_ptr<std::string> query_build_log(RBasePathSlice base_name);
 };
 
 std::unique_ptr<CNixStore> open_nix_store();
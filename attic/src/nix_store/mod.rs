@@ -53,7 +53,7 @@ use std::path::{Path, PathBuf};
 
 use lazy_static::lazy_static;
 use regex::Regex;
-use serde::{de, Deserialize, Serialize};
+use serde::{de, ser, Deserialize, Serialize};
 
 use crate::error::{AtticError, AtticResult};
 use crate::hash::Hash;
@@ -148,12 +148,15 @@ pub struct ValidPathInfo {
 
     /// Content Address.
     pub ca: Option<String>,
+
+    /// The derivation that produced this path, if known.
+    pub deriver: Option<StorePath>,
 }
 
 #[cfg_attr(not(feature = "nix_store"), allow(dead_code))]
 impl StorePath {
     /// Creates a StorePath with a base name.
-    fn from_base_name(base_name: PathBuf) -> AtticResult<Self> {
+    pub fn from_base_name(base_name: PathBuf) -> AtticResult<Self> {
         let s = base_name
             .as_os_str()
             .to_str()
@@ -219,6 +222,34 @@ impl StorePath {
     }
 }
 
+impl Serialize for StorePath {
+    /// Serializes a store path into its base name.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        use ser::Error;
+        let s = self
+            .base_name
+            .to_str()
+            .ok_or_else(|| Error::custom("Name contains non-UTF-8 characters"))?;
+        serializer.serialize_str(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for StorePath {
+    /// Deserializes a potentially-invalid store path from its base name.
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        use de::Error;
+        String::deserialize(deserializer).and_then(|s| {
+            Self::from_base_name(PathBuf::from(s)).map_err(|e| Error::custom(e.to_string()))
+        })
+    }
+}
+
 impl StorePathHash {
     /// Creates a store path hash from a string.
     pub fn new(hash: String) -> AtticResult<Self> {
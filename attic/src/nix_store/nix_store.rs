@@ -11,6 +11,7 @@ use super::bindings::{open_nix_store, AsyncWriteAdapter, FfiNixStore};
 use super::{to_base_name, StorePath, ValidPathInfo};
 use crate::error::AtticResult;
 use crate::hash::Hash;
+use crate::realisation::Realisation;
 
 /// High-level wrapper for the Unix Domain Socket Nix Store.
 pub struct NixStore {
@@ -222,6 +223,12 @@ impl NixStore {
                 })
                 .collect();
             let ca = c_path_info.pin_mut().ca();
+            let deriver = c_path_info.pin_mut().deriver();
+            let deriver = if deriver.is_empty() {
+                None
+            } else {
+                Some(StorePath::from_base_name(PathBuf::from(deriver))?)
+            };
 
             Ok(ValidPathInfo {
                 path: store_path,
@@ -230,9 +237,44 @@ impl NixStore {
                 references,
                 sigs,
                 ca: if ca.is_empty() { None } else { Some(ca) },
+                deriver,
             })
         })
         .await
         .unwrap()
     }
+
+    /// Returns the realisations pointing to a valid path.
+    ///
+    /// This is only useful for outputs of content-addressed derivations
+    /// and returns nothing if the deriver isn't in the store.
+    pub async fn query_realisations(&self, store_path: StorePath) -> AtticResult<Vec<Realisation>> {
+        let inner = self.inner.clone();
+
+        spawn_blocking(move || {
+            let base_name = store_path.as_base_name_bytes();
+            let cxx_vector = inner.store().query_realisations(base_name)?;
+
+            cxx_vector
+                .iter()
+                .map(|s| Realisation::from_json(s.as_bytes()))
+                .collect()
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Returns the build log of a derivation, if any.
+    pub async fn query_build_log(&self, drv_path: StorePath) -> AtticResult<Option<Vec<u8>>> {
+        let inner = self.inner.clone();
+
+        spawn_blocking(move || {
+            let base_name = drv_path.as_base_name_bytes();
+            let log = inner.store().query_build_log(base_name)?;
+
+            Ok(log.as_ref().map(|log| log.as_bytes().to_vec()))
+        })
+        .await
+        .unwrap()
+    }
 }
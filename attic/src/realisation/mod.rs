@@ -0,0 +1,200 @@
+//! Realisations of content-addressed derivations.
+//!
+//! A realisation maps a derivation output to the store path it was
+//! built into. This is needed to substitute the outputs of
+//! content-addressed derivations, whose paths are only known after
+//! they are built.
+//!
+//! ## `.doi` format
+//!
+//! Realisations are served by binary caches at
+//! `realisations/{drvOutput}.doi` in JSON:
+//!
+//! ```text
+//! {
+//!   "id": "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad!out",
+//!   "outPath": "ia70ss13m22znbl8khrf2hq72qmh5drr-ruby-2.7.5",
+//!   "signatures": [],
+//!   "dependentRealisations": {}
+//! }
+//! ```
+//!
+//! Consult `src/libstore/realisation.cc` for the Nix implementation.
+//!
+//! ## Fingerprint
+//!
+//! The fingerprint is the compact JSON serialization of the realisation
+//! without the signatures, with keys in lexicographical order.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{de, ser, Deserialize, Serialize};
+
+use crate::error::{AtticError, AtticResult};
+use crate::hash::Hash;
+use crate::nix_store::StorePath;
+use crate::signing::NixKeypair;
+
+#[cfg(test)]
+mod tests;
+
+lazy_static! {
+    /// Regex for a valid output name.
+    ///
+    /// Output names follow the same rules as the human-readable
+    /// part of store paths.
+    static ref OUTPUT_NAME_REGEX: Regex = {
+        Regex::new(r"^[A-Za-z0-9+-._?=]+$").unwrap()
+    };
+}
+
+/// An output of a derivation.
+///
+/// The string representation is `{drvHash}!{outputName}`, where
+/// the derivation hash is a typed hash in hexadecimal format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrvOutput {
+    /// The hash modulo of the derivation.
+    pub drv_hash: Hash,
+
+    /// The name of the output.
+    pub output_name: String,
+}
+
+/// A realisation of a derivation output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Realisation {
+    /// The derivation output.
+    pub id: DrvOutput,
+
+    /// The store path the output was built into.
+    pub out_path: StorePath,
+
+    /// Signatures of the realisation.
+    #[serde(default)]
+    pub signatures: Vec<String>,
+
+    /// Realisations of the dependencies of the derivation.
+    ///
+    /// This is deprecated in Nix and is usually empty.
+    #[serde(default)]
+    pub dependent_realisations: BTreeMap<String, String>,
+}
+
+/// The signed portion of a realisation.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Fingerprint<'a> {
+    dependent_realisations: &'a BTreeMap<String, String>,
+    id: &'a DrvOutput,
+    out_path: &'a StorePath,
+}
+
+impl FromStr for DrvOutput {
+    type Err = AtticError;
+
+    fn from_str(s: &str) -> AtticResult<Self> {
+        let (drv_hash, output_name) =
+            s.split_once('!')
+                .ok_or_else(|| AtticError::InvalidDrvOutput {
+                    id: s.to_owned(),
+                    reason: "ID lacks an output name",
+                })?;
+
+        if !OUTPUT_NAME_REGEX.is_match(output_name) {
+            return Err(AtticError::InvalidDrvOutput {
+                id: s.to_owned(),
+                reason: "Output name is of invalid format",
+            });
+        }
+
+        Ok(Self {
+            drv_hash: Hash::from_typed(drv_hash)?,
+            output_name: output_name.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for DrvOutput {
+    /// Formats the derivation output in its canonical representation.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}!{}",
+            self.drv_hash.to_typed_base16(),
+            self.output_name
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for DrvOutput {
+    /// Deserializes a potentially-invalid derivation output.
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        use de::Error;
+        String::deserialize(deserializer)
+            .and_then(|s| Self::from_str(&s).map_err(|e| Error::custom(e.to_string())))
+    }
+}
+
+impl Serialize for DrvOutput {
+    /// Serializes a derivation output into its canonical representation.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl Realisation {
+    /// Parses a realisation from its JSON representation.
+    pub fn from_json(json: &[u8]) -> AtticResult<Self> {
+        serde_json::from_slice(json).map_err(|e| AtticError::InvalidRealisation {
+            reason: e.to_string(),
+        })
+    }
+
+    /// Returns the JSON representation of the realisation.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Returns the fingerprint of the realisation.
+    pub fn fingerprint(&self) -> Vec<u8> {
+        let fingerprint = Fingerprint {
+            dependent_realisations: &self.dependent_realisations,
+            id: &self.id,
+            out_path: &self.out_path,
+        };
+
+        serde_json::to_vec(&fingerprint).unwrap()
+    }
+
+    /// Signs the realisation and adds the signature to the realisation.
+    ///
+    /// The signature isn't added again if it already exists.
+    pub fn sign(&mut self, keypair: &NixKeypair) {
+        let signature = keypair.sign(&self.fingerprint());
+
+        if !self.signatures.contains(&signature) {
+            self.signatures.push(signature);
+        }
+    }
+
+    /// Adds signatures that aren't already present.
+    pub fn merge_signatures(&mut self, signatures: &[String]) {
+        for signature in signatures {
+            if !self.signatures.contains(signature) {
+                self.signatures.push(signature.to_owned());
+            }
+        }
+    }
+}
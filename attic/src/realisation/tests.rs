@@ -0,0 +1,80 @@
+use super::*;
+
+const REALISATION: &str = r#"{
+    "id": "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad!out",
+    "outPath": "ia70ss13m22znbl8khrf2hq72qmh5drr-ruby-2.7.5",
+    "signatures": [],
+    "dependentRealisations": {}
+}"#;
+
+#[test]
+fn test_basic() {
+    let realisation = Realisation::from_json(REALISATION.as_bytes()).unwrap();
+
+    assert_eq!("out", realisation.id.output_name);
+    assert_eq!(
+        "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad!out",
+        realisation.id.to_string()
+    );
+    assert_eq!(
+        "ia70ss13m22znbl8khrf2hq72qmh5drr-ruby-2.7.5",
+        realisation.out_path.as_os_str()
+    );
+
+    let round_trip = Realisation::from_json(realisation.to_json().as_bytes()).unwrap();
+    assert_eq!(realisation, round_trip);
+}
+
+#[test]
+fn test_drv_output() {
+    // base32 hashes are normalized to hexadecimal
+    let id: DrvOutput = "sha256:1b8m03r63zqhnjf7l5wnldhh7c134ap5vpj0850ymkq1iyzicy5s!dev"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad!dev",
+        id.to_string()
+    );
+
+    let bad = [
+        "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad!",
+        "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad!a/b",
+        "md5:900150983cd24fb0d6963f7d28e17f72!out",
+        "!out",
+    ];
+
+    for id in bad {
+        assert!(id.parse::<DrvOutput>().is_err(), "{} should be invalid", id);
+    }
+}
+
+#[test]
+fn test_fingerprint() {
+    let realisation = Realisation::from_json(REALISATION.as_bytes()).unwrap();
+
+    assert_eq!(
+        r#"{"dependentRealisations":{},"id":"sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad!out","outPath":"ia70ss13m22znbl8khrf2hq72qmh5drr-ruby-2.7.5"}"#,
+        String::from_utf8(realisation.fingerprint()).unwrap()
+    );
+}
+
+#[test]
+fn test_sign() {
+    let keypair = NixKeypair::generate("attic-test").unwrap();
+    let mut realisation = Realisation::from_json(REALISATION.as_bytes()).unwrap();
+
+    realisation.sign(&keypair);
+    realisation.sign(&keypair);
+    assert_eq!(1, realisation.signatures.len());
+
+    keypair
+        .verify(&realisation.fingerprint(), &realisation.signatures[0])
+        .unwrap();
+
+    // signatures aren't part of the fingerprint
+    let fingerprint = realisation.fingerprint();
+    realisation.merge_signatures(&["other:c2lnbmF0dXJl".to_string()]);
+    assert_eq!(2, realisation.signatures.len());
+    assert_eq!(fingerprint, realisation.fingerprint());
+}
@@ -1,16 +1,18 @@
 use std::error::Error as StdError;
 use std::fmt;
+use std::pin::Pin;
 
 use anyhow::Result;
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use const_format::concatcp;
 use displaydoc::Display;
 use futures::{
     future,
-    stream::{self, StreamExt, TryStream, TryStreamExt},
+    stream::{self, Stream, StreamExt, TryStream, TryStreamExt},
 };
 use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION, USER_AGENT},
+    header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, USER_AGENT},
     Body, Client as HttpClient, Response, StatusCode, Url,
 };
 use serde::Deserialize;
@@ -18,12 +20,33 @@ use serde::Deserialize;
 use crate::config::ServerConfig;
 use crate::version::ATTIC_DISTRIBUTOR;
 use attic::api::v1::cache_config::{CacheConfig, CreateCacheRequest};
+use attic::api::v1::cache_events::{LaggedEvent, PathPushedEvent, EVENT_LAGGED, EVENT_PATH_PUSHED};
+use attic::api::v1::cache_stats::CacheStats;
+use attic::api::v1::copy_paths::{CopyPathsRequest, CopyPathsResponse};
+use attic::api::v1::delete_paths::{DeletePathsRequest, DeletePathsResponse};
+use attic::api::v1::get_missing_chunks::{GetMissingChunksRequest, GetMissingChunksResponse};
 use attic::api::v1::get_missing_paths::{GetMissingPathsRequest, GetMissingPathsResponse};
+use attic::api::v1::list_caches::ListCachesResponse;
+use attic::api::v1::list_objects::{ListObjectsQuery, ListObjectsResponse};
+use attic::api::v1::narinfo_batch::{NarInfoBatchRequest, NarInfoBatchResponse};
+use attic::api::v1::pin::{
+    ListPinsResponse, PinPathsRequest, PinPathsResponse, UnpinPathsRequest, UnpinPathsResponse,
+};
+use attic::api::v1::possession_challenge::{
+    GetPossessionChallengeRequest, GetPossessionChallengeResponse, PossessionChallenge,
+    PossessionProof,
+};
+use attic::api::v1::tag::{SetTagRequest, TagHistoryResponse, TagInfo};
+use attic::api::v1::upload_log::{UploadLogInfo, ATTIC_BUILD_LOG_INFO};
 use attic::api::v1::upload_path::{
-    UploadPathNarInfo, UploadPathResult, ATTIC_NAR_INFO, ATTIC_NAR_INFO_PREAMBLE_SIZE,
+    UploadChunkedPathInfo, UploadPathNarInfo, UploadPathResult, ATTIC_NAR_INFO,
+    ATTIC_NAR_INFO_PREAMBLE_SIZE, ATTIC_POSSESSION_PROOF,
 };
+use attic::api::v1::upload_realisation::UploadRealisationRequest;
 use attic::cache::CacheName;
+use attic::hash::Hash;
 use attic::nix_store::StorePathHash;
+use attic::realisation::Realisation;
 
 /// The User-Agent string of Attic.
 const ATTIC_USER_AGENT: &str =
@@ -60,6 +83,22 @@ pub struct StructuredApiError {
     message: String,
 }
 
+/// An event in a cache.
+#[derive(Debug)]
+pub enum CacheEvent {
+    /// A store path was pushed.
+    PathPushed(PathPushedEvent),
+
+    /// Events were dropped because we fell behind.
+    Lagged(LaggedEvent),
+}
+
+/// A reader of Server-Sent Events.
+struct SseReader {
+    stream: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>,
+    buffer: Vec<u8>,
+}
+
 impl ApiClient {
     pub fn from_server_config(config: ServerConfig) -> Result<Self> {
         let client = build_http_client(config.token()?.as_deref());
@@ -94,6 +133,69 @@ impl ApiClient {
         }
     }
 
+    /// Returns the storage statistics of a cache.
+    pub async fn get_cache_stats(&self, cache: &CacheName) -> Result<CacheStats> {
+        let endpoint = self
+            .endpoint
+            .join("_api/v1/cache/")?
+            .join(&format!("{}/stats", cache.as_str()))?;
+
+        let res = self.client.get(endpoint).send().await?;
+
+        if res.status().is_success() {
+            let stats = res.json().await?;
+            Ok(stats)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Subscribes to events in a cache.
+    ///
+    /// The stream ends when the server closes the connection.
+    pub async fn get_cache_events(
+        &self,
+        cache: &CacheName,
+    ) -> Result<impl Stream<Item = Result<CacheEvent>>> {
+        let endpoint = self
+            .endpoint
+            .join("_api/v1/cache/")?
+            .join(&format!("{}/events", cache.as_str()))?;
+
+        let res = self
+            .client
+            .get(endpoint)
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            let api_error = ApiError::try_from_response(res).await?;
+            return Err(api_error.into());
+        }
+
+        let reader = SseReader {
+            stream: Box::pin(res.bytes_stream()),
+            buffer: Vec::new(),
+        };
+
+        Ok(stream::try_unfold(reader, |mut reader| async move {
+            while let Some((name, data)) = reader.next_event().await? {
+                let event = match name.as_str() {
+                    EVENT_PATH_PUSHED => CacheEvent::PathPushed(serde_json::from_str(&data)?),
+                    EVENT_LAGGED => CacheEvent::Lagged(serde_json::from_str(&data)?),
+                    // Events added in newer servers
+                    _ => continue,
+                };
+
+                return Ok(Some((event, reader)));
+            }
+
+            Ok(None)
+        }))
+    }
+
     /// Creates a cache.
     pub async fn create_cache(&self, cache: &CacheName, request: CreateCacheRequest) -> Result<()> {
         let endpoint = self
@@ -168,6 +270,308 @@ impl ApiClient {
         }
     }
 
+    /// Deletes paths from a cache.
+    pub async fn delete_paths(
+        &self,
+        cache: &CacheName,
+        store_path_hashes: Vec<StorePathHash>,
+        closure: bool,
+        dry_run: bool,
+    ) -> Result<DeletePathsResponse> {
+        let endpoint = self.endpoint.join("_api/v1/delete-paths")?;
+        let payload = DeletePathsRequest {
+            cache: cache.to_owned(),
+            store_path_hashes,
+            closure,
+            dry_run,
+        };
+
+        let res = self.client.post(endpoint).json(&payload).send().await?;
+
+        if res.status().is_success() {
+            let deleted = res.json().await?;
+            Ok(deleted)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Copies paths between caches on the server.
+    pub async fn copy_paths(
+        &self,
+        source_cache: &CacheName,
+        destination_cache: &CacheName,
+        store_path_hashes: Vec<StorePathHash>,
+        closure: bool,
+    ) -> Result<CopyPathsResponse> {
+        let endpoint = self.endpoint.join("_api/v1/copy-paths")?;
+        let payload = CopyPathsRequest {
+            source_cache: source_cache.to_owned(),
+            destination_cache: destination_cache.to_owned(),
+            store_path_hashes,
+            closure,
+        };
+
+        let res = self.client.post(endpoint).json(&payload).send().await?;
+
+        if res.status().is_success() {
+            let copied = res.json().await?;
+            Ok(copied)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Pins paths in a cache.
+    pub async fn pin_paths(
+        &self,
+        cache: &CacheName,
+        store_path_hashes: Vec<StorePathHash>,
+        name: Option<String>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<PinPathsResponse> {
+        let endpoint = self.endpoint.join("_api/v1/pin-paths")?;
+        let payload = PinPathsRequest {
+            cache: cache.to_owned(),
+            store_path_hashes,
+            name,
+            expires_at,
+        };
+
+        let res = self.client.post(endpoint).json(&payload).send().await?;
+
+        if res.status().is_success() {
+            let pinned = res.json().await?;
+            Ok(pinned)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Unpins paths in a cache.
+    pub async fn unpin_paths(
+        &self,
+        cache: &CacheName,
+        store_path_hashes: Vec<StorePathHash>,
+        name: Option<String>,
+    ) -> Result<UnpinPathsResponse> {
+        let endpoint = self.endpoint.join("_api/v1/unpin-paths")?;
+        let payload = UnpinPathsRequest {
+            cache: cache.to_owned(),
+            store_path_hashes,
+            name,
+        };
+
+        let res = self.client.post(endpoint).json(&payload).send().await?;
+
+        if res.status().is_success() {
+            let unpinned = res.json().await?;
+            Ok(unpinned)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Lists pins in a cache.
+    pub async fn list_pins(&self, cache: &CacheName) -> Result<ListPinsResponse> {
+        let endpoint = self
+            .endpoint
+            .join("_api/v1/cache/")?
+            .join(&format!("{}/pins", cache.as_str()))?;
+
+        let res = self.client.get(endpoint).send().await?;
+
+        if res.status().is_success() {
+            let pins = res.json().await?;
+            Ok(pins)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Returns the current target of a tag.
+    pub async fn get_tag(&self, cache: &CacheName, tag: &str) -> Result<TagInfo> {
+        let endpoint = self.endpoint.join("_api/v1/cache/")?.join(&format!(
+            "{}/tags/{}",
+            cache.as_str(),
+            tag
+        ))?;
+
+        let res = self.client.get(endpoint).send().await?;
+
+        if res.status().is_success() {
+            let tag = res.json().await?;
+            Ok(tag)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Points a tag at a store path.
+    pub async fn set_tag(
+        &self,
+        cache: &CacheName,
+        tag: &str,
+        store_path_hash: StorePathHash,
+    ) -> Result<TagInfo> {
+        let endpoint = self.endpoint.join("_api/v1/cache/")?.join(&format!(
+            "{}/tags/{}",
+            cache.as_str(),
+            tag
+        ))?;
+        let payload = SetTagRequest { store_path_hash };
+
+        let res = self.client.put(endpoint).json(&payload).send().await?;
+
+        if res.status().is_success() {
+            let tag = res.json().await?;
+            Ok(tag)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Returns the history of a tag.
+    pub async fn get_tag_history(
+        &self,
+        cache: &CacheName,
+        tag: &str,
+    ) -> Result<TagHistoryResponse> {
+        let endpoint = self.endpoint.join("_api/v1/cache/")?.join(&format!(
+            "{}/tag-history/{}",
+            cache.as_str(),
+            tag
+        ))?;
+
+        let res = self.client.get(endpoint).send().await?;
+
+        if res.status().is_success() {
+            let history = res.json().await?;
+            Ok(history)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Lists caches that can be discovered.
+    pub async fn list_caches(&self) -> Result<ListCachesResponse> {
+        let endpoint = self.endpoint.join("_api/v1/caches")?;
+
+        let res = self.client.get(endpoint).send().await?;
+
+        if res.status().is_success() {
+            let caches = res.json().await?;
+            Ok(caches)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Lists objects in a cache.
+    pub async fn list_objects(
+        &self,
+        cache: &CacheName,
+        query: &ListObjectsQuery,
+    ) -> Result<ListObjectsResponse> {
+        let endpoint = self
+            .endpoint
+            .join("_api/v1/cache/")?
+            .join(&format!("{}/objects", cache.as_str()))?;
+
+        let res = self.client.get(endpoint).query(query).send().await?;
+
+        if res.status().is_success() {
+            let objects = res.json().await?;
+            Ok(objects)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Returns chunks that need to be uploaded to a cache.
+    pub async fn get_missing_chunks(
+        &self,
+        cache: &CacheName,
+        chunk_hashes: Vec<Hash>,
+    ) -> Result<GetMissingChunksResponse> {
+        let endpoint = self.endpoint.join("_api/v1/get-missing-chunks")?;
+        let payload = GetMissingChunksRequest {
+            cache: cache.to_owned(),
+            chunk_hashes,
+        };
+
+        let res = self.client.post(endpoint).json(&payload).send().await?;
+
+        if res.status().is_success() {
+            let missing_chunks = res.json().await?;
+            Ok(missing_chunks)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Returns a challenge to prove possession of a NAR.
+    ///
+    /// Returns `None` if the server doesn't require proof of possession.
+    pub async fn get_possession_challenge(
+        &self,
+        cache: &CacheName,
+        nar_hash: Hash,
+        nar_size: usize,
+    ) -> Result<Option<PossessionChallenge>> {
+        let endpoint = self.endpoint.join("_api/v1/get-possession-challenge")?;
+        let payload = GetPossessionChallengeRequest {
+            cache: cache.to_owned(),
+            nar_hash,
+            nar_size,
+        };
+
+        let res = self.client.post(endpoint).json(&payload).send().await?;
+
+        if res.status().is_success() {
+            let response: GetPossessionChallengeResponse = res.json().await?;
+            Ok(response.challenge)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Returns the NAR information of paths in a cache.
+    #[allow(dead_code)]
+    pub async fn get_nar_info_batch(
+        &self,
+        cache: &CacheName,
+        store_path_hashes: Vec<StorePathHash>,
+    ) -> Result<NarInfoBatchResponse> {
+        let endpoint = self.endpoint.join("_api/v1/narinfo-batch")?;
+        let payload = NarInfoBatchRequest {
+            cache: cache.to_owned(),
+            store_path_hashes,
+        };
+
+        let res = self.client.post(endpoint).json(&payload).send().await?;
+
+        if res.status().is_success() {
+            let narinfos = res.json().await?;
+            Ok(narinfos)
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
     /// Uploads a path.
     pub async fn upload_path<S>(
         &self,
@@ -214,11 +618,150 @@ impl ApiClient {
             Err(api_error.into())
         }
     }
+
+    /// Uploads a path by proving possession of a NAR the server already has.
+    pub async fn upload_path_with_proof(
+        &self,
+        nar_info: UploadPathNarInfo,
+        proof: &PossessionProof,
+    ) -> Result<Option<UploadPathResult>> {
+        let endpoint = self.endpoint.join("_api/v1/upload-path")?;
+
+        // There is no NAR, so the NAR info is the entire body
+        let preamble = serde_json::to_vec(&nar_info)?;
+        let preamble_len = preamble.len();
+
+        let res = self
+            .client
+            .put(endpoint)
+            .header(USER_AGENT, HeaderValue::from_str(ATTIC_USER_AGENT)?)
+            .header(ATTIC_NAR_INFO_PREAMBLE_SIZE, preamble_len)
+            .header(
+                ATTIC_POSSESSION_PROOF,
+                HeaderValue::from_str(&serde_json::to_string(proof)?)?,
+            )
+            .body(preamble)
+            .send()
+            .await?;
+
+        if res.status().is_success() {
+            match res.json().await {
+                Ok(r) => Ok(Some(r)),
+                Err(_) => Ok(None),
+            }
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Uploads a path as a list of chunks.
+    ///
+    /// The stream must contain the data of the included chunks in order.
+    pub async fn upload_chunked_path<S>(
+        &self,
+        upload_info: UploadChunkedPathInfo,
+        stream: S,
+    ) -> Result<Option<UploadPathResult>>
+    where
+        S: TryStream<Ok = Bytes> + Send + Sync + 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>> + Send + Sync,
+    {
+        let endpoint = self.endpoint.join("_api/v1/upload-chunked-path")?;
+
+        // The chunk list can be large, so it's always sent in the body
+        let preamble = Bytes::from(serde_json::to_vec(&upload_info)?);
+        let preamble_len = preamble.len();
+        let preamble_stream = stream::once(future::ok(preamble));
+        let chained = preamble_stream.chain(stream.into_stream());
+
+        let res = self
+            .client
+            .put(endpoint)
+            .header(USER_AGENT, HeaderValue::from_str(ATTIC_USER_AGENT)?)
+            .header(ATTIC_NAR_INFO_PREAMBLE_SIZE, preamble_len)
+            .body(Body::wrap_stream(chained))
+            .send()
+            .await?;
+
+        if res.status().is_success() {
+            match res.json().await {
+                Ok(r) => Ok(Some(r)),
+                Err(_) => Ok(None),
+            }
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Uploads realisations.
+    pub async fn upload_realisations(
+        &self,
+        cache: &CacheName,
+        realisations: Vec<Realisation>,
+    ) -> Result<()> {
+        let endpoint = self.endpoint.join("_api/v1/upload-realisation")?;
+        let payload = UploadRealisationRequest {
+            cache: cache.to_owned(),
+            realisations,
+        };
+
+        let res = self.client.put(endpoint).json(&payload).send().await?;
+
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
+
+    /// Uploads the build log of a derivation.
+    pub async fn upload_log(
+        &self,
+        cache: &CacheName,
+        drv_path: String,
+        log: Vec<u8>,
+    ) -> Result<()> {
+        let endpoint = self.endpoint.join("_api/v1/upload-log")?;
+        let upload_info = UploadLogInfo {
+            cache: cache.to_owned(),
+            drv_path,
+        };
+        let upload_info_json = serde_json::to_string(&upload_info)?;
+
+        let res = self
+            .client
+            .put(endpoint)
+            .header(
+                ATTIC_BUILD_LOG_INFO,
+                HeaderValue::from_str(&upload_info_json)?,
+            )
+            .body(log)
+            .send()
+            .await?;
+
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            let api_error = ApiError::try_from_response(res).await?;
+            Err(api_error.into())
+        }
+    }
 }
 
 impl StdError for ApiError {}
 
 impl ApiError {
+    /// Returns the name of the error if it's structured.
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::Structured(e) => Some(&e.error),
+            Self::Unstructured(_, _) => None,
+        }
+    }
+
     async fn try_from_response(response: Response) -> Result<Self> {
         let status = response.status();
         let text = response.text().await?;
@@ -229,6 +772,64 @@ impl ApiError {
     }
 }
 
+impl SseReader {
+    /// Reads the next event, returning its name and data.
+    async fn next_event(&mut self) -> Result<Option<(String, String)>> {
+        let mut name = None;
+        let mut data: Vec<String> = Vec::new();
+
+        while let Some(line) = self.next_line().await? {
+            if line.is_empty() {
+                // End of event
+                if !data.is_empty() {
+                    let name = name.unwrap_or_else(|| "message".to_string());
+                    return Ok(Some((name, data.join("\n"))));
+                }
+
+                name = None;
+                continue;
+            }
+
+            if line.starts_with(':') {
+                // Comment, used for keep-alive
+                continue;
+            }
+
+            let (field, value) = line.split_once(':').unwrap_or((line.as_str(), ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+
+            match field {
+                "event" => name = Some(value.to_string()),
+                "data" => data.push(value.to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads the next line, without the line terminator.
+    async fn next_line(&mut self) -> Result<Option<String>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+                let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+                line.pop();
+
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+
+                return Ok(Some(String::from_utf8(line)?));
+            }
+
+            match self.stream.next().await {
+                Some(bytes) => self.buffer.extend_from_slice(&bytes?),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
 impl fmt::Display for StructuredApiError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}: {}", self.error, self.message)
@@ -8,10 +8,16 @@ use clap_complete::Shell;
 use enum_as_inner::EnumAsInner;
 
 use crate::command::cache::{self, Cache};
+use crate::command::copy::{self, Copy};
+use crate::command::delete::{self, Delete};
 use crate::command::get_closure::{self, GetClosure};
 use crate::command::login::{self, Login};
+use crate::command::pin::{self, Pin};
 use crate::command::push::{self, Push};
 use crate::command::r#use::{self, Use};
+use crate::command::tag::{self, Tag};
+use crate::command::unpin::{self, Unpin};
+use crate::command::watch_cache::{self, WatchCache};
 use crate::command::watch_store::{self, WatchStore};
 
 /// Attic binary cache client.
@@ -28,8 +34,14 @@ pub enum Command {
     Login(Login),
     Use(Use),
     Push(Push),
-    Cache(Cache),
+    Cache(Box<Cache>),
+    Delete(Delete),
+    Copy(Copy),
+    Pin(Pin),
+    Unpin(Unpin),
+    Tag(Tag),
     WatchStore(WatchStore),
+    WatchCache(WatchCache),
 
     #[clap(hide = true)]
     GetClosure(GetClosure),
@@ -55,7 +67,13 @@ pub async fn run() -> Result<()> {
         Command::Use(_) => r#use::run(opts).await,
         Command::Push(_) => push::run(opts).await,
         Command::Cache(_) => cache::run(opts).await,
+        Command::Delete(_) => delete::run(opts).await,
+        Command::Copy(_) => copy::run(opts).await,
+        Command::Pin(_) => pin::run(opts).await,
+        Command::Unpin(_) => unpin::run(opts).await,
+        Command::Tag(_) => tag::run(opts).await,
         Command::WatchStore(_) => watch_store::run(opts).await,
+        Command::WatchCache(_) => watch_cache::run(opts).await,
         Command::GetClosure(_) => get_closure::run(opts).await,
     }
 }
@@ -1,15 +1,25 @@
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
 use dialoguer::Input;
 use humantime::Duration;
+use indicatif::HumanBytes;
 
 use crate::api::ApiClient;
-use crate::cache::CacheRef;
+use crate::cache::{CacheRef, ServerName};
 use crate::cli::Opts;
 use crate::config::Config;
 use attic::api::v1::cache_config::{
-    CacheConfig, CreateCacheRequest, KeypairConfig, RetentionPeriodConfig,
+    CacheConfig, CreateCacheRequest, KeypairConfig, KeypairState, QuotaConfig,
+    RetentionPeriodConfig, WebhookConfig,
 };
+use attic::api::v1::list_caches::CachePermissions;
+use attic::api::v1::list_objects::{ListObjectsQuery, ObjectInfo};
+use attic::api::v1::webhook::WebhookEvent;
+use attic::cache::CacheName;
 
 /// Manage caches on an Attic server.
 #[derive(Debug, Parser)]
@@ -24,6 +34,8 @@ enum Command {
     Configure(Configure),
     Destroy(Destroy),
     Info(Info),
+    List(List),
+    Ls(Ls),
 }
 
 /// Create a cache.
@@ -72,6 +84,37 @@ struct Create {
         default_value = "cache.nixos.org-1"
     )]
     upstream_cache_key_names: Vec<String>,
+
+    /// The URL of an upstream binary cache to pull through.
+    ///
+    /// Store paths missing from the cache will be served from
+    /// the upstream caches, which are consulted in order. Specify
+    /// this flag multiple times to add multiple upstreams.
+    #[clap(value_name = "URL", long = "upstream")]
+    upstream_urls: Vec<String>,
+
+    /// Ingest NARs fetched from upstream caches.
+    ///
+    /// Ingested NARs are uploaded to the cache so subsequent
+    /// requests are served locally.
+    #[clap(long)]
+    ingest_upstream: bool,
+
+    /// The name of a cache to layer as a member.
+    ///
+    /// A cache with members is virtual. Store paths missing from
+    /// the cache will be served from the members that the client
+    /// can pull from, which are consulted in order. Specify this
+    /// flag multiple times to add multiple members.
+    #[clap(value_name = "CACHE", long = "member")]
+    member_caches: Vec<CacheName>,
+
+    /// Use a keypair held by an external signer on the server.
+    ///
+    /// The secret key of the signer is never stored in the
+    /// server's database. By default, a keypair is generated.
+    #[clap(value_name = "SIGNER", long = "signer")]
+    signer: Option<String>,
 }
 
 /// Configure a cache.
@@ -87,10 +130,26 @@ struct Configure {
     ///
     /// The server-side signing key will be regenerated and
     /// all users will need to configure the new signing key
-    /// in `nix.conf`.
+    /// in `nix.conf`. The previous keypair keeps signing
+    /// objects until it's revoked with `--revoke-key`.
     #[clap(long)]
     regenerate_keypair: bool,
 
+    /// Switch to a keypair held by an external signer on the server.
+    ///
+    /// Like `--regenerate-keypair`, the previous keypair keeps
+    /// signing objects until it's revoked with `--revoke-key`.
+    #[clap(value_name = "SIGNER", long = "signer")]
+    signer: Option<String>,
+
+    /// Revoke a previous keypair by its public key.
+    ///
+    /// Objects will no longer be signed with the keypair.
+    /// Specify this flag multiple times to revoke multiple
+    /// keypairs.
+    #[clap(value_name = "PUBLIC_KEY", long = "revoke-key")]
+    revoke_public_keys: Option<Vec<String>>,
+
     /// Make the cache public.
     ///
     /// Use `--private` to make it private.
@@ -125,6 +184,43 @@ struct Configure {
     #[clap(value_name = "NAME", long = "upstream-cache-key-name")]
     upstream_cache_key_names: Option<Vec<String>>,
 
+    /// The URL of an upstream binary cache to pull through.
+    ///
+    /// Store paths missing from the cache will be served from
+    /// the upstream caches, which are consulted in order. Specify
+    /// this flag multiple times to add multiple upstreams.
+    #[clap(value_name = "URL", long = "upstream")]
+    upstream_urls: Option<Vec<String>>,
+
+    /// Remove all upstream binary caches.
+    #[clap(long)]
+    no_upstreams: bool,
+
+    /// Ingest NARs fetched from upstream caches.
+    ///
+    /// Use `--no-ingest-upstream` to disable ingestion.
+    #[clap(long)]
+    ingest_upstream: bool,
+
+    /// Don't ingest NARs fetched from upstream caches.
+    ///
+    /// Use `--ingest-upstream` to enable ingestion.
+    #[clap(long)]
+    no_ingest_upstream: bool,
+
+    /// The name of a cache to layer as a member.
+    ///
+    /// A cache with members is virtual. Store paths missing from
+    /// the cache will be served from the members that the client
+    /// can pull from, which are consulted in order. Specify this
+    /// flag multiple times to add multiple members.
+    #[clap(value_name = "CACHE", long = "member")]
+    member_caches: Option<Vec<CacheName>>,
+
+    /// Remove all member caches.
+    #[clap(long)]
+    no_members: bool,
+
     /// Set the retention period of the cache.
     ///
     /// You can use expressions like "2 years", "3 months"
@@ -135,6 +231,66 @@ struct Configure {
     /// Reset the retention period of the cache to global default.
     #[clap(long)]
     reset_retention_period: bool,
+
+    /// Set the maximum total NAR size of objects in the cache.
+    ///
+    /// You can use sizes like "500 MiB", "10G" and "1TB".
+    /// Pushes that would exceed the quota are rejected.
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    quota: Option<u64>,
+
+    /// Set the maximum number of objects in the cache.
+    #[clap(long, value_name = "COUNT")]
+    quota_objects: Option<u64>,
+
+    /// Remove all quotas of the cache.
+    #[clap(long)]
+    no_quota: bool,
+
+    /// Validate pushed NARs.
+    ///
+    /// NARs must be well-formed, and pushed paths must declare all
+    /// store paths found in their NARs as references.
+    ///
+    /// Use `--no-validate-nars` to disable validation.
+    #[clap(long)]
+    validate_nars: bool,
+
+    /// Don't validate pushed NARs.
+    ///
+    /// Use `--validate-nars` to enable validation.
+    #[clap(long)]
+    no_validate_nars: bool,
+
+    /// The URL of a webhook to notify of events in the cache.
+    ///
+    /// Notifications are sent as HTTP POST requests with a JSON
+    /// payload. Specify this flag multiple times to add multiple
+    /// webhooks. The webhooks replace all existing webhooks.
+    #[clap(value_name = "URL", long = "webhook")]
+    webhooks: Option<Vec<String>>,
+
+    /// A file containing the secret used to sign notifications
+    /// to the webhooks.
+    ///
+    /// Use "-" to read the secret from standard input. The
+    /// HMAC-SHA256 of the payload is sent in the `X-Attic-Signature`
+    /// header.
+    #[clap(long, value_name = "PATH")]
+    webhook_secret_file: Option<PathBuf>,
+
+    /// An event to notify the webhooks of.
+    ///
+    /// One of "path-pushed", "cache-configured", "cache-destroyed"
+    /// and "garbage-collected". Specify this flag multiple times
+    /// to select multiple events. By default, the webhooks are
+    /// notified of all events.
+    #[clap(value_name = "EVENT", long = "webhook-event", value_parser = parse_webhook_event)]
+    webhook_events: Vec<WebhookEvent>,
+
+    /// Remove all webhooks.
+    #[clap(long)]
+    no_webhooks: bool,
 }
 
 /// Destroy a cache.
@@ -161,6 +317,76 @@ struct Destroy {
 struct Info {
     /// Name of the cache to query.
     cache: CacheRef,
+
+    /// Also show the storage statistics of the cache.
+    ///
+    /// Stored bytes that are shared with other caches would
+    /// not be freed if the cache were destroyed.
+    #[clap(long)]
+    stats: bool,
+}
+
+/// List caches on a server.
+///
+/// Only caches that you have any permission on and public
+/// caches are listed.
+#[derive(Debug, Clone, Parser)]
+struct List {
+    /// Name of the server.
+    ///
+    /// By default, the default server is used.
+    server: Option<ServerName>,
+}
+
+/// List objects in a cache.
+///
+/// Objects are listed from the most recently uploaded. Times
+/// can be either RFC 3339 timestamps like "2024-05-01T00:00:00Z"
+/// or durations like "1 day" that are relative to now.
+///
+/// You need the `pull` permission on the cache.
+#[derive(Debug, Clone, Parser)]
+struct Ls {
+    /// Name of the cache to list.
+    cache: CacheRef,
+
+    /// Only list store paths containing this string.
+    #[clap(long)]
+    name: Option<String>,
+
+    /// Only list objects uploaded by this user.
+    #[clap(long, value_name = "USER")]
+    created_by: Option<String>,
+
+    /// Only list objects built for this system.
+    #[clap(long)]
+    system: Option<String>,
+
+    /// Only list objects uploaded at or after this time.
+    #[clap(long, value_name = "TIME", value_parser = parse_time)]
+    created_after: Option<DateTime<Utc>>,
+
+    /// Only list objects uploaded before this time.
+    #[clap(long, value_name = "TIME", value_parser = parse_time)]
+    created_before: Option<DateTime<Utc>>,
+
+    /// Only list objects last accessed at or after this time.
+    #[clap(long, value_name = "TIME", value_parser = parse_time)]
+    accessed_after: Option<DateTime<Utc>>,
+
+    /// Only list objects last accessed before this time.
+    ///
+    /// Objects that have never been accessed are included.
+    #[clap(long, value_name = "TIME", value_parser = parse_time)]
+    accessed_before: Option<DateTime<Utc>>,
+
+    /// The maximum number of objects to list.
+    #[clap(long)]
+    limit: Option<usize>,
+
+    /// Output the objects as JSON.
+    #[clap(long)]
+    json: bool,
 }
 
 pub async fn run(opts: Opts) -> Result<()> {
@@ -170,6 +396,8 @@ pub async fn run(opts: Opts) -> Result<()> {
         Command::Configure(sub) => configure_cache(sub.to_owned()).await,
         Command::Destroy(sub) => destroy_cache(sub.to_owned()).await,
         Command::Info(sub) => show_cache_config(sub.to_owned()).await,
+        Command::List(sub) => list_caches(sub.to_owned()).await,
+        Command::Ls(sub) => list_objects(sub.to_owned()).await,
     }
 }
 
@@ -179,13 +407,20 @@ async fn create_cache(sub: Create) -> Result<()> {
     let (server_name, server, cache) = config.resolve_cache(&sub.cache)?;
     let api = ApiClient::from_server_config(server.clone())?;
 
+    let keypair = match sub.signer {
+        Some(signer) => KeypairConfig::External(signer),
+        None => KeypairConfig::Generate,
+    };
+
     let request = CreateCacheRequest {
-        // TODO: Make this configurable?
-        keypair: KeypairConfig::Generate,
+        keypair,
         is_public: sub.public,
         priority: sub.priority,
         store_dir: sub.store_dir,
         upstream_cache_key_names: sub.upstream_cache_key_names,
+        upstream_urls: sub.upstream_urls,
+        ingest_upstream: sub.ingest_upstream,
+        member_caches: sub.member_caches,
     };
 
     api.create_cache(cache, request).await?;
@@ -216,6 +451,56 @@ async fn configure_cache(sub: Configure) -> Result<()> {
         ));
     }
 
+    if sub.upstream_urls.is_some() && sub.no_upstreams {
+        return Err(anyhow!(
+            "`--upstream` and `--no-upstreams` cannot be set at the same time."
+        ));
+    }
+
+    if sub.ingest_upstream && sub.no_ingest_upstream {
+        return Err(anyhow!(
+            "`--ingest-upstream` and `--no-ingest-upstream` cannot be set at the same time."
+        ));
+    }
+
+    if sub.member_caches.is_some() && sub.no_members {
+        return Err(anyhow!(
+            "`--member` and `--no-members` cannot be set at the same time."
+        ));
+    }
+
+    if sub.regenerate_keypair && sub.signer.is_some() {
+        return Err(anyhow!(
+            "`--regenerate-keypair` and `--signer` cannot be set at the same time."
+        ));
+    }
+
+    if (sub.quota.is_some() || sub.quota_objects.is_some()) && sub.no_quota {
+        return Err(anyhow!(
+            "`--quota` and `--quota-objects` cannot be set at the same time as `--no-quota`."
+        ));
+    }
+
+    if sub.validate_nars && sub.no_validate_nars {
+        return Err(anyhow!(
+            "`--validate-nars` and `--no-validate-nars` cannot be set at the same time."
+        ));
+    }
+
+    if sub.webhooks.is_some() && sub.no_webhooks {
+        return Err(anyhow!(
+            "`--webhook` and `--no-webhooks` cannot be set at the same time."
+        ));
+    }
+
+    if sub.webhooks.is_none()
+        && (sub.webhook_secret_file.is_some() || !sub.webhook_events.is_empty())
+    {
+        return Err(anyhow!(
+            "`--webhook-secret-file` and `--webhook-event` require `--webhook`."
+        ));
+    }
+
     if sub.public {
         patch.is_public = Some(true);
     } else if sub.private {
@@ -230,12 +515,68 @@ async fn configure_cache(sub: Configure) -> Result<()> {
 
     if sub.regenerate_keypair {
         patch.keypair = Some(KeypairConfig::Generate);
+    } else if let Some(signer) = sub.signer {
+        patch.keypair = Some(KeypairConfig::External(signer));
     }
 
+    patch.revoke_public_keys = sub.revoke_public_keys;
+
     patch.store_dir = sub.store_dir;
     patch.priority = sub.priority;
     patch.upstream_cache_key_names = sub.upstream_cache_key_names;
 
+    if sub.no_upstreams {
+        patch.upstream_urls = Some(Vec::new());
+    } else {
+        patch.upstream_urls = sub.upstream_urls;
+    }
+
+    if sub.ingest_upstream {
+        patch.ingest_upstream = Some(true);
+    } else if sub.no_ingest_upstream {
+        patch.ingest_upstream = Some(false);
+    }
+
+    if sub.no_members {
+        patch.member_caches = Some(Vec::new());
+    } else {
+        patch.member_caches = sub.member_caches;
+    }
+
+    if sub.no_quota {
+        patch.quota_bytes = Some(QuotaConfig::Unlimited);
+        patch.quota_objects = Some(QuotaConfig::Unlimited);
+    } else {
+        patch.quota_bytes = sub.quota.map(QuotaConfig::Limit);
+        patch.quota_objects = sub.quota_objects.map(QuotaConfig::Limit);
+    }
+
+    if sub.validate_nars {
+        patch.validate_nars = Some(true);
+    } else if sub.no_validate_nars {
+        patch.validate_nars = Some(false);
+    }
+
+    if sub.no_webhooks {
+        patch.webhooks = Some(Vec::new());
+    } else if let Some(urls) = sub.webhooks {
+        let secret = sub
+            .webhook_secret_file
+            .as_deref()
+            .map(read_secret)
+            .transpose()?;
+
+        patch.webhooks = Some(
+            urls.into_iter()
+                .map(|url| WebhookConfig {
+                    url,
+                    secret: secret.clone(),
+                    events: sub.webhook_events.clone(),
+                })
+                .collect(),
+        );
+    }
+
     let api = ApiClient::from_server_config(server.clone())?;
     api.configure_cache(cache, &patch).await?;
 
@@ -298,6 +639,17 @@ async fn show_cache_config(sub: Info) -> Result<()> {
         eprintln!("           Public Key: {}", public_key);
     }
 
+    if let Some(public_keys) = cache_config.public_keys {
+        for public_key in public_keys {
+            if public_key.state != KeypairState::Active {
+                eprintln!(
+                    "  Previous Public Key: {} ({:?})",
+                    public_key.public_key, public_key.state
+                );
+            }
+        }
+    }
+
     if let Some(substituter_endpoint) = cache_config.substituter_endpoint {
         eprintln!("Binary Cache Endpoint: {}", substituter_endpoint);
     }
@@ -318,6 +670,19 @@ async fn show_cache_config(sub: Info) -> Result<()> {
         eprintln!("  Upstream Cache Keys: {:?}", upstream_cache_key_names);
     }
 
+    if let Some(upstream_urls) = cache_config.upstream_urls {
+        eprintln!("      Upstream Caches: {:?}", upstream_urls);
+    }
+
+    if let Some(ingest_upstream) = cache_config.ingest_upstream {
+        eprintln!("      Ingest Upstream: {}", ingest_upstream);
+    }
+
+    if let Some(member_caches) = cache_config.member_caches {
+        let member_caches: Vec<&str> = member_caches.iter().map(CacheName::as_str).collect();
+        eprintln!("        Member Caches: {:?}", member_caches);
+    }
+
     if let Some(retention_period) = cache_config.retention_period {
         match retention_period {
             RetentionPeriodConfig::Period(period) => {
@@ -329,5 +694,250 @@ async fn show_cache_config(sub: Info) -> Result<()> {
         }
     }
 
+    if let Some(QuotaConfig::Limit(quota)) = cache_config.quota_bytes {
+        eprintln!("           Size Quota: {}", HumanBytes(quota));
+    }
+
+    if let Some(QuotaConfig::Limit(quota)) = cache_config.quota_objects {
+        eprintln!("         Object Quota: {}", quota);
+    }
+
+    if let Some(validate_nars) = cache_config.validate_nars {
+        eprintln!("        Validate NARs: {}", validate_nars);
+    }
+
+    if let Some(webhooks) = cache_config.webhooks {
+        for webhook in webhooks {
+            let events = if webhook.events.is_empty() {
+                "all events".to_string()
+            } else {
+                webhook
+                    .events
+                    .iter()
+                    .map(WebhookEvent::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+
+            eprintln!("              Webhook: {} ({})", webhook.url, events);
+        }
+    }
+
+    if sub.stats {
+        let stats = api.get_cache_stats(cache).await?;
+
+        eprintln!("              Objects: {}", stats.object_count);
+        eprintln!("             NAR Size: {}", HumanBytes(stats.nar_size));
+        eprintln!("          Stored Size: {}", HumanBytes(stats.stored_size));
+        eprintln!("          Shared Size: {}", HumanBytes(stats.shared_size));
+
+        if let Some(last_pushed_at) = stats.last_pushed_at {
+            eprintln!("            Last Push: {}", last_pushed_at.to_rfc3339());
+        }
+    }
+
     Ok(())
 }
+
+async fn list_caches(sub: List) -> Result<()> {
+    let config = Config::load()?;
+
+    let (server_name, server) = config.resolve_server(sub.server.as_ref())?;
+    let api = ApiClient::from_server_config(server.clone())?;
+    let caches = api.list_caches().await?.caches;
+
+    if caches.is_empty() {
+        eprintln!("No caches found on \"{}\".", server_name.as_str());
+        return Ok(());
+    }
+
+    let width = caches
+        .iter()
+        .map(|cache| cache.name.as_str().len())
+        .max()
+        .unwrap_or_default();
+
+    for cache in caches {
+        let visibility = if cache.is_public { "public" } else { "private" };
+        println!(
+            "{:<width$}  {:<7}  {}",
+            cache.name.as_str(),
+            visibility,
+            format_permission(&cache.permission),
+        );
+    }
+
+    Ok(())
+}
+
+async fn list_objects(sub: Ls) -> Result<()> {
+    let config = Config::load()?;
+
+    let (_, server, cache) = config.resolve_cache(&sub.cache)?;
+    let api = ApiClient::from_server_config(server.clone())?;
+
+    let mut query = ListObjectsQuery {
+        name: sub.name,
+        created_by: sub.created_by,
+        system: sub.system,
+        created_after: sub.created_after,
+        created_before: sub.created_before,
+        accessed_after: sub.accessed_after,
+        accessed_before: sub.accessed_before,
+        ..Default::default()
+    };
+
+    let mut objects: Vec<ObjectInfo> = Vec::new();
+    loop {
+        if let Some(limit) = sub.limit {
+            if objects.len() >= limit {
+                break;
+            }
+            query.limit = Some(limit - objects.len());
+        }
+
+        let page = api.list_objects(cache, &query).await?;
+        objects.extend(page.objects);
+
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => break,
+        }
+    }
+
+    if sub.json {
+        println!("{}", serde_json::to_string_pretty(&objects)?);
+        return Ok(());
+    }
+
+    if objects.is_empty() {
+        eprintln!("No objects found.");
+        return Ok(());
+    }
+
+    let rows: Vec<[String; 4]> = objects
+        .into_iter()
+        .map(|object| {
+            [
+                object.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                object.created_by.unwrap_or_else(|| "-".to_string()),
+                HumanBytes(object.nar_size).to_string(),
+                object.store_path,
+            ]
+        })
+        .collect();
+
+    let header = ["CREATED", "CREATED BY", "NAR SIZE", "STORE PATH"].map(str::to_string);
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        println!(
+            "{:<w0$}  {:<w1$}  {:>w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+        );
+    }
+
+    Ok(())
+}
+
+/// Formats permissions as a comma-separated list.
+fn format_permission(permission: &CachePermissions) -> String {
+    let granted = [
+        (permission.pull, "pull"),
+        (permission.push, "push"),
+        (permission.delete, "delete"),
+        (permission.create_cache, "create-cache"),
+        (permission.configure_cache, "configure-cache"),
+        (
+            permission.configure_cache_retention,
+            "configure-cache-retention",
+        ),
+        (permission.destroy_cache, "destroy-cache"),
+    ];
+
+    granted
+        .into_iter()
+        .filter_map(|(granted, name)| granted.then_some(name))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses an RFC 3339 timestamp or a duration relative to now.
+fn parse_time(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let duration = humantime::parse_duration(s)
+        .map_err(|_| anyhow!("\"{}\" is neither a timestamp nor a duration", s))?;
+
+    Ok(Utc::now() - chrono::Duration::from_std(duration)?)
+}
+
+/// Reads a secret from a file, or standard input if the path is "-".
+///
+/// Trailing newlines are stripped.
+fn read_secret(path: &Path) -> Result<String> {
+    let mut secret = String::new();
+
+    if path == Path::new("-") {
+        io::stdin().read_to_string(&mut secret)?;
+    } else {
+        secret = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    }
+
+    let secret = secret.trim_end_matches(['\r', '\n']);
+    if secret.is_empty() {
+        return Err(anyhow!("The webhook secret is empty."));
+    }
+
+    Ok(secret.to_string())
+}
+
+/// Parses the name of a webhook event.
+fn parse_webhook_event(s: &str) -> Result<WebhookEvent> {
+    serde_json::from_value(serde_json::Value::String(s.to_string()))
+        .map_err(|_| anyhow!("\"{}\" is not a webhook event", s))
+}
+
+/// Parses a size like "10 GiB".
+///
+/// Both binary and decimal units are accepted.
+fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("\"{}\" is not a valid size", s))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        _ => return Err(anyhow!("\"{}\" has an unknown unit", s)),
+    };
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("\"{}\" is too large", s))
+}
@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+use crate::api::ApiClient;
+use crate::cache::CacheRef;
+use crate::cli::Opts;
+use crate::command::delete::parse_store_path_hash;
+use crate::config::Config;
+
+/// Copy store paths between binary caches on the same server.
+///
+/// The paths are copied on the server without transferring any
+/// data. You need the `pull` permission on the source cache and
+/// the `push` permission on the destination cache.
+#[derive(Debug, Parser)]
+pub struct Copy {
+    /// The cache to copy from.
+    ///
+    /// This can be either `servername:cachename` or `cachename`
+    /// when using the default server.
+    source: CacheRef,
+
+    /// The cache to copy to.
+    destination: CacheRef,
+
+    /// The store paths to copy.
+    ///
+    /// Full store paths, base names and hashes are accepted.
+    /// The paths don't need to exist locally.
+    #[clap(required = true)]
+    paths: Vec<String>,
+
+    /// Also copy the closures of the paths.
+    ///
+    /// Only paths in the source cache are followed.
+    #[clap(long)]
+    closure: bool,
+}
+
+pub async fn run(opts: Opts) -> Result<()> {
+    let sub = opts.command.as_copy().unwrap();
+    let config = Config::load()?;
+
+    let (server_name, server, source) = config.resolve_cache(&sub.source)?;
+    let (destination_server_name, _, destination) = config.resolve_cache(&sub.destination)?;
+
+    if server_name != destination_server_name {
+        return Err(anyhow!(
+            "Paths can only be copied between caches on the same server"
+        ));
+    }
+
+    let mut api = ApiClient::from_server_config(server.clone())?;
+    let cache_config = api.get_cache_config(source).await?;
+
+    if let Some(api_endpoint) = &cache_config.api_endpoint {
+        // Use delegated API endpoint
+        api.set_endpoint(api_endpoint)?;
+    }
+
+    let store_dir = cache_config.store_dir.as_deref().unwrap_or("/nix/store");
+    let store_path_hashes = sub
+        .paths
+        .iter()
+        .map(|path| parse_store_path_hash(Path::new(store_dir), path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let result = api
+        .copy_paths(source, destination, store_path_hashes, sub.closure)
+        .await?;
+
+    for hash in &result.missing_paths {
+        eprintln!("⚠️ {} is not in \"{}\"", hash.as_str(), source.as_str());
+    }
+
+    for path in &result.copied_paths {
+        eprintln!("✅ {}", path);
+    }
+
+    eprintln!(
+        "Copied {} paths from \"{}\" to \"{}\" on \"{}\" ({} already present)",
+        result.copied_paths.len(),
+        source.as_str(),
+        destination.as_str(),
+        server_name.as_str(),
+        result.skipped_paths.len(),
+    );
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+use crate::api::ApiClient;
+use crate::cache::CacheRef;
+use crate::cli::Opts;
+use crate::config::Config;
+use attic::nix_store::{StorePath, StorePathHash};
+
+/// Delete store paths from a binary cache.
+///
+/// You need the `delete` permission on the cache. The underlying
+/// data is garbage-collected once no cache refers to it.
+#[derive(Debug, Parser)]
+pub struct Delete {
+    /// The cache to delete from.
+    ///
+    /// This can be either `servername:cachename` or `cachename`
+    /// when using the default server.
+    cache: CacheRef,
+
+    /// The store paths to delete.
+    ///
+    /// Full store paths, base names and hashes are accepted.
+    /// The paths don't need to exist locally.
+    #[clap(required = true)]
+    paths: Vec<String>,
+
+    /// Also delete the closures of the paths.
+    ///
+    /// Only paths in the cache are followed. Paths in the closures
+    /// are deleted even if other paths in the cache refer to them.
+    #[clap(long)]
+    closure: bool,
+
+    /// Only show the paths that would be deleted.
+    #[clap(long)]
+    dry_run: bool,
+}
+
+pub async fn run(opts: Opts) -> Result<()> {
+    let sub = opts.command.as_delete().unwrap();
+    let config = Config::load()?;
+
+    let (server_name, server, cache) = config.resolve_cache(&sub.cache)?;
+
+    let mut api = ApiClient::from_server_config(server.clone())?;
+    let cache_config = api.get_cache_config(cache).await?;
+
+    if let Some(api_endpoint) = &cache_config.api_endpoint {
+        // Use delegated API endpoint
+        api.set_endpoint(api_endpoint)?;
+    }
+
+    let store_dir = cache_config.store_dir.as_deref().unwrap_or("/nix/store");
+    let store_path_hashes = sub
+        .paths
+        .iter()
+        .map(|path| parse_store_path_hash(Path::new(store_dir), path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let result = api
+        .delete_paths(cache, store_path_hashes, sub.closure, sub.dry_run)
+        .await?;
+
+    for hash in &result.missing_paths {
+        eprintln!("⚠️ {} is not in the cache", hash.as_str());
+    }
+
+    for path in &result.deleted_paths {
+        eprintln!("🗑️ {}", path);
+    }
+
+    if sub.dry_run {
+        eprintln!(
+            "Would delete {} paths from \"{}\" on \"{}\"",
+            result.deleted_paths.len(),
+            cache.as_str(),
+            server_name.as_str()
+        );
+    } else {
+        eprintln!(
+            "✅ Deleted {} paths from \"{}\" on \"{}\"",
+            result.deleted_paths.len(),
+            cache.as_str(),
+            server_name.as_str()
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the hash of a store path, base name or hash.
+pub(crate) fn parse_store_path_hash(store_dir: &Path, path: &str) -> Result<StorePathHash> {
+    let base_name = match Path::new(path).strip_prefix(store_dir) {
+        Ok(rest) => rest
+            .components()
+            .next()
+            .ok_or_else(|| anyhow!("{} is not a store path", path))?
+            .as_os_str(),
+        Err(_) => path.as_ref(),
+    };
+
+    if let Some(hash) = base_name
+        .to_str()
+        .and_then(|s| StorePathHash::new(s.to_owned()).ok())
+    {
+        return Ok(hash);
+    }
+
+    let store_path = StorePath::from_base_name(PathBuf::from(base_name))?;
+    Ok(store_path.to_hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_store_path_hash() {
+        let store_dir = Path::new("/nix/store");
+        let hash = "ia70ss13m22znbl8khrf2hq72qmh5drr";
+
+        for path in [
+            "/nix/store/ia70ss13m22znbl8khrf2hq72qmh5drr-ruby-2.7.5",
+            "/nix/store/ia70ss13m22znbl8khrf2hq72qmh5drr-ruby-2.7.5/bin/ruby",
+            "ia70ss13m22znbl8khrf2hq72qmh5drr-ruby-2.7.5",
+            "ia70ss13m22znbl8khrf2hq72qmh5drr",
+        ] {
+            assert_eq!(
+                hash,
+                parse_store_path_hash(store_dir, path).unwrap().as_str()
+            );
+        }
+
+        assert!(parse_store_path_hash(store_dir, "/nix/store").is_err());
+        assert!(parse_store_path_hash(store_dir, "ruby-2.7.5").is_err());
+        assert!(
+            parse_store_path_hash(store_dir, "/tmp/ia70ss13m22znbl8khrf2hq72qmh5drr-ruby").is_err()
+        );
+    }
+}
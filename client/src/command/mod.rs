@@ -1,6 +1,12 @@
 pub mod cache;
+pub mod copy;
+pub mod delete;
 pub mod get_closure;
 pub mod login;
+pub mod pin;
 pub mod push;
+pub mod tag;
+pub mod unpin;
 pub mod r#use;
+pub mod watch_cache;
 pub mod watch_store;
@@ -0,0 +1,121 @@
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::Utc;
+use clap::Parser;
+use humantime::Duration;
+
+use crate::api::ApiClient;
+use crate::cache::CacheRef;
+use crate::cli::Opts;
+use crate::command::delete::parse_store_path_hash;
+use crate::config::Config;
+
+/// Pin store paths in a binary cache.
+///
+/// Pinned paths and their closures in the cache are exempt from
+/// time-based garbage collection. You need the
+/// `configure_cache_retention` permission on the cache.
+#[derive(Debug, Parser)]
+pub struct Pin {
+    /// The cache to pin paths in.
+    ///
+    /// This can be either `servername:cachename` or `cachename`
+    /// when using the default server.
+    cache: CacheRef,
+
+    /// The store paths to pin.
+    ///
+    /// Full store paths, base names and hashes are accepted.
+    /// The paths don't need to exist locally.
+    #[clap(required_unless_present = "list")]
+    paths: Vec<String>,
+
+    /// A name for the pins, like a release version.
+    ///
+    /// All paths pinned with a name can be unpinned at once
+    /// with `attic unpin --name`.
+    #[clap(long)]
+    name: Option<String>,
+
+    /// Expire the pins after a duration.
+    ///
+    /// You can use expressions like "30 days" and "1y". By default,
+    /// the pins never expire.
+    #[clap(long, value_name = "DURATION")]
+    expires_in: Option<Duration>,
+
+    /// List the pins in the cache instead.
+    #[clap(long, conflicts_with_all = ["paths", "name", "expires_in"])]
+    list: bool,
+}
+
+pub async fn run(opts: Opts) -> Result<()> {
+    let sub = opts.command.as_pin().unwrap();
+    let config = Config::load()?;
+
+    let (server_name, server, cache) = config.resolve_cache(&sub.cache)?;
+
+    let mut api = ApiClient::from_server_config(server.clone())?;
+    let cache_config = api.get_cache_config(cache).await?;
+
+    if let Some(api_endpoint) = &cache_config.api_endpoint {
+        // Use delegated API endpoint
+        api.set_endpoint(api_endpoint)?;
+    }
+
+    if sub.list {
+        let pins = api.list_pins(cache).await?.pins;
+
+        if pins.is_empty() {
+            eprintln!("No pins in \"{}\"", cache.as_str());
+        }
+
+        for pin in pins {
+            let expiry = match pin.expires_at {
+                Some(expires_at) => format!("expires {}", expires_at.to_rfc3339()),
+                None => "never expires".to_string(),
+            };
+
+            match pin.name {
+                Some(name) => println!("{} ({}, {})", pin.store_path, name, expiry),
+                None => println!("{} ({})", pin.store_path, expiry),
+            }
+        }
+
+        return Ok(());
+    }
+
+    let store_dir = cache_config.store_dir.as_deref().unwrap_or("/nix/store");
+    let store_path_hashes = sub
+        .paths
+        .iter()
+        .map(|path| parse_store_path_hash(Path::new(store_dir), path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let expires_at = sub
+        .expires_in
+        .map(|duration| chrono::Duration::from_std(*duration).map(|d| Utc::now() + d))
+        .transpose()?;
+
+    let result = api
+        .pin_paths(cache, store_path_hashes, sub.name.clone(), expires_at)
+        .await?;
+
+    for hash in &result.missing_paths {
+        eprintln!("⚠️ {} is not in the cache", hash.as_str());
+    }
+
+    for path in &result.pinned_paths {
+        eprintln!("📌 {}", path);
+    }
+
+    eprintln!(
+        "✅ Pinned {} paths in \"{}\" on \"{}\"",
+        result.pinned_paths.len(),
+        cache.as_str(),
+        server_name.as_str()
+    );
+
+    Ok(())
+}
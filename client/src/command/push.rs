@@ -42,6 +42,12 @@ pub struct Push {
     #[clap(short = 'j', long, default_value = "5")]
     jobs: usize,
 
+    /// Upload the build logs of the derivers of pushed paths.
+    ///
+    /// Only logs present in the local store are uploaded.
+    #[clap(long)]
+    logs: bool,
+
     /// Always send the upload info as part of the payload.
     #[clap(long, hide = true)]
     force_preamble: bool,
@@ -79,6 +85,8 @@ impl PushContext {
             .await?;
 
         if plan.store_path_map.is_empty() {
+            self.pusher.upload_realisations(plan.realisations).await;
+
             if plan.num_all_paths == 0 {
                 eprintln!("🤷 Nothing selected.");
             } else {
@@ -100,10 +108,17 @@ impl PushContext {
             );
         }
 
+        let derivers = plan.derivers();
+
         for (_, path_info) in plan.store_path_map {
             self.pusher.queue(path_info).await?;
         }
 
+        // The paths may still be uploading, but a realisation pointing
+        // to a missing path merely causes Nix to build it locally
+        self.pusher.upload_realisations(plan.realisations).await;
+        self.pusher.upload_logs(derivers).await;
+
         let results = self.pusher.wait().await;
         results.into_values().collect::<Result<Vec<()>>>()?;
 
@@ -159,6 +174,7 @@ pub async fn run(opts: Opts) -> Result<()> {
     let push_config = PushConfig {
         num_workers: sub.jobs,
         force_preamble: sub.force_preamble,
+        upload_logs: sub.logs,
     };
 
     let mp = MultiProgress::new();
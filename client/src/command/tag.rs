@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use crate::api::ApiClient;
+use crate::cache::CacheRef;
+use crate::cli::Opts;
+use crate::command::delete::parse_store_path_hash;
+use crate::config::Config;
+
+/// Manage tags in a binary cache.
+///
+/// Tags are named pointers to store paths, like `myapp/latest`.
+/// The current targets of tags and their closures are exempt
+/// from time-based garbage collection.
+#[derive(Debug, Parser)]
+pub struct Tag {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    Set(Set),
+    Get(Get),
+    History(History),
+}
+
+/// Point a tag at a store path.
+///
+/// You need the `push` permission on the cache.
+#[derive(Debug, Clone, Parser)]
+struct Set {
+    /// The cache containing the tag.
+    ///
+    /// This can be either `servername:cachename` or `cachename`
+    /// when using the default server.
+    cache: CacheRef,
+
+    /// The name of the tag.
+    tag: String,
+
+    /// The store path to point the tag at.
+    ///
+    /// The path must be in the cache.
+    path: String,
+}
+
+/// Print the store path a tag points at.
+#[derive(Debug, Clone, Parser)]
+struct Get {
+    /// The cache containing the tag.
+    cache: CacheRef,
+
+    /// The name of the tag.
+    tag: String,
+}
+
+/// Show the previous targets of a tag.
+#[derive(Debug, Clone, Parser)]
+struct History {
+    /// The cache containing the tag.
+    cache: CacheRef,
+
+    /// The name of the tag.
+    tag: String,
+}
+
+pub async fn run(opts: Opts) -> Result<()> {
+    let sub = opts.command.as_tag().unwrap();
+    match &sub.command {
+        Command::Set(sub) => set_tag(sub.to_owned()).await,
+        Command::Get(sub) => get_tag(sub.to_owned()).await,
+        Command::History(sub) => show_tag_history(sub.to_owned()).await,
+    }
+}
+
+async fn set_tag(sub: Set) -> Result<()> {
+    let config = Config::load()?;
+
+    let (_, server, cache) = config.resolve_cache(&sub.cache)?;
+    let mut api = ApiClient::from_server_config(server.clone())?;
+    let cache_config = api.get_cache_config(cache).await?;
+
+    if let Some(api_endpoint) = &cache_config.api_endpoint {
+        // Use delegated API endpoint
+        api.set_endpoint(api_endpoint)?;
+    }
+
+    let store_dir = cache_config.store_dir.as_deref().unwrap_or("/nix/store");
+    let store_path_hash = parse_store_path_hash(Path::new(store_dir), &sub.path)?;
+
+    let tag = api.set_tag(cache, &sub.tag, store_path_hash).await?;
+
+    eprintln!("✅ {} -> {}", tag.name, tag.store_path);
+
+    Ok(())
+}
+
+async fn get_tag(sub: Get) -> Result<()> {
+    let config = Config::load()?;
+
+    let (_, server, cache) = config.resolve_cache(&sub.cache)?;
+    let api = ApiClient::from_server_config(server.clone())?;
+    let tag = api.get_tag(cache, &sub.tag).await?;
+
+    println!("{}", tag.store_path);
+
+    Ok(())
+}
+
+async fn show_tag_history(sub: History) -> Result<()> {
+    let config = Config::load()?;
+
+    let (_, server, cache) = config.resolve_cache(&sub.cache)?;
+    let api = ApiClient::from_server_config(server.clone())?;
+    let history = api.get_tag_history(cache, &sub.tag).await?.history;
+
+    for tag in history {
+        let created_by = tag.created_by.as_deref().unwrap_or("-");
+        println!(
+            "{}  {:<16}  {}",
+            tag.created_at.format("%Y-%m-%d %H:%M:%S"),
+            created_by,
+            tag.store_path
+        );
+    }
+
+    Ok(())
+}
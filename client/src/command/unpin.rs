@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use anyhow::Result;
+use clap::Parser;
+
+use crate::api::ApiClient;
+use crate::cache::CacheRef;
+use crate::cli::Opts;
+use crate::command::delete::parse_store_path_hash;
+use crate::config::Config;
+
+/// Unpin store paths in a binary cache.
+///
+/// The paths become subject to time-based garbage collection
+/// again. You need the `configure_cache_retention` permission
+/// on the cache.
+#[derive(Debug, Parser)]
+pub struct Unpin {
+    /// The cache to unpin paths in.
+    ///
+    /// This can be either `servername:cachename` or `cachename`
+    /// when using the default server.
+    cache: CacheRef,
+
+    /// The store paths to unpin.
+    ///
+    /// Full store paths, base names and hashes are accepted.
+    #[clap(required_unless_present = "name")]
+    paths: Vec<String>,
+
+    /// Unpin all paths pinned with this name.
+    #[clap(long)]
+    name: Option<String>,
+}
+
+pub async fn run(opts: Opts) -> Result<()> {
+    let sub = opts.command.as_unpin().unwrap();
+    let config = Config::load()?;
+
+    let (server_name, server, cache) = config.resolve_cache(&sub.cache)?;
+
+    let mut api = ApiClient::from_server_config(server.clone())?;
+    let cache_config = api.get_cache_config(cache).await?;
+
+    if let Some(api_endpoint) = &cache_config.api_endpoint {
+        // Use delegated API endpoint
+        api.set_endpoint(api_endpoint)?;
+    }
+
+    let store_dir = cache_config.store_dir.as_deref().unwrap_or("/nix/store");
+    let store_path_hashes = sub
+        .paths
+        .iter()
+        .map(|path| parse_store_path_hash(Path::new(store_dir), path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let result = api
+        .unpin_paths(cache, store_path_hashes, sub.name.clone())
+        .await?;
+
+    for path in &result.unpinned_paths {
+        eprintln!("✅ {}", path);
+    }
+
+    eprintln!(
+        "Unpinned {} paths in \"{}\" on \"{}\"",
+        result.unpinned_paths.len(),
+        cache.as_str(),
+        server_name.as_str()
+    );
+
+    Ok(())
+}
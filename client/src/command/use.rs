@@ -8,6 +8,7 @@ use crate::cli::Opts;
 use crate::config::Config;
 use crate::nix_config::NixConfig;
 use crate::nix_netrc::NixNetrc;
+use attic::api::v1::cache_config::{CachePublicKey, KeypairState};
 
 /// Configure Nix to use a binary cache.
 #[derive(Debug, Parser)]
@@ -34,6 +35,14 @@ pub async fn run(opts: Opts) -> Result<()> {
     let public_key = cache_config.public_key
         .ok_or_else(|| anyhow!("The server did not tell us which public key it uses. Is signing managed by the client?"))?;
 
+    // Older servers only tell us about the current public key
+    let public_keys = cache_config.public_keys.unwrap_or_else(|| {
+        vec![CachePublicKey {
+            public_key,
+            state: KeypairState::Active,
+        }]
+    });
+
     eprintln!(
         "Configuring Nix to use \"{cache}\" on \"{server_name}\":",
         cache = cache.as_str(),
@@ -42,11 +51,20 @@ pub async fn run(opts: Opts) -> Result<()> {
 
     // Modify nix.conf
     eprintln!("+ Substituter: {}", substituter);
-    eprintln!("+ Trusted Public Key: {}", public_key);
 
     let mut nix_config = NixConfig::load().await?;
     nix_config.add_substituter(&substituter);
-    nix_config.add_trusted_public_key(&public_key);
+
+    // Keys are prepended, so add the current key last to have it first
+    for public_key in public_keys.iter().rev() {
+        if public_key.state == KeypairState::Revoked {
+            eprintln!("- Trusted Public Key: {}", public_key.public_key);
+            nix_config.remove_trusted_public_key(&public_key.public_key);
+        } else {
+            eprintln!("+ Trusted Public Key: {}", public_key.public_key);
+            nix_config.add_trusted_public_key(&public_key.public_key);
+        }
+    }
 
     // Modify netrc
     if let Some(token) = server.token()? {
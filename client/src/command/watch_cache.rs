@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use futures::stream::StreamExt;
+use tokio::time;
+
+use crate::api::{ApiClient, CacheEvent};
+use crate::cache::CacheRef;
+use crate::cli::Opts;
+use crate::config::Config;
+
+/// Delay before reconnecting after losing the connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Watch a binary cache for newly pushed paths.
+///
+/// Store paths are printed to stdout as they are pushed, one per
+/// line. Paths pushed while disconnected are not printed.
+///
+/// You need the `pull` permission on the cache.
+#[derive(Debug, Parser)]
+pub struct WatchCache {
+    /// The cache to watch.
+    ///
+    /// This can be either `servername:cachename` or `cachename`
+    /// when using the default server.
+    cache: CacheRef,
+
+    /// Print the events as JSON, one per line.
+    #[clap(long)]
+    json: bool,
+}
+
+pub async fn run(opts: Opts) -> Result<()> {
+    let sub = opts.command.as_watch_cache().unwrap();
+    let config = Config::load()?;
+
+    let (server_name, server, cache) = config.resolve_cache(&sub.cache)?;
+    let mut api = ApiClient::from_server_config(server.clone())?;
+    let cache_config = api.get_cache_config(cache).await?;
+
+    if let Some(api_endpoint) = &cache_config.api_endpoint {
+        // Use delegated API endpoint
+        api.set_endpoint(api_endpoint)?;
+    }
+
+    // Errors on the first connection are fatal
+    let mut events = Box::pin(api.get_cache_events(cache).await?);
+
+    eprintln!(
+        "👀 Watching \"{}\" on \"{}\"...",
+        cache.as_str(),
+        server_name.as_str()
+    );
+
+    loop {
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(CacheEvent::PathPushed(event)) => {
+                    if sub.json {
+                        println!("{}", serde_json::to_string(&event)?);
+                    } else {
+                        println!("{}", event.store_path);
+                    }
+                }
+                Ok(CacheEvent::Lagged(event)) => {
+                    eprintln!(
+                        "⚠️ Fell behind and missed up to {} events",
+                        event.missed_events
+                    );
+                }
+                Err(e) => {
+                    eprintln!("⚠️ Lost connection: {}", e);
+                    break;
+                }
+            }
+        }
+
+        loop {
+            time::sleep(RECONNECT_DELAY).await;
+
+            match api.get_cache_events(cache).await {
+                Ok(stream) => {
+                    events = Box::pin(stream);
+                    eprintln!("👀 Reconnected");
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("⚠️ Failed to reconnect: {}", e);
+                }
+            }
+        }
+    }
+}
@@ -186,12 +186,25 @@ impl ConfigData {
                 Ok((name, config, cache))
             }
             CacheRef::ServerQualified(server, cache) => {
+                let (name, config) = self.resolve_server(Some(server))?;
+                Ok((name, config, cache))
+            }
+        }
+    }
+
+    pub fn resolve_server<'a>(
+        &'a self,
+        server: Option<&'a ServerName>,
+    ) -> Result<(&'a ServerName, &'a ServerConfig)> {
+        match server {
+            Some(server) => {
                 let config = self
                     .servers
                     .get(server)
                     .ok_or_else(|| anyhow!("Server \"{}\" does not exist", server.as_str()))?;
-                Ok((server, config, cache))
+                Ok((server, config))
             }
+            None => self.default_server(),
         }
     }
 }
@@ -100,6 +100,11 @@ impl NixConfig {
         self.prepend_to_list("trusted-public-keys", public_key, CACHE_NIXOS_ORG_KEY);
     }
 
+    /// Removes a trusted public key.
+    pub fn remove_trusted_public_key(&mut self, public_key: &str) {
+        self.remove_from_list("trusted-public-keys", public_key);
+    }
+
     /// Sets the netrc-file config.
     pub fn set_netrc_file(&mut self, path: &str) {
         if let Some(kv) = self.find_key("netrc-file") {
@@ -131,6 +136,20 @@ impl NixConfig {
         }
     }
 
+    fn remove_from_list(&mut self, key: &str, value: &str) {
+        if let Some(Line::KV {
+            value: ref mut list,
+            ..
+        }) = self.find_key(key)
+        {
+            *list = list
+                .split(' ')
+                .filter(|el| *el != value)
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+    }
+
     fn find_key(&mut self, key: &str) -> Option<&mut Line> {
         self.lines.iter_mut().find(|l| {
             if let Line::KV { key: k, .. } = l {
@@ -247,6 +266,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_nix_config_trusted_public_keys() {
+        let mut nix_config = NixConfig {
+            path: None,
+            lines: Line::from_lines("trusted-public-keys = old:a= other:b=").unwrap(),
+        };
+
+        nix_config.add_trusted_public_key("new:c=");
+        nix_config.add_trusted_public_key("other:b=");
+        nix_config.remove_trusted_public_key("old:a=");
+
+        assert_eq!("trusted-public-keys = new:c= other:b=", nix_config.to_string());
+    }
+
     #[test]
     fn test_nix_config_line_roundtrip() {
         let cases = [
@@ -17,6 +17,7 @@
 
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::io;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -26,18 +27,33 @@ use anyhow::{anyhow, Result};
 use async_channel as channel;
 use bytes::Bytes;
 use futures::future::join_all;
-use futures::stream::{Stream, TryStreamExt};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressState, ProgressStyle};
 use tokio::sync::{mpsc, Mutex};
 use tokio::task::{spawn, JoinHandle};
 use tokio::time;
-
-use crate::api::ApiClient;
-use attic::api::v1::cache_config::CacheConfig;
-use attic::api::v1::upload_path::{UploadPathNarInfo, UploadPathResult, UploadPathResultKind};
+use tokio_util::io::StreamReader;
+
+use crate::api::{ApiClient, ApiError};
+use attic::api::v1::cache_config::{CacheConfig, ChunkingParameters};
+use attic::api::v1::possession_challenge::{PossessionChallenge, PossessionProof, RangeCollector};
+use attic::api::v1::upload_path::{
+    NarChunk, UploadChunkedPathInfo, UploadPathNarInfo, UploadPathResult, UploadPathResultKind,
+};
 use attic::cache::CacheName;
+use attic::chunking::chunk_stream;
 use attic::error::AtticResult;
+use attic::hash::Hash;
 use attic::nix_store::{NixStore, StorePath, StorePathHash, ValidPathInfo};
+use attic::realisation::Realisation;
+
+/// Number of chunk hashes to query in a single request.
+const MISSING_CHUNKS_BATCH_SIZE: usize = 1000;
+
+/// Minimum NAR size to try proving possession instead of uploading.
+///
+/// Proofs are only attempted for chunked uploads.
+const POSSESSION_PROOF_THRESHOLD: usize = 1024 * 1024;
 
 type JobSender = channel::Sender<ValidPathInfo>;
 type JobReceiver = channel::Receiver<ValidPathInfo>;
@@ -50,6 +66,9 @@ pub struct PushConfig {
 
     /// Whether to always include the upload info in the PUT payload.
     pub force_preamble: bool,
+
+    /// Whether to upload the build logs of the derivers of pushed paths.
+    pub upload_logs: bool,
 }
 
 /// Configuration for a push session.
@@ -72,6 +91,7 @@ pub struct Pusher {
     store: Arc<NixStore>,
     cache: CacheName,
     cache_config: CacheConfig,
+    config: PushConfig,
     workers: Vec<JoinHandle<HashMap<StorePath, Result<()>>>>,
     sender: JobSender,
 }
@@ -133,6 +153,12 @@ pub struct PushPlan {
 
     /// Number of paths that have been filtered out because they are signed by an upstream cache.
     pub num_upstream: usize,
+
+    /// Realisations pointing to paths in the closure.
+    ///
+    /// These include realisations of paths that are already cached,
+    /// since the same output can be produced by different derivations.
+    pub realisations: Vec<Realisation>,
 }
 
 /// Wrapper to update a progress bar as a NAR is streamed.
@@ -159,6 +185,7 @@ impl Pusher {
                 store.clone(),
                 api.clone(),
                 cache.clone(),
+                cache_config.chunking.clone(),
                 mp.clone(),
                 config,
             )));
@@ -169,6 +196,7 @@ impl Pusher {
             store,
             cache,
             cache_config,
+            config,
             workers,
             sender,
         }
@@ -216,6 +244,63 @@ impl Pusher {
         .await
     }
 
+    /// Uploads realisations to the cache.
+    ///
+    /// Failures are reported but not fatal, since older servers
+    /// don't support realisations.
+    pub async fn upload_realisations(&self, realisations: Vec<Realisation>) {
+        if realisations.is_empty() {
+            return;
+        }
+
+        let num_realisations = realisations.len();
+        if let Err(e) = self
+            .api
+            .upload_realisations(&self.cache, realisations)
+            .await
+        {
+            eprintln!(
+                "⚠️ Failed to upload {} realisations: {}",
+                num_realisations, e
+            );
+        }
+    }
+
+    /// Uploads the build logs of derivations to the cache.
+    ///
+    /// This does nothing unless `upload_logs` is enabled. Derivations
+    /// without logs in the local store are skipped, and failures are
+    /// reported but not fatal.
+    pub async fn upload_logs(&self, derivers: HashSet<StorePath>) {
+        if !self.config.upload_logs {
+            return;
+        }
+
+        let mut num_uploaded = 0;
+        for deriver in derivers {
+            let log = match self.store.query_build_log(deriver.clone()).await {
+                Ok(Some(log)) => log,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!("Could not query build log of {:?}: {}", deriver, e);
+                    continue;
+                }
+            };
+
+            let drv_path = deriver.as_os_str().to_string_lossy().into_owned();
+            match self.api.upload_log(&self.cache, drv_path, log).await {
+                Ok(()) => num_uploaded += 1,
+                Err(e) => {
+                    eprintln!("⚠️ Failed to upload build log of {:?}: {}", deriver, e);
+                }
+            }
+        }
+
+        if num_uploaded > 0 {
+            eprintln!("📜 Uploaded {} build logs", num_uploaded);
+        }
+    }
+
     /// Converts the pusher into a `PushSession`.
     ///
     /// This is useful when the list of store paths is streamed from some
@@ -230,6 +315,7 @@ impl Pusher {
         store: Arc<NixStore>,
         api: ApiClient,
         cache: CacheName,
+        chunking: Option<ChunkingParameters>,
         mp: MultiProgress,
         config: PushConfig,
     ) -> HashMap<StorePath, Result<()>> {
@@ -251,6 +337,7 @@ impl Pusher {
                 store.clone(),
                 api.clone(),
                 &cache,
+                chunking.as_ref(),
                 mp.clone(),
                 config.force_preamble,
             )
@@ -356,6 +443,8 @@ impl PushSession {
             plan.store_path_map
                 .retain(|sph, _| !known_paths.contains(sph));
 
+            let derivers = plan.derivers();
+
             // Push everything
             for (store_path_hash, path_info) in plan.store_path_map.into_iter() {
                 pusher.queue(path_info).await?;
@@ -364,6 +453,9 @@ impl PushSession {
 
             drop(known_paths);
 
+            pusher.upload_realisations(plan.realisations).await;
+            pusher.upload_logs(derivers).await;
+
             if done {
                 let result = pusher.wait().await;
                 result_sender.send(Ok(result)).await?;
@@ -445,6 +537,7 @@ impl PushPlan {
                 num_all_paths,
                 num_already_cached: 0,
                 num_upstream: 0,
+                realisations: Vec::new(),
             });
         }
 
@@ -474,9 +567,37 @@ impl PushPlan {
                 num_all_paths,
                 num_already_cached: 0,
                 num_upstream: num_all_paths - num_filtered_paths,
+                realisations: Vec::new(),
             });
         }
 
+        // Query realisations
+        //
+        // Only content-addressed paths can have realisations. Failures
+        // aren't fatal since the realisations are only a bonus.
+        let realisations = {
+            let futures = store_path_map
+                .values()
+                .filter(|pi| pi.ca.is_some())
+                .map(|pi| {
+                    let store = store.clone();
+                    let path = pi.path.clone();
+
+                    async move {
+                        store
+                            .query_realisations(path.clone())
+                            .await
+                            .unwrap_or_else(|e| {
+                                tracing::warn!("Could not query realisations of {:?}: {}", path, e);
+                                Vec::new()
+                            })
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            join_all(futures).await.into_iter().flatten().collect()
+        };
+
         // Query missing paths
         let missing_path_hashes: HashSet<StorePathHash> = {
             let store_path_hashes = store_path_map.keys().map(|sph| sph.to_owned()).collect();
@@ -491,8 +612,17 @@ impl PushPlan {
             num_all_paths,
             num_already_cached: num_filtered_paths - num_missing_paths,
             num_upstream: num_all_paths - num_filtered_paths,
+            realisations,
         })
     }
+
+    /// Returns the derivers of the store paths to push.
+    pub fn derivers(&self) -> HashSet<StorePath> {
+        self.store_path_map
+            .values()
+            .filter_map(|pi| pi.deriver.clone())
+            .collect()
+    }
 }
 
 /// Uploads a single path to a cache.
@@ -501,6 +631,7 @@ pub async fn upload_path(
     store: Arc<NixStore>,
     api: ApiClient,
     cache: &CacheName,
+    chunking: Option<&ChunkingParameters>,
     mp: MultiProgress,
     force_preamble: bool,
 ) -> Result<()> {
@@ -527,8 +658,11 @@ pub async fn upload_path(
             store_path_hash: path.to_hash(),
             store_path: full_path,
             references,
-            system: None,  // TODO
-            deriver: None, // TODO
+            system: None, // TODO
+            deriver: path_info
+                .deriver
+                .as_ref()
+                .map(|deriver| deriver.as_os_str().to_string_lossy().into_owned()),
             sigs: path_info.sigs,
             ca: path_info.ca,
             nar_hash: path_info.nar_hash.to_owned(),
@@ -560,14 +694,44 @@ pub async fn upload_path(
         );
     let bar = mp.add(ProgressBar::new(path_info.nar_size));
     bar.set_style(style);
-    let nar_stream = NarStreamProgress::new(store.nar_from_path(path.to_owned()), bar.clone())
-        .map_ok(Bytes::from);
 
     let start = Instant::now();
-    match api
-        .upload_path(upload_info, nar_stream, force_preamble)
-        .await
-    {
+
+    // Only send the chunks that the server doesn't have if possible
+    let chunked_result = match chunking {
+        Some(chunking) if upload_info.nar_size >= chunking.nar_size_threshold => {
+            match upload_path_chunked(upload_info.clone(), path, &store, &api, chunking, &bar).await
+            {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    mp.suspend(|| {
+                        eprintln!(
+                            "⚠️ {}: Chunked upload failed, uploading the entire NAR: {}",
+                            path.as_os_str().to_string_lossy(),
+                            e
+                        );
+                    });
+                    bar.reset();
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let result = match chunked_result {
+        Some(r) => Ok(r),
+        None => {
+            let nar_stream =
+                NarStreamProgress::new(store.nar_from_path(path.to_owned()), bar.clone())
+                    .map_ok(Bytes::from);
+
+            api.upload_path(upload_info, nar_stream, force_preamble)
+                .await
+        }
+    };
+
+    match result {
         Ok(r) => {
             let r = r.unwrap_or(UploadPathResult {
                 kind: UploadPathResultKind::Uploaded,
@@ -615,6 +779,158 @@ pub async fn upload_path(
     }
 }
 
+/// Uploads a path by proving possession of a NAR the server already has.
+///
+/// Returns `None` if the server rejects the proof, in which case the
+/// NAR must be uploaded.
+async fn upload_path_with_proof(
+    nar_info: UploadPathNarInfo,
+    challenge: PossessionChallenge,
+    collector: RangeCollector,
+    api: &ApiClient,
+) -> Result<Option<Option<UploadPathResult>>> {
+    let proof = PossessionProof {
+        response: collector.finish(&challenge.nonce),
+        nonce: challenge.nonce,
+    };
+
+    match api.upload_path_with_proof(nar_info, &proof).await {
+        Ok(r) => Ok(Some(r)),
+        Err(e) => match e.downcast_ref::<ApiError>() {
+            // The server doesn't have the NAR
+            Some(api_error) if api_error.name() == Some("PossessionProofRejected") => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
+/// Uploads a path as a list of chunks, skipping chunks the server already has.
+///
+/// The NAR is chunked locally with the parameters of the server. This
+/// dumps the NAR twice: once to find out which chunks are missing, then
+/// again to send them.
+///
+/// For large NARs, a challenge to prove possession of the NAR is
+/// answered while chunking, so the upload is skipped entirely if the
+/// server already has the NAR.
+async fn upload_path_chunked(
+    nar_info: UploadPathNarInfo,
+    path: &StorePath,
+    store: &Arc<NixStore>,
+    api: &ApiClient,
+    chunking: &ChunkingParameters,
+    bar: &ProgressBar,
+) -> Result<Option<UploadPathResult>> {
+    let challenge = if nar_info.nar_size >= POSSESSION_PROOF_THRESHOLD {
+        api.get_possession_challenge(
+            &nar_info.cache,
+            nar_info.nar_hash.clone(),
+            nar_info.nar_size,
+        )
+        .await?
+    } else {
+        None
+    };
+    let mut collector = challenge
+        .as_ref()
+        .map(|challenge| RangeCollector::new(&challenge.ranges));
+
+    // Chunk the NAR
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    let mut nar_chunks = chunk_nar(store.nar_from_path(path.to_owned()), chunking);
+    while let Some(bytes) = nar_chunks.next().await {
+        let bytes = bytes?;
+        if let Some(collector) = &mut collector {
+            collector.update(offset, &bytes);
+        }
+        offset += bytes.len() as u64;
+
+        chunks.push(NarChunk {
+            hash: Hash::sha256_from_bytes(&bytes),
+            size: bytes.len(),
+            included: false,
+        });
+    }
+
+    // Skip the upload entirely if the server has the NAR
+    if let (Some(challenge), Some(collector)) = (challenge, collector) {
+        if !collector.is_complete() {
+            return Err(anyhow!("The NAR is shorter than expected"));
+        }
+
+        if let Some(r) = upload_path_with_proof(nar_info.clone(), challenge, collector, api).await?
+        {
+            return Ok(r);
+        }
+    }
+
+    // Find out which chunks are missing
+    let mut seen = HashSet::new();
+    let unique_hashes: Vec<Hash> = chunks
+        .iter()
+        .filter(|chunk| seen.insert(chunk.hash.to_typed_base16()))
+        .map(|chunk| chunk.hash.clone())
+        .collect();
+
+    let mut missing = HashSet::new();
+    for batch in unique_hashes.chunks(MISSING_CHUNKS_BATCH_SIZE) {
+        let response = api
+            .get_missing_chunks(&nar_info.cache, batch.to_vec())
+            .await?;
+
+        missing.extend(
+            response
+                .missing_chunk_hashes
+                .iter()
+                .map(Hash::to_typed_base16),
+        );
+    }
+
+    // Only send the first occurrence of each missing chunk
+    for chunk in chunks.iter_mut() {
+        chunk.included = missing.remove(&chunk.hash.to_typed_base16());
+    }
+
+    let included: Vec<(bool, usize)> = chunks
+        .iter()
+        .map(|chunk| (chunk.included, chunk.size))
+        .collect();
+    let nar_stream = NarStreamProgress::new(store.nar_from_path(path.to_owned()), bar.clone());
+    let data = chunk_nar(nar_stream, chunking)
+        .zip(stream::iter(included))
+        .filter_map(|(bytes, (included, size))| async move {
+            match bytes {
+                Ok(bytes) if bytes.len() != size => Some(Err(io::Error::other(
+                    "The NAR changed while being uploaded",
+                ))),
+                Ok(bytes) => included.then_some(Ok(bytes)),
+                Err(e) => Some(Err(e)),
+            }
+        });
+
+    let upload_info = UploadChunkedPathInfo { nar_info, chunks };
+    api.upload_chunked_path(upload_info, data).await
+}
+
+/// Splits a NAR stream into chunks.
+fn chunk_nar<S>(
+    stream: S,
+    chunking: &ChunkingParameters,
+) -> Pin<Box<impl Stream<Item = io::Result<Bytes>>>>
+where
+    S: Stream<Item = AtticResult<Vec<u8>>> + Unpin + Send,
+{
+    let stream = StreamReader::new(stream.map(|r| r.map(Bytes::from).map_err(io::Error::other)));
+
+    Box::pin(chunk_stream(
+        stream,
+        chunking.min_size,
+        chunking.avg_size,
+        chunking.max_size,
+    ))
+}
+
 impl<S: Stream<Item = AtticResult<Vec<u8>>>> NarStreamProgress<S> {
     fn new(stream: S, bar: ProgressBar) -> Self {
         Self { stream, bar }
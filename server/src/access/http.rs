@@ -90,6 +90,39 @@ impl AuthState {
 
         permission
     }
+
+    /// Returns the members of a virtual cache that can be pulled from, in order.
+    ///
+    /// Members that don't exist or use a different store directory are skipped.
+    pub async fn pullable_members(
+        &self,
+        database: &DatabaseConnection,
+        cache: &CacheModel,
+    ) -> ServerResult<Vec<CacheModel>> {
+        if !cache.is_virtual() {
+            return Ok(Vec::new());
+        }
+
+        let member_names = &cache.member_caches.0;
+        let mut members = Vec::new();
+
+        for member in database.find_caches(member_names).await? {
+            let member_name: CacheName = member.name.parse()?;
+            let permission = self.get_permission_for_cache(&member_name, member.is_public);
+
+            if permission.require_pull().is_ok() && member.store_dir == cache.store_dir {
+                members.push(member);
+            }
+        }
+
+        members.sort_by_key(|member| {
+            member_names
+                .iter()
+                .position(|name| name.as_str() == member.name)
+        });
+
+        Ok(members)
+    }
 }
 
 /// Performs auth.
@@ -5,35 +5,47 @@
 //! The implementation is based on the specifications at <https://github.com/fzakaria/nix-http-binary-cache-api-spec>.
 
 use std::collections::VecDeque;
-use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::str::FromStr;
 
+use anyhow::anyhow;
 use axum::{
     body::Body,
     extract::{Extension, Path},
-    http::StatusCode,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Redirect, Response},
     routing::get,
-    Router,
+    Json, Router,
 };
+use chrono::{DateTime, Utc};
 use futures::stream::BoxStream;
 use futures::TryStreamExt as _;
+use sea_orm::entity::prelude::*;
 use serde::Serialize;
 use tokio_util::io::ReaderStream;
 use tracing::instrument;
 
+use crate::buildlog;
+use crate::conditional;
+use crate::database::entity::cache::CacheModel;
 use crate::database::entity::chunk::ChunkModel;
+use crate::database::entity::nar::NarModel;
+use crate::database::entity::narlisting::{self, Entity as NarListing};
+use crate::database::entity::object::ObjectModel;
 use crate::database::AtticDatabase;
-use crate::error::{ErrorKind, ServerResult};
-use crate::narinfo::NarInfo;
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::middleware::set_cache_control_header;
+use crate::nar::{self, RangeRequest};
+use crate::narinfo::Compression;
 use crate::nix_manifest;
-use crate::storage::{Download, StorageBackend};
+use crate::signing::{sign_nar_info, signing_keys};
+use crate::storage::Download;
+use crate::upstream::{self, UpstreamHit};
 use crate::{RequestState, State};
 use attic::cache::CacheName;
 use attic::mime;
 use attic::nix_store::StorePathHash;
-use attic::stream::merge_chunks;
+use attic::realisation::{DrvOutput, Realisation};
 
 /// Nix cache information.
 ///
@@ -62,27 +74,33 @@ struct NixCacheInfo {
     priority: i32,
 }
 
-impl IntoResponse for NixCacheInfo {
-    fn into_response(self) -> Response {
-        match nix_manifest::to_string(&self) {
-            Ok(body) => Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", mime::NIX_CACHE_INFO)
-                .body(body)
-                .unwrap()
-                .into_response(),
-            Err(e) => e.into_response(),
-        }
-    }
+/// An object found in a cache.
+struct FoundObject {
+    object: ObjectModel,
+
+    /// The cache containing the object.
+    cache: CacheModel,
+
+    /// The virtual cache through which the object was found.
+    virtual_cache: Option<CacheModel>,
+
+    nar: NarModel,
+    chunks: Vec<Option<ChunkModel>>,
 }
 
 /// Gets information on a cache.
+///
+/// The priority of a virtual cache is the highest priority among
+/// the cache itself and the members that the client can pull from.
+/// Since it depends on the client, the response may not be stored
+/// by shared caches.
 #[instrument(skip_all, fields(cache_name))]
 async fn get_nix_cache_info(
     Extension(state): Extension<State>,
     Extension(req_state): Extension<RequestState>,
     Path(cache_name): Path<CacheName>,
-) -> ServerResult<NixCacheInfo> {
+    headers: HeaderMap,
+) -> ServerResult<Response> {
     let database = state.database().await?;
     let cache = req_state
         .auth
@@ -94,13 +112,29 @@ async fn get_nix_cache_info(
 
     req_state.set_public_cache(cache.is_public);
 
+    let is_virtual = cache.is_virtual();
+    let mut priority = cache.priority;
+    for member in req_state.auth.pullable_members(database, &cache).await? {
+        priority = priority.min(member.priority);
+    }
+
     let info = NixCacheInfo {
         want_mass_query: true,
         store_dir: cache.store_dir.into(),
-        priority: cache.priority,
+        priority,
     };
 
-    Ok(info)
+    let body = nix_manifest::to_string(&info)?;
+    let mut response = manifest_response(&headers, mime::NIX_CACHE_INFO, body, None);
+
+    if is_virtual {
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("private, no-cache"),
+        );
+    }
+
+    Ok(response)
 }
 
 /// Gets various information on a store path hash.
@@ -108,68 +142,133 @@ async fn get_nix_cache_info(
 /// `/:cache/:path`, which may be one of
 /// - GET `/:cache/{storePathHash}.narinfo`
 /// - HEAD `/:cache/{storePathHash}.narinfo`
-/// - GET `/:cache/{storePathHash}.ls` (not implemented)
+/// - GET `/:cache/{storePathHash}.ls`
 #[instrument(skip_all, fields(cache_name, path))]
 #[axum_macros::debug_handler]
 async fn get_store_path_info(
     Extension(state): Extension<State>,
     Extension(req_state): Extension<RequestState>,
     Path((cache_name, path)): Path<(CacheName, String)>,
-) -> ServerResult<NarInfo> {
+    headers: HeaderMap,
+) -> ServerResult<Response> {
     let components: Vec<&str> = path.splitn(2, '.').collect();
 
     if components.len() != 2 {
         return Err(ErrorKind::NotFound.into());
     }
 
-    // TODO: Other endpoints
-    if components[1] != "narinfo" {
-        return Err(ErrorKind::NotFound.into());
+    match components[1] {
+        "narinfo" => {
+            let store_path_hash = StorePathHash::new(components[0].to_string())?;
+            get_nar_info(state, req_state, cache_name, store_path_hash, &headers).await
+        }
+        "ls" => {
+            let store_path_hash = StorePathHash::new(components[0].to_string())?;
+            get_nar_listing(state, req_state, cache_name, store_path_hash).await
+        }
+        _ => Err(ErrorKind::NotFound.into()),
     }
+}
 
-    let store_path_hash = StorePathHash::new(components[0].to_string())?;
-
+/// Gets the `.narinfo` of a store path hash.
+async fn get_nar_info(
+    state: State,
+    req_state: RequestState,
+    cache_name: CacheName,
+    store_path_hash: StorePathHash,
+    headers: &HeaderMap,
+) -> ServerResult<Response> {
     tracing::debug!(
         "Received request for {}.narinfo in {:?}",
         store_path_hash.as_str(),
         cache_name
     );
 
-    let (object, cache, nar, _) = state
-        .database()
-        .await?
-        .find_object_and_chunks_by_store_path_hash(&cache_name, &store_path_hash, false)
-        .await?;
+    let found = find_object(&state, &req_state, &cache_name, &store_path_hash, false).await;
 
-    let permission = req_state
-        .auth
-        .get_permission_for_cache(&cache_name, cache.is_public);
-    permission.require_pull()?;
+    let found = match found {
+        Err(e) if matches!(e.kind(), ErrorKind::NoSuchObject) => {
+            let (_, hit) =
+                find_in_upstreams(&state, &req_state, &cache_name, &store_path_hash, e).await?;
+            let body = nix_manifest::to_string(&hit.to_nar_info())?;
+            return Ok(manifest_response(headers, mime::NARINFO, body, None));
+        }
+        found => found?,
+    };
 
-    req_state.set_public_cache(cache.is_public);
+    let mut narinfo = found.object.to_nar_info(&found.nar)?;
 
-    let mut narinfo = object.to_nar_info(&nar)?;
+    sign_nar_info(&signing_keys(&state, &found.cache)?, &mut narinfo).await?;
 
-    if narinfo.signature().is_none() {
-        let keypair = cache.keypair()?;
-        narinfo.sign(&keypair);
+    if let Some(virtual_cache) = &found.virtual_cache {
+        sign_nar_info(&signing_keys(&state, virtual_cache)?, &mut narinfo).await?;
     }
 
-    Ok(narinfo)
+    let body = nix_manifest::to_string(&narinfo)?;
+    let last_modified = found.object.created_at;
+
+    Ok(manifest_response(
+        headers,
+        mime::NARINFO,
+        body,
+        Some(&last_modified),
+    ))
+}
+
+/// Gets the file listing of a store path hash.
+///
+/// Listings are built when NARs are uploaded, so NARs uploaded
+/// by older versions of the server don't have them.
+async fn get_nar_listing(
+    state: State,
+    req_state: RequestState,
+    cache_name: CacheName,
+    store_path_hash: StorePathHash,
+) -> ServerResult<Response> {
+    tracing::debug!(
+        "Received request for {}.ls in {:?}",
+        store_path_hash.as_str(),
+        cache_name
+    );
+
+    let database = state.database().await?;
+
+    let found = find_object(&state, &req_state, &cache_name, &store_path_hash, false).await?;
+
+    let listing = NarListing::find()
+        .filter(narlisting::Column::NarId.eq(found.nar.id))
+        .one(database)
+        .await
+        .map_err(ServerError::database_error)?
+        .ok_or(ErrorKind::NotFound)?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", mime::NAR_LISTING)
+        .body(Body::from(listing.listing))
+        .unwrap())
 }
 
 /// Gets a NAR.
 ///
 /// - GET `:cache/nar/{storePathHash}.nar`
+/// - GET `:cache/nar/{storePathHash}.upstream.nar`
 ///
 /// Here we use the store path hash not the NAR hash or file hash
 /// for better logging. In reality, the files are deduplicated by
 /// content-addressing.
+///
+/// NARs in upstream caches are served under a separate URL, since
+/// they may be compressed differently from the NAR we ingest.
+///
+/// Single byte ranges are supported if the size of the file is known.
+/// Only the chunks overlapping with the requested range are fetched.
 #[instrument(skip_all, fields(cache_name, path))]
 async fn get_nar(
     Extension(state): Extension<State>,
     Extension(req_state): Extension<RequestState>,
     Path((cache_name, path)): Path<(CacheName, String)>,
+    headers: HeaderMap,
 ) -> ServerResult<Response> {
     let components: Vec<&str> = path.splitn(2, '.').collect();
 
@@ -177,12 +276,16 @@ async fn get_nar(
         return Err(ErrorKind::NotFound.into());
     }
 
-    if components[1] != "nar" {
-        return Err(ErrorKind::NotFound.into());
-    }
-
     let store_path_hash = StorePathHash::new(components[0].to_string())?;
 
+    match components[1] {
+        "nar" => {}
+        "upstream.nar" => {
+            return get_upstream_nar(state, req_state, cache_name, store_path_hash).await;
+        }
+        _ => return Err(ErrorKind::NotFound.into()),
+    }
+
     tracing::debug!(
         "Received request for {}.nar in {:?}",
         store_path_hash.as_str(),
@@ -191,16 +294,14 @@ async fn get_nar(
 
     let database = state.database().await?;
 
-    let (object, cache, _nar, chunks) = database
-        .find_object_and_chunks_by_store_path_hash(&cache_name, &store_path_hash, true)
-        .await?;
+    let found = find_object(&state, &req_state, &cache_name, &store_path_hash, true).await;
 
-    let permission = req_state
-        .auth
-        .get_permission_for_cache(&cache_name, cache.is_public);
-    permission.require_pull()?;
-
-    req_state.set_public_cache(cache.is_public);
+    let FoundObject {
+        object,
+        nar,
+        chunks,
+        ..
+    } = found?;
 
     if chunks.iter().any(Option::is_none) {
         // at least one of the chunks is missing :(
@@ -209,59 +310,476 @@ async fn get_nar(
 
     database.bump_object_last_accessed(object.id).await?;
 
-    if chunks.len() == 1 {
+    let chunks: VecDeque<_> = chunks.into_iter().map(Option::unwrap).collect();
+    let file_size = nar::file_size(&chunks);
+    let etag = conditional::nar_etag(&nar);
+    let last_modified = conditional::http_date(&nar.created_at);
+
+    if conditional::if_none_match(&headers, &etag) {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, etag)
+            .header(header::LAST_MODIFIED, last_modified)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let range = match file_size {
+        Some(file_size) => requested_range(&headers, &etag, file_size),
+        None => RangeRequest::Full,
+    };
+
+    if range == RangeRequest::Unsatisfiable {
+        return Ok(Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes */{}", file_size.unwrap()),
+            )
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let stream: BoxStream<_> = if chunks.len() == 1 {
         // single chunk
-        let chunk = chunks[0].as_ref().unwrap();
+        let chunk = &chunks[0];
         let remote_file = &chunk.remote_file.0;
         let storage = state.storage().await?;
         match storage.download_file_db(remote_file, false).await? {
-            Download::Url(url) => Ok(Redirect::temporary(&url).into_response()),
+            // the client will send the same range to the storage backend
+            Download::Url(url) => return Ok(Redirect::temporary(&url).into_response()),
             Download::AsyncRead(stream) => {
-                let stream = ReaderStream::new(stream).map_err(|e| {
-                    tracing::error!(%e, "Stream error");
-                    e
-                });
-                let body = Body::from_stream(stream);
-
-                Ok(body.into_response())
+                let stream = ReaderStream::new(stream);
+                match range {
+                    RangeRequest::Partial(range) => {
+                        Box::pin(nar::slice_stream(stream, range.start, range.len()))
+                    }
+                    _ => Box::pin(stream),
+                }
             }
         }
     } else {
         // reassemble NAR
-        fn io_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> IoError {
-            IoError::new(IoErrorKind::Other, e)
-        }
+        let storage = state.storage().await?.clone();
 
-        let streamer = |chunk: ChunkModel, storage: Arc<Box<dyn StorageBackend + 'static>>| async move {
-            match storage
-                .download_file_db(&chunk.remote_file.0, true)
-                .await
-                .map_err(io_error)?
-            {
-                Download::Url(_) => Err(IoError::new(
-                    IoErrorKind::Other,
-                    "URLs not supported for NAR reassembly",
-                )),
-                Download::AsyncRead(stream) => {
-                    let stream: BoxStream<_> = Box::pin(ReaderStream::new(stream));
-                    Ok(stream)
+        match range {
+            RangeRequest::Partial(range) => Box::pin(nar::stream_nar_range(storage, chunks, range)),
+            _ => {
+                let merged = Box::pin(nar::stream_nar(storage, chunks));
+                if nar.file_hash.is_none() {
+                    Box::pin(nar::record_file_hash(state.clone(), nar.id, merged))
+                } else {
+                    merged
                 }
             }
+        }
+    };
+
+    let stream = stream.map_err(|e| {
+        tracing::error!(%e, "Stream error");
+        e
+    });
+
+    let mut response = Response::builder()
+        .header(header::ETAG, etag)
+        .header(header::LAST_MODIFIED, last_modified);
+
+    if let Some(file_size) = file_size {
+        response = response.header(header::ACCEPT_RANGES, "bytes");
+
+        response = match range {
+            RangeRequest::Partial(range) => response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, range.content_range(file_size))
+                .header(header::CONTENT_LENGTH, range.len()),
+            _ => response.header(header::CONTENT_LENGTH, file_size),
         };
+    }
 
-        let chunks: VecDeque<_> = chunks.into_iter().map(Option::unwrap).collect();
-        let storage = state.storage().await?.clone();
+    Ok(response.body(Body::from_stream(stream)).unwrap())
+}
+
+/// Streams a NAR from the upstream caches of a cache.
+///
+/// If the cache ingests from upstreams, the NAR is also uploaded
+/// to the cache in the background.
+async fn get_upstream_nar(
+    state: State,
+    req_state: RequestState,
+    cache_name: CacheName,
+    store_path_hash: StorePathHash,
+) -> ServerResult<Response> {
+    let error = ErrorKind::NoSuchObject.into();
+    let (cache, hit) =
+        find_in_upstreams(&state, &req_state, &cache_name, &store_path_hash, error).await?;
 
-        // TODO: Make num_prefetch configurable
-        // The ideal size depends on the average chunk size
-        let merged = merge_chunks(chunks, streamer, storage, 2).map_err(|e| {
-            tracing::error!(%e, "Stream error");
-            e
-        });
-        let body = Body::from_stream(merged);
+    let (stream, file_size) = state.upstreams.stream_nar(&hit).await?;
 
-        Ok(body.into_response())
+    if cache.ingest_upstream {
+        upstream::spawn_ingest(state.clone(), cache, hit);
     }
+
+    let mut response = Response::builder();
+    if let Some(file_size) = file_size {
+        response = response.header(header::CONTENT_LENGTH, file_size);
+    }
+
+    Ok(response.body(Body::from_stream(stream)).unwrap())
+}
+
+/// Finds an object in a cache and checks for pull permission.
+///
+/// If the object doesn't exist in the cache itself, the members
+/// of the cache are searched in order. Members aren't expanded
+/// recursively.
+async fn find_object(
+    state: &State,
+    req_state: &RequestState,
+    cache_name: &CacheName,
+    store_path_hash: &StorePathHash,
+    include_chunks: bool,
+) -> ServerResult<FoundObject> {
+    let database = state.database().await?;
+
+    let error = match database
+        .find_object_and_chunks_by_store_path_hash(cache_name, store_path_hash, include_chunks)
+        .await
+    {
+        Ok((object, cache, nar, chunks)) => {
+            let permission = req_state
+                .auth
+                .get_permission_for_cache(cache_name, cache.is_public);
+            permission.require_pull()?;
+
+            req_state.set_public_cache(cache.is_public);
+
+            return Ok(FoundObject {
+                object,
+                cache,
+                virtual_cache: None,
+                nar,
+                chunks,
+            });
+        }
+        Err(e) if matches!(e.kind(), ErrorKind::NoSuchObject) => e,
+        Err(e) => return Err(e),
+    };
+
+    let cache = req_state
+        .auth
+        .auth_cache(database, cache_name, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    req_state.set_public_cache(cache.is_public);
+
+    for member in req_state.auth.pullable_members(database, &cache).await? {
+        let member_name: CacheName = member.name.parse()?;
+
+        match database
+            .find_object_and_chunks_by_store_path_hash(
+                &member_name,
+                store_path_hash,
+                include_chunks,
+            )
+            .await
+        {
+            Ok((object, member, nar, chunks)) => {
+                req_state.set_public_cache(cache.is_public && member.is_public);
+
+                return Ok(FoundObject {
+                    object,
+                    cache: member,
+                    virtual_cache: Some(cache),
+                    nar,
+                    chunks,
+                });
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::NoSuchObject) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(error)
+}
+
+/// Finds a store path in the upstream caches of a cache.
+///
+/// The original error is returned if none of the upstreams has it.
+async fn find_in_upstreams(
+    state: &State,
+    req_state: &RequestState,
+    cache_name: &CacheName,
+    store_path_hash: &StorePathHash,
+    error: ServerError,
+) -> ServerResult<(CacheModel, UpstreamHit)> {
+    let cache = state.database().await?.find_cache(cache_name).await?;
+
+    let permission = req_state
+        .auth
+        .get_permission_for_cache(cache_name, cache.is_public);
+    permission.require_pull()?;
+
+    req_state.set_public_cache(cache.is_public);
+
+    if cache.upstream_urls.0.is_empty() {
+        return Err(error);
+    }
+
+    match state
+        .upstreams
+        .find_nar_info(&state.config.allowed_upstreams, &cache, store_path_hash)
+        .await?
+    {
+        Some(hit) => Ok((cache, hit)),
+        None => Err(error),
+    }
+}
+
+/// Returns a manifest with validators.
+///
+/// `304 Not Modified` is returned if `If-None-Match` matches.
+fn manifest_response(
+    headers: &HeaderMap,
+    content_type: &'static str,
+    body: String,
+    last_modified: Option<&DateTime<Utc>>,
+) -> Response {
+    let etag = conditional::body_etag(body.as_bytes());
+    let mut response = Response::builder().header(header::ETAG, &etag);
+
+    if let Some(last_modified) = last_modified {
+        response = response.header(header::LAST_MODIFIED, conditional::http_date(last_modified));
+    }
+
+    if conditional::if_none_match(headers, &etag) {
+        return response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    response
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// Evaluates the `Range` and `If-Range` headers of a NAR request.
+///
+/// The range is only honored if `If-Range` is absent or matches the
+/// entity tag of the file. `Last-Modified` is a weak validator, so
+/// dates in `If-Range` never match.
+fn requested_range(headers: &HeaderMap, etag: &str, file_size: u64) -> RangeRequest {
+    let Some(range) = headers.get(header::RANGE) else {
+        return RangeRequest::Full;
+    };
+
+    let Ok(range) = range.to_str() else {
+        return RangeRequest::Full;
+    };
+
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        // weak entity tags never match in If-Range
+        let matches = match if_range.to_str() {
+            Ok(if_range) => if_range.trim() == etag,
+            Err(_) => false,
+        };
+
+        if !matches {
+            return RangeRequest::Full;
+        }
+    }
+
+    nar::parse_range(range, file_size)
+}
+
+/// Gets a realisation.
+///
+/// - GET `:cache/realisations/{drvOutput}.doi`
+///
+/// The realisation is signed with the keys of the cache in addition
+/// to the signatures supplied by the uploader.
+#[instrument(skip_all, fields(cache_name, path))]
+async fn get_realisation(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path((cache_name, path)): Path<(CacheName, String)>,
+) -> ServerResult<Response> {
+    let drv_output = parse_realisation_path(&path)?;
+
+    tracing::debug!(
+        "Received request for realisation {} in {:?}",
+        drv_output,
+        cache_name
+    );
+
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    req_state.set_public_cache(cache.is_public);
+
+    let mut realisation = database
+        .find_realisation(cache.id, &drv_output)
+        .await?
+        .to_realisation()?;
+
+    let fingerprint = realisation.fingerprint();
+    for key in signing_keys(&state, &cache)? {
+        let signature = key.sign(&fingerprint).await?;
+        realisation.merge_signatures(&[signature]);
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", mime::REALISATION)
+        .body(Body::from(realisation.to_json()))
+        .unwrap())
+}
+
+/// Uploads a realisation.
+///
+/// - PUT `:cache/realisations/{drvOutput}.doi`
+///
+/// This allows Nix to upload realisations directly with `nix copy`.
+#[instrument(skip_all, fields(cache_name, path))]
+async fn put_realisation(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path((cache_name, path)): Path<(CacheName, String)>,
+    Json(realisation): Json<Realisation>,
+) -> ServerResult<()> {
+    let drv_output = parse_realisation_path(&path)?;
+
+    if drv_output != realisation.id {
+        return Err(
+            ErrorKind::RequestError(anyhow!("Realisation ID doesn't match the path")).into(),
+        );
+    }
+
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_push()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let username = req_state.auth.username().map(str::to_string);
+
+    database
+        .upsert_realisation(cache.id, realisation, username)
+        .await
+}
+
+/// Gets a build log.
+///
+/// - GET `:cache/log/{drvPath}`
+///
+/// The log is served as-is with `Content-Encoding` if it's stored
+/// with a content coding the client accepts. Otherwise, it's
+/// decompressed on the fly.
+#[instrument(skip_all, fields(cache_name, path))]
+async fn get_build_log(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path((cache_name, path)): Path<(CacheName, String)>,
+    headers: HeaderMap,
+) -> ServerResult<Response> {
+    let drv_path = buildlog::parse_drv_path(&path)?;
+
+    tracing::debug!(
+        "Received request for log of {} in {:?}",
+        drv_path,
+        cache_name
+    );
+
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    req_state.set_public_cache(cache.is_public);
+
+    let log = database.find_build_log(cache.id, &drv_path).await?;
+    let compression = Compression::from_str(&log.compression)?;
+    let stream = buildlog::open_log(&state, &log).await?;
+
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, mime::BUILD_LOG)
+        .header(header::VARY, "Accept-Encoding");
+
+    let stream = if compression == Compression::None {
+        response = response.header(header::CONTENT_LENGTH, log.file_size);
+        stream
+    } else if let Some(encoding) = buildlog::content_encoding(&compression)
+        .filter(|encoding| buildlog::accepts_encoding(&headers, encoding))
+    {
+        response = response
+            .header(header::CONTENT_ENCODING, encoding)
+            .header(header::CONTENT_LENGTH, log.file_size);
+        stream
+    } else {
+        compression.decompress(stream)?
+    };
+
+    let stream = ReaderStream::new(stream).map_err(|e| {
+        tracing::error!(%e, "Stream error");
+        e
+    });
+
+    Ok(response.body(Body::from_stream(stream)).unwrap())
+}
+
+/// Uploads a build log.
+///
+/// - PUT `:cache/log/{drvPath}`
+///
+/// This allows Nix to upload build logs directly with `nix store copy-log`.
+#[instrument(skip_all, fields(cache_name, path))]
+async fn put_build_log(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path((cache_name, path)): Path<(CacheName, String)>,
+    headers: HeaderMap,
+    body: Body,
+) -> ServerResult<()> {
+    let drv_path = buildlog::parse_drv_path(&path)?;
+    let stream = buildlog::request_body(&headers, body)?;
+
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_push()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let username = req_state.auth.username().map(str::to_string);
+
+    buildlog::upload_log(&state, cache.id, drv_path, stream, username).await
+}
+
+/// Parses the derivation output from `{drvOutput}.doi`.
+fn parse_realisation_path(path: &str) -> ServerResult<DrvOutput> {
+    let drv_output = path.strip_suffix(".doi").ok_or(ErrorKind::NotFound)?;
+    drv_output.parse().map_err(ServerError::request_error)
 }
 
 pub fn get_router() -> Router {
@@ -269,4 +787,10 @@ pub fn get_router() -> Router {
         .route("/:cache/nix-cache-info", get(get_nix_cache_info))
         .route("/:cache/:path", get(get_store_path_info))
         .route("/:cache/nar/:path", get(get_nar))
+        .route(
+            "/:cache/realisations/:path",
+            get(get_realisation).put(put_realisation),
+        )
+        .route("/:cache/log/:path", get(get_build_log).put(put_build_log))
+        .route_layer(axum::middleware::from_fn(set_cache_control_header))
 }
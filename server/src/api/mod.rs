@@ -1,7 +1,7 @@
 //! HTTP API.
 
 mod binary_cache;
-mod v1;
+pub(crate) mod v1;
 
 use axum::{response::Html, routing::get, Router};
 
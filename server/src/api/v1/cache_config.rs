@@ -1,5 +1,8 @@
 //! Cache configuration endpoint.
 
+use std::collections::HashSet;
+use std::iter;
+
 use anyhow::anyhow;
 use axum::extract::{Extension, Json, Path};
 use chrono::Utc;
@@ -8,15 +11,22 @@ use sea_orm::ActiveValue::Set;
 use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
 use tracing::instrument;
 
-use crate::database::entity::cache::{self, Entity as Cache};
+use crate::config::Config;
+use crate::database::entity::buildlog::{self, Entity as BuildLog};
+use crate::database::entity::cache::{self, CacheModel, Entity as Cache, PreviousKeypair};
 use crate::database::entity::Json as DbJson;
 use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::signing::get_signer;
+use crate::upstream::parse_upstream_url;
+use crate::webhook::validate_webhooks;
 use crate::{RequestState, State};
 use attic::api::v1::cache_config::{
-    CacheConfig, CreateCacheRequest, KeypairConfig, RetentionPeriodConfig,
+    CacheConfig, ChunkingParameters, CreateCacheRequest, KeypairConfig, KeypairState, QuotaConfig,
+    RetentionPeriodConfig, WebhookConfig,
 };
+use attic::api::v1::webhook::WebhookEventData;
 use attic::cache::CacheName;
-use attic::signing::NixKeypair;
+use attic::signing::{NixKeypair, NixPublicKey};
 
 #[instrument(skip_all, fields(cache_name))]
 pub(crate) async fn get_cache_config(
@@ -25,15 +35,16 @@ pub(crate) async fn get_cache_config(
     Path(cache_name): Path<CacheName>,
 ) -> ServerResult<Json<CacheConfig>> {
     let database = state.database().await?;
-    let cache = req_state
+    let (cache, can_configure) = req_state
         .auth
         .auth_cache(database, &cache_name, |cache, permission| {
             permission.require_pull()?;
-            Ok(cache)
+            Ok((cache, permission.configure_cache))
         })
         .await?;
 
-    let public_key = cache.keypair()?.export_public_key();
+    let public_key = cache.public_key()?;
+    let public_keys = cache.public_keys()?;
 
     let retention_period_config = if let Some(period) = cache.retention_period {
         RetentionPeriodConfig::Period(period as u32)
@@ -46,11 +57,46 @@ pub(crate) async fn get_cache_config(
         api_endpoint: Some(req_state.api_endpoint()?),
         keypair: None,
         public_key: Some(public_key),
+        public_keys: Some(public_keys),
+        revoke_public_keys: None,
         is_public: Some(cache.is_public),
         store_dir: Some(cache.store_dir),
         priority: Some(cache.priority),
         upstream_cache_key_names: Some(cache.upstream_cache_key_names.0),
+        // The URLs may contain credentials
+        upstream_urls: if can_configure {
+            Some(cache.upstream_urls.0)
+        } else {
+            None
+        },
+        ingest_upstream: Some(cache.ingest_upstream),
+        // The names of private caches shouldn't be disclosed
+        member_caches: if can_configure {
+            Some(cache.member_caches.0)
+        } else {
+            None
+        },
         retention_period: Some(retention_period_config),
+        quota_bytes: Some(quota_config(cache.quota_bytes)),
+        quota_objects: Some(quota_config(cache.quota_objects)),
+        validate_nars: Some(cache.validate_nars),
+        chunking: chunking_parameters(&state.config),
+        // The secrets are never returned
+        webhooks: if can_configure {
+            Some(
+                cache
+                    .webhooks
+                    .0
+                    .into_iter()
+                    .map(|webhook| WebhookConfig {
+                        secret: None,
+                        ..webhook
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        },
     }))
 }
 
@@ -77,15 +123,29 @@ pub(crate) async fn configure_cache(
 
     let mut modified = false;
 
+    let mut public_key = cache.public_key()?;
+    let mut previous_keypairs = cache.previous_keypairs.0.clone();
+
     if let Some(keypair_cfg) = payload.keypair {
-        let keypair = match keypair_cfg {
-            KeypairConfig::Generate => NixKeypair::generate(cache_name.as_str())?,
-            KeypairConfig::Keypair(k) => k,
-        };
-        update.keypair = Set(keypair.export_keypair());
+        let name = next_keypair_name(&cache_name, &public_key, &previous_keypairs);
+        let new_keypair = new_keypair(&state.config, &cache_name, keypair_cfg, &name)?;
+
+        retire_keypair(&mut previous_keypairs, &cache, &new_keypair.public_key)?;
+        update.keypair = Set(new_keypair.keypair);
+        update.signer = Set(new_keypair.signer);
+        public_key = new_keypair.public_key;
+        modified = true;
+    }
+
+    if let Some(public_keys) = payload.revoke_public_keys {
+        revoke_keypairs(&mut previous_keypairs, &public_key, &public_keys)?;
         modified = true;
     }
 
+    if previous_keypairs != cache.previous_keypairs.0 {
+        update.previous_keypairs = Set(DbJson(previous_keypairs));
+    }
+
     if let Some(is_public) = payload.is_public {
         update.is_public = Set(is_public);
         modified = true;
@@ -106,6 +166,23 @@ pub(crate) async fn configure_cache(
         modified = true;
     }
 
+    if let Some(upstream_urls) = payload.upstream_urls {
+        validate_upstream_urls(&state.config, &upstream_urls)?;
+        update.upstream_urls = Set(DbJson(upstream_urls));
+        modified = true;
+    }
+
+    if let Some(ingest_upstream) = payload.ingest_upstream {
+        update.ingest_upstream = Set(ingest_upstream);
+        modified = true;
+    }
+
+    if let Some(member_caches) = payload.member_caches {
+        validate_member_caches(&cache_name, &member_caches)?;
+        update.member_caches = Set(DbJson(member_caches));
+        modified = true;
+    }
+
     if let Some(retention_period_config) = payload.retention_period {
         permission.require_configure_cache_retention()?;
 
@@ -124,12 +201,48 @@ pub(crate) async fn configure_cache(
         modified = true;
     }
 
+    if let Some(quota) = payload.quota_bytes {
+        permission.require_configure_cache_retention()?;
+        update.quota_bytes = Set(quota_limit(quota)?);
+        modified = true;
+    }
+
+    if let Some(quota) = payload.quota_objects {
+        permission.require_configure_cache_retention()?;
+        update.quota_objects = Set(quota_limit(quota)?);
+        modified = true;
+    }
+
+    if let Some(validate_nars) = payload.validate_nars {
+        update.validate_nars = Set(validate_nars);
+        modified = true;
+    }
+
+    if let Some(webhooks) = payload.webhooks {
+        validate_webhooks(&webhooks, state.config.allow_private_webhooks)?;
+        update.webhooks = Set(DbJson(webhooks));
+        modified = true;
+    }
+
     if modified {
-        Cache::update(update)
+        let cache = Cache::update(update)
             .exec(database)
             .await
             .map_err(ServerError::database_error)?;
 
+        state
+            .webhooks
+            .notify(
+                database,
+                cache.id,
+                &cache_name,
+                &cache.webhooks.0,
+                WebhookEventData::CacheConfigured {
+                    configured_by: req_state.auth.username().map(str::to_string),
+                },
+            )
+            .await;
+
         Ok(())
     } else {
         Err(ErrorKind::RequestError(anyhow!("No modifiable fields were set.")).into())
@@ -163,12 +276,19 @@ pub(crate) async fn destroy_cache(
 
         if deletion.rows_affected == 0 {
             // Someone raced to (soft) delete the cache before us
-            Err(ErrorKind::NoSuchCache.into())
-        } else {
-            Ok(())
+            return Err(ErrorKind::NoSuchCache.into());
         }
     } else {
         // Perform hard deletion
+        //
+        // Build logs are removed with the cache, so we need to
+        // collect their files beforehand.
+        let build_logs = BuildLog::find()
+            .filter(buildlog::Column::CacheId.eq(cache.id))
+            .all(database)
+            .await
+            .map_err(ServerError::database_error)?;
+
         let deletion = Cache::delete_many()
             .filter(cache::Column::Id.eq(cache.id))
             .filter(cache::Column::DeletedAt.is_null()) // don't operate on soft-deleted caches
@@ -178,11 +298,31 @@ pub(crate) async fn destroy_cache(
 
         if deletion.rows_affected == 0 {
             // Someone raced to (soft) delete the cache before us
-            Err(ErrorKind::NoSuchCache.into())
-        } else {
-            Ok(())
+            return Err(ErrorKind::NoSuchCache.into());
+        }
+
+        let storage = state.storage().await?;
+        for log in build_logs {
+            if let Err(e) = storage.delete_file_db(&log.remote_file.0).await {
+                tracing::warn!("Failed to delete build log {}: {}", log.drv_path, e);
+            }
         }
     }
+
+    state
+        .webhooks
+        .notify(
+            database,
+            cache.id,
+            &cache_name,
+            &cache.webhooks.0,
+            WebhookEventData::CacheDestroyed {
+                destroyed_by: req_state.auth.username().map(str::to_string),
+            },
+        )
+        .await;
+
+    Ok(())
 }
 
 #[instrument(skip_all, fields(cache_name, payload))]
@@ -197,18 +337,27 @@ pub(crate) async fn create_cache(
 
     let database = state.database().await?;
 
-    let keypair = match payload.keypair {
-        KeypairConfig::Generate => NixKeypair::generate(cache_name.as_str())?,
-        KeypairConfig::Keypair(k) => k,
-    };
+    let keypair = new_keypair(
+        &state.config,
+        &cache_name,
+        payload.keypair,
+        cache_name.as_str(),
+    )?;
+
+    validate_upstream_urls(&state.config, &payload.upstream_urls)?;
+    validate_member_caches(&cache_name, &payload.member_caches)?;
 
     let num_inserted = Cache::insert(cache::ActiveModel {
         name: Set(cache_name.to_string()),
-        keypair: Set(keypair.export_keypair()),
+        keypair: Set(keypair.keypair),
+        signer: Set(keypair.signer),
         is_public: Set(payload.is_public),
         store_dir: Set(payload.store_dir),
         priority: Set(payload.priority),
         upstream_cache_key_names: Set(DbJson(payload.upstream_cache_key_names)),
+        upstream_urls: Set(DbJson(payload.upstream_urls)),
+        ingest_upstream: Set(payload.ingest_upstream),
+        member_caches: Set(DbJson(payload.member_caches)),
         created_at: Set(Utc::now()),
         ..Default::default()
     })
@@ -228,3 +377,213 @@ pub(crate) async fn create_cache(
         Ok(())
     }
 }
+
+/// Returns the chunking parameters advertised to clients.
+fn chunking_parameters(config: &Config) -> Option<ChunkingParameters> {
+    let chunking = &config.chunking;
+
+    if chunking.nar_size_threshold == 0 {
+        return None;
+    }
+
+    Some(ChunkingParameters {
+        nar_size_threshold: chunking.nar_size_threshold,
+        min_size: chunking.min_size,
+        avg_size: chunking.avg_size,
+        max_size: chunking.max_size,
+    })
+}
+
+/// Returns the configuration of a quota.
+fn quota_config(quota: Option<i64>) -> QuotaConfig {
+    match quota {
+        Some(limit) => QuotaConfig::Limit(limit as u64),
+        None => QuotaConfig::Unlimited,
+    }
+}
+
+/// Returns the value of a quota column.
+fn quota_limit(quota: QuotaConfig) -> ServerResult<Option<i64>> {
+    match quota {
+        QuotaConfig::Unlimited => Ok(None),
+        QuotaConfig::Limit(limit) => {
+            Ok(Some(limit.try_into().map_err(|_| {
+                ErrorKind::RequestError(anyhow!("Invalid quota"))
+            })?))
+        }
+    }
+}
+
+fn validate_upstream_urls(config: &Config, upstream_urls: &[String]) -> ServerResult<()> {
+    for url in upstream_urls {
+        parse_upstream_url(url, &config.allowed_upstreams)?;
+    }
+
+    Ok(())
+}
+
+fn validate_member_caches(cache_name: &CacheName, member_caches: &[CacheName]) -> ServerResult<()> {
+    if member_caches.contains(cache_name) {
+        return Err(
+            ErrorKind::RequestError(anyhow!("A cache cannot be a member of itself")).into(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Sets up a new keypair.
+///
+/// `name` is the name of the keypair if one is generated.
+fn new_keypair(
+    config: &Config,
+    cache_name: &CacheName,
+    keypair_cfg: KeypairConfig,
+    name: &str,
+) -> ServerResult<NewKeypair> {
+    let local = |keypair: NixKeypair| NewKeypair {
+        public_key: keypair.export_public_key(),
+        keypair: keypair.export_keypair(),
+        signer: None,
+    };
+
+    match keypair_cfg {
+        KeypairConfig::Generate => Ok(local(NixKeypair::generate(name)?)),
+        KeypairConfig::Keypair(k) => Ok(local(k)),
+        KeypairConfig::External(signer) => {
+            let public_key = NixPublicKey::from_str(
+                get_signer(&config.signers, &signer, cache_name)?.public_key(),
+            )?;
+
+            Ok(NewKeypair {
+                public_key: public_key.export(),
+                keypair: public_key.export(),
+                signer: Some(signer),
+            })
+        }
+    }
+}
+
+/// Returns an unused name for a new keypair of a cache.
+///
+/// Nix identifies trusted public keys by their names, so a new
+/// keypair cannot reuse the name of a previous keypair.
+fn next_keypair_name(
+    cache_name: &CacheName,
+    current: &str,
+    previous_keypairs: &[PreviousKeypair],
+) -> String {
+    let used: HashSet<&str> = iter::once(current)
+        .chain(previous_keypairs.iter().map(|p| p.public_key.as_str()))
+        .map(key_name)
+        .collect();
+
+    iter::once(cache_name.to_string())
+        .chain((2..).map(|n| format!("{}-{}", cache_name.as_str(), n)))
+        .find(|name| !used.contains(name.as_str()))
+        .unwrap()
+}
+
+/// Replaces the current keypair, keeping it as a retiring keypair.
+fn retire_keypair(
+    previous_keypairs: &mut Vec<PreviousKeypair>,
+    cache: &CacheModel,
+    new: &str,
+) -> ServerResult<()> {
+    let current = cache.public_key()?;
+
+    if current == new {
+        return Ok(());
+    }
+
+    if let Some(previous) = previous_keypairs.iter().find(|p| p.public_key == new) {
+        if previous.state == KeypairState::Revoked {
+            return Err(ErrorKind::RequestError(anyhow!(
+                "The keypair has been revoked and cannot be used again"
+            ))
+            .into());
+        }
+    }
+
+    // Reinstating a retiring keypair is fine
+    previous_keypairs.retain(|p| p.public_key != new);
+
+    let conflict = iter::once(current.as_str())
+        .chain(
+            previous_keypairs
+                .iter()
+                .filter(|p| p.state != KeypairState::Revoked)
+                .map(|p| p.public_key.as_str()),
+        )
+        .any(|public_key| key_name(public_key) == key_name(new));
+
+    if conflict {
+        return Err(ErrorKind::RequestError(anyhow!(
+            "A keypair named \"{}\" is already in use",
+            key_name(new)
+        ))
+        .into());
+    }
+
+    let (keypair, signer) = match &cache.signer {
+        Some(signer) => (None, Some(signer.clone())),
+        None => (Some(cache.keypair.clone()), None),
+    };
+
+    previous_keypairs.push(PreviousKeypair {
+        public_key: current,
+        keypair,
+        signer,
+        state: KeypairState::Retiring,
+    });
+
+    Ok(())
+}
+
+/// Revokes previous keypairs.
+///
+/// The private keys of revoked keypairs are discarded.
+fn revoke_keypairs(
+    previous_keypairs: &mut [PreviousKeypair],
+    current: &str,
+    public_keys: &[String],
+) -> ServerResult<()> {
+    for public_key in public_keys {
+        if public_key == current {
+            return Err(ErrorKind::RequestError(anyhow!(
+                "The current keypair cannot be revoked. Replace it first."
+            ))
+            .into());
+        }
+
+        let previous = previous_keypairs
+            .iter_mut()
+            .find(|p| p.public_key == *public_key)
+            .ok_or_else(|| {
+                ErrorKind::RequestError(anyhow!("No keypair with public key {}", public_key))
+            })?;
+
+        previous.state = KeypairState::Revoked;
+        previous.keypair = None;
+        previous.signer = None;
+    }
+
+    Ok(())
+}
+
+/// A new keypair of a cache.
+struct NewKeypair {
+    /// The public key, in the canonical format used by Nix.
+    public_key: String,
+
+    /// The value of the `keypair` column.
+    keypair: String,
+
+    /// The external signer holding the keypair.
+    signer: Option<String>,
+}
+
+/// Returns the name portion of a public key.
+fn key_name(public_key: &str) -> &str {
+    public_key.split(':').next().unwrap()
+}
@@ -0,0 +1,61 @@
+use std::convert::Infallible;
+
+use async_stream::stream;
+use axum::extract::{Extension, Path};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::stream::Stream;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::instrument;
+
+use crate::error::ServerResult;
+use crate::{RequestState, State};
+use attic::api::v1::cache_events::{LaggedEvent, EVENT_LAGGED, EVENT_PATH_PUSHED};
+use attic::cache::CacheName;
+
+/// Streams events in a cache.
+///
+/// The stream ends when the server shuts down.
+#[instrument(skip_all, fields(cache_name))]
+pub(crate) async fn get_cache_events(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path(cache_name): Path<CacheName>,
+) -> ServerResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let mut receiver = state.events.subscribe();
+
+    let stream = stream! {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    if event.cache_id != cache.id {
+                        continue;
+                    }
+
+                    match Event::default().event(EVENT_PATH_PUSHED).json_data(&event.data) {
+                        Ok(sse) => yield Ok(sse),
+                        Err(e) => tracing::warn!("Failed to encode event: {}", e),
+                    }
+                }
+                Err(RecvError::Lagged(missed_events)) => {
+                    let lagged = LaggedEvent { missed_events };
+
+                    if let Ok(sse) = Event::default().event(EVENT_LAGGED).json_data(&lagged) {
+                        yield Ok(sse);
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
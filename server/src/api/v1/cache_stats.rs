@@ -0,0 +1,144 @@
+use axum::extract::{Extension, Json, Path};
+use chrono::{DateTime, Utc};
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{Alias, Query, SelectStatement};
+use sea_orm::{FromQueryResult, JoinType, QuerySelect, RelationTrait};
+use tracing::instrument;
+
+use crate::database::entity::cache::{self, Entity as Cache};
+use crate::database::entity::chunk::{self, Entity as Chunk};
+use crate::database::entity::chunkref::{self, Entity as ChunkRef};
+use crate::database::entity::nar;
+use crate::database::entity::object::{self, Entity as Object};
+use crate::error::{ServerError, ServerResult};
+use crate::{RequestState, State};
+use attic::api::v1::cache_stats::CacheStats;
+use attic::cache::CacheName;
+
+#[derive(Debug, FromQueryResult)]
+struct ObjectStats {
+    object_count: i64,
+    nar_size: Option<i64>,
+    last_pushed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct ChunkStats {
+    size: Option<i64>,
+}
+
+/// Gets the storage statistics of a cache.
+///
+/// Stored bytes are attributed through the chunks that back the
+/// NARs of the objects in the cache. A NAR shared by several caches
+/// shares all of its chunks, so chunk-level accounting also covers
+/// NAR deduplication.
+#[instrument(skip_all, fields(cache_name))]
+pub(crate) async fn get_cache_stats(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path(cache_name): Path<CacheName>,
+) -> ServerResult<Json<CacheStats>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let object_stats = Object::find()
+        .select_only()
+        .column_as(object::Column::Id.count(), "object_count")
+        .column_as(
+            nar::Column::NarSize.sum().cast_as(Alias::new("BIGINT")),
+            "nar_size",
+        )
+        .column_as(object::Column::CreatedAt.max(), "last_pushed_at")
+        .join(JoinType::InnerJoin, object::Relation::Nar.def())
+        .filter(object::Column::CacheId.eq(cache.id))
+        .into_model::<ObjectStats>()
+        .one(database)
+        .await
+        .map_err(ServerError::database_error)?;
+
+    let stored_size = sum_chunk_sizes(
+        database,
+        Chunk::find().filter(chunk::Column::Id.in_subquery(chunk_ids_of_objects(cache.id, true))),
+    )
+    .await?;
+
+    let shared_size = sum_chunk_sizes(
+        database,
+        Chunk::find()
+            .filter(chunk::Column::Id.in_subquery(chunk_ids_of_objects(cache.id, true)))
+            .filter(chunk::Column::Id.in_subquery(chunk_ids_of_objects(cache.id, false))),
+    )
+    .await?;
+
+    let (object_count, nar_size, last_pushed_at) = match object_stats {
+        Some(stats) => (
+            stats.object_count as u64,
+            stats.nar_size.unwrap_or(0) as u64,
+            stats.last_pushed_at,
+        ),
+        None => (0, 0, None),
+    };
+
+    Ok(Json(CacheStats {
+        object_count,
+        nar_size,
+        stored_size,
+        shared_size,
+        last_pushed_at,
+    }))
+}
+
+/// Returns a query selecting the IDs of chunks used by objects.
+///
+/// If `in_cache` is false, the chunks used by objects in all other
+/// live caches are selected instead.
+fn chunk_ids_of_objects(cache_id: i64, in_cache: bool) -> SelectStatement {
+    let mut query = Query::select()
+        .from(ChunkRef)
+        .expr(chunkref::Column::ChunkId.into_expr())
+        .inner_join(
+            Object,
+            object::Column::NarId
+                .into_expr()
+                .eq(chunkref::Column::NarId.into_expr()),
+        )
+        .to_owned();
+
+    if in_cache {
+        query.and_where(object::Column::CacheId.eq(cache_id));
+    } else {
+        query
+            .inner_join(
+                Cache,
+                cache::Column::Id
+                    .into_expr()
+                    .eq(object::Column::CacheId.into_expr()),
+            )
+            .and_where(object::Column::CacheId.ne(cache_id))
+            .and_where(cache::Column::DeletedAt.is_null());
+    }
+
+    query
+}
+
+async fn sum_chunk_sizes(database: &DatabaseConnection, query: Select<Chunk>) -> ServerResult<u64> {
+    let stats = query
+        .select_only()
+        .column_as(
+            chunk::Column::FileSize.sum().cast_as(Alias::new("BIGINT")),
+            "size",
+        )
+        .into_model::<ChunkStats>()
+        .one(database)
+        .await
+        .map_err(ServerError::database_error)?;
+
+    Ok(stats.and_then(|stats| stats.size).unwrap_or(0) as u64)
+}
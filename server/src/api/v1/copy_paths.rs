@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+use axum::extract::{Extension, Json};
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, QuerySelect, TransactionTrait};
+use tracing::instrument;
+
+use crate::database::entity::nar::{self, Entity as Nar};
+use crate::database::entity::object::{self, Entity as Object, InsertExt};
+use crate::database::AtticDatabase;
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::quota::{check_quota, Addition};
+use crate::{RequestState, State};
+use attic::api::v1::cache_events::PathPushedEvent;
+use attic::api::v1::copy_paths::{CopyPathsRequest, CopyPathsResponse};
+use attic::api::v1::webhook::WebhookEventData;
+use attic::hash::Hash;
+use attic::nix_store::StorePathHash;
+
+/// Number of objects to insert in a single statement.
+const INSERT_BATCH_SIZE: usize = 100;
+
+/// Number of objects to look up in a single query.
+const LOOKUP_BATCH_SIZE: usize = 1000;
+
+/// Copies paths from one cache to another.
+///
+/// Objects in the destination cache are created to point at the
+/// NARs of the objects in the source cache, so no data is transferred.
+/// Paths that already exist in the destination cache with the same
+/// NAR are left untouched. The copied paths count toward the quota of
+/// the destination cache, and are announced as pushed paths to event
+/// subscribers and webhooks of the destination cache.
+#[instrument(skip_all, fields(payload))]
+pub(crate) async fn copy_paths(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Json(payload): Json<CopyPathsRequest>,
+) -> ServerResult<Json<CopyPathsResponse>> {
+    let database = state.database().await?;
+    let source = req_state
+        .auth
+        .auth_cache(database, &payload.source_cache, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+    let destination = req_state
+        .auth
+        .auth_cache(database, &payload.destination_cache, |cache, permission| {
+            permission.require_push()?;
+            Ok(cache)
+        })
+        .await?;
+
+    if source.id == destination.id {
+        return Err(ErrorKind::RequestError(anyhow!(
+            "The source and destination caches are the same"
+        ))
+        .into());
+    }
+
+    if source.store_dir != destination.store_dir {
+        return Err(ErrorKind::RequestError(anyhow!(
+            "The source and destination caches use different store directories"
+        ))
+        .into());
+    }
+
+    let objects = database
+        .find_objects_in_closure(source.id, &payload.store_path_hashes, payload.closure)
+        .await?;
+
+    // Store path hash -> NAR ID
+    let mut existing: HashMap<String, i64> = HashMap::new();
+    for batch in objects.chunks(LOOKUP_BATCH_SIZE) {
+        let objects = Object::find()
+            .filter(object::Column::CacheId.eq(destination.id))
+            .filter(
+                object::Column::StorePathHash
+                    .is_in(batch.iter().map(|object| object.store_path_hash.as_str())),
+            )
+            .all(database)
+            .await
+            .map_err(ServerError::database_error)?;
+
+        existing.extend(
+            objects
+                .into_iter()
+                .map(|object| (object.store_path_hash, object.nar_id)),
+        );
+    }
+
+    let found: HashSet<&str> = objects
+        .iter()
+        .map(|object| object.store_path_hash.as_str())
+        .collect();
+
+    let missing_paths = payload
+        .store_path_hashes
+        .iter()
+        .filter(|hash| !found.contains(hash.as_str()))
+        .cloned()
+        .collect::<Vec<StorePathHash>>();
+
+    let (skipped, copied): (Vec<_>, Vec<_>) = objects
+        .into_iter()
+        .partition(|object| existing.get(&object.store_path_hash) == Some(&object.nar_id));
+
+    // NAR ID -> (NAR hash, NAR size)
+    let mut nars: HashMap<i64, (String, i64)> = HashMap::new();
+    for batch in copied.chunks(LOOKUP_BATCH_SIZE) {
+        let found = Nar::find()
+            .select_only()
+            .column(nar::Column::Id)
+            .column(nar::Column::NarHash)
+            .column(nar::Column::NarSize)
+            .filter(nar::Column::Id.is_in(batch.iter().map(|object| object.nar_id)))
+            .into_tuple::<(i64, String, i64)>()
+            .all(database)
+            .await
+            .map_err(ServerError::database_error)?;
+
+        nars.extend(
+            found
+                .into_iter()
+                .map(|(id, nar_hash, nar_size)| (id, (nar_hash, nar_size))),
+        );
+    }
+
+    if !copied.is_empty() {
+        let addition = Addition {
+            objects: copied.len() as u64,
+            nar_size: copied
+                .iter()
+                .map(|object| nars.get(&object.nar_id).map_or(0, |(_, size)| *size) as u64)
+                .sum(),
+            store_path_hashes: copied
+                .iter()
+                .map(|object| object.store_path_hash.as_str())
+                .collect(),
+        };
+
+        check_quota(&state, &destination, &addition).await?;
+    }
+
+    let username = req_state.auth.username().map(str::to_string);
+    let now = Utc::now();
+
+    let txn = database
+        .begin()
+        .await
+        .map_err(ServerError::database_error)?;
+
+    for batch in copied.chunks(INSERT_BATCH_SIZE) {
+        let new_objects = batch.iter().map(|object| object::ActiveModel {
+            cache_id: Set(destination.id),
+            nar_id: Set(object.nar_id),
+            store_path_hash: Set(object.store_path_hash.clone()),
+            store_path: Set(object.store_path.clone()),
+            references: Set(object.references.clone()),
+            system: Set(object.system.clone()),
+            deriver: Set(object.deriver.clone()),
+            sigs: Set(object.sigs.clone()),
+            ca: Set(object.ca.clone()),
+            created_at: Set(now),
+            last_accessed_at: Set(None),
+            created_by: Set(username.clone()),
+            ..Default::default()
+        });
+
+        Object::insert_many(new_objects)
+            .on_conflict_do_update()
+            .exec(&txn)
+            .await
+            .map_err(ServerError::database_error)?;
+    }
+
+    txn.commit().await.map_err(ServerError::database_error)?;
+
+    let mut webhook_events = Vec::new();
+    for object in &copied {
+        let Some((nar_hash, nar_size)) = nars.get(&object.nar_id) else {
+            continue;
+        };
+
+        let nar_hash = Hash::from_typed(nar_hash)?.to_typed_base32();
+        let nar_size = *nar_size as u64;
+
+        state.events.send(
+            destination.id,
+            PathPushedEvent {
+                store_path_hash: StorePathHash::new(object.store_path_hash.clone())?,
+                store_path: object.store_path.clone(),
+                references: object.references.0.clone(),
+                system: object.system.clone(),
+                nar_hash: nar_hash.clone(),
+                nar_size,
+                pushed_at: now,
+            },
+        );
+
+        webhook_events.push(WebhookEventData::PathPushed {
+            store_path: object.store_path.clone(),
+            nar_hash,
+            nar_size,
+            pushed_by: username.clone(),
+        });
+    }
+
+    state
+        .webhooks
+        .notify_many(
+            database,
+            destination.id,
+            &payload.destination_cache,
+            &destination.webhooks.0,
+            webhook_events,
+        )
+        .await;
+
+    Ok(Json(CopyPathsResponse {
+        copied_paths: copied.into_iter().map(|object| object.store_path).collect(),
+        skipped_paths: skipped
+            .into_iter()
+            .map(|object| object.store_path)
+            .collect(),
+        missing_paths,
+    }))
+}
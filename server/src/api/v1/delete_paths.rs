@@ -0,0 +1,78 @@
+use std::collections::HashSet;
+
+use axum::extract::{Extension, Json};
+use sea_orm::entity::prelude::*;
+use sea_orm::TransactionTrait;
+use tracing::instrument;
+
+use crate::database::entity::object::{self, Entity as Object};
+use crate::database::AtticDatabase;
+use crate::error::{ServerError, ServerResult};
+use crate::{RequestState, State};
+use attic::api::v1::delete_paths::{DeletePathsRequest, DeletePathsResponse};
+use attic::nix_store::StorePathHash;
+
+/// Number of objects to delete in a single statement.
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/// Deletes paths from a cache.
+///
+/// Only the objects in the cache are deleted. NARs and chunks that
+/// are no longer referenced by any cache are reaped by garbage
+/// collection.
+#[instrument(skip_all, fields(payload))]
+pub(crate) async fn delete_paths(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Json(payload): Json<DeletePathsRequest>,
+) -> ServerResult<Json<DeletePathsResponse>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &payload.cache, |cache, permission| {
+            permission.require_delete()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let objects = database
+        .find_objects_in_closure(cache.id, &payload.store_path_hashes, payload.closure)
+        .await?;
+
+    let found: HashSet<&str> = objects
+        .iter()
+        .map(|object| object.store_path_hash.as_str())
+        .collect();
+
+    if !payload.dry_run && !objects.is_empty() {
+        let txn = database
+            .begin()
+            .await
+            .map_err(ServerError::database_error)?;
+
+        for batch in objects.chunks(DELETE_BATCH_SIZE) {
+            Object::delete_many()
+                .filter(object::Column::Id.is_in(batch.iter().map(|object| object.id)))
+                .exec(&txn)
+                .await
+                .map_err(ServerError::database_error)?;
+        }
+
+        txn.commit().await.map_err(ServerError::database_error)?;
+    }
+
+    let missing_paths = payload
+        .store_path_hashes
+        .iter()
+        .filter(|hash| !found.contains(hash.as_str()))
+        .cloned()
+        .collect::<Vec<StorePathHash>>();
+
+    Ok(Json(DeletePathsResponse {
+        deleted_paths: objects
+            .into_iter()
+            .map(|object| object.store_path)
+            .collect(),
+        missing_paths,
+    }))
+}
@@ -0,0 +1,56 @@
+use std::collections::HashSet;
+
+use axum::extract::{Extension, Json};
+use tracing::instrument;
+
+use crate::database::AtticDatabase;
+use crate::error::ServerResult;
+use crate::narinfo::Compression;
+use crate::{RequestState, State};
+use attic::api::v1::get_missing_chunks::{GetMissingChunksRequest, GetMissingChunksResponse};
+
+/// Gets information on missing chunks.
+///
+/// Requires "push" permission as it essentially allows probing
+/// of cache contents.
+///
+/// If proof of possession is required, only chunks that are part of
+/// NARs in the cache count as existing. Otherwise, chunks anywhere in
+/// the global cache can be reused.
+#[instrument(skip_all, fields(payload))]
+pub(crate) async fn get_missing_chunks(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Json(payload): Json<GetMissingChunksRequest>,
+) -> ServerResult<Json<GetMissingChunksResponse>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &payload.cache, |cache, permission| {
+            permission.require_push()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let compression: Compression = state.config.compression.r#type.into();
+    let cache_id = if state.config.require_proof_of_possession {
+        Some(cache.id)
+    } else {
+        None
+    };
+
+    let existing = database
+        .find_existing_chunk_hashes(&payload.chunk_hashes, compression, cache_id)
+        .await?;
+
+    let mut seen = HashSet::new();
+    let mut missing_chunk_hashes = payload.chunk_hashes;
+    missing_chunk_hashes.retain(|hash| {
+        let hash = hash.to_typed_base16();
+        !existing.contains(&hash) && seen.insert(hash)
+    });
+
+    Ok(Json(GetMissingChunksResponse {
+        missing_chunk_hashes,
+    }))
+}
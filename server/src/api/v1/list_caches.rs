@@ -0,0 +1,66 @@
+use axum::extract::{Extension, Json};
+use sea_orm::entity::prelude::*;
+use sea_orm::QueryOrder;
+use tracing::instrument;
+
+use crate::access::CachePermission;
+use crate::database::entity::cache::{self, Entity as Cache};
+use crate::error::{ServerError, ServerResult};
+use crate::{RequestState, State};
+use attic::api::v1::list_caches::{CacheInfo, CachePermissions, ListCachesResponse};
+use attic::cache::CacheName;
+
+/// Lists caches that the client can discover.
+///
+/// Token permissions are keyed by patterns, so every cache is
+/// checked against the token.
+#[instrument(skip_all)]
+pub(crate) async fn list_caches(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+) -> ServerResult<Json<ListCachesResponse>> {
+    let database = state.database().await?;
+
+    let mut query = Cache::find().filter(cache::Column::DeletedAt.is_null());
+
+    if req_state.auth.token.get().is_none() {
+        // Anonymous clients can only discover public caches
+        query = query.filter(cache::Column::IsPublic.eq(true));
+    }
+
+    let all_caches = query
+        .order_by_asc(cache::Column::Name)
+        .all(database)
+        .await
+        .map_err(ServerError::database_error)?;
+
+    let mut caches = Vec::new();
+    for cache in all_caches {
+        let name: CacheName = cache.name.parse()?;
+        let permission = req_state
+            .auth
+            .get_permission_for_cache(&name, cache.is_public);
+
+        if permission.can_discover() {
+            caches.push(CacheInfo {
+                name,
+                is_public: cache.is_public,
+                permission: to_api_permission(&permission),
+            });
+        }
+    }
+
+    Ok(Json(ListCachesResponse { caches }))
+}
+
+fn to_api_permission(permission: &CachePermission) -> CachePermissions {
+    CachePermissions {
+        pull: permission.pull,
+        push: permission.push,
+        delete: permission.delete,
+        create_cache: permission.create_cache,
+        configure_cache: permission.configure_cache,
+        configure_cache_retention: permission.configure_cache_retention,
+        destroy_cache: permission.destroy_cache,
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::anyhow;
+use axum::extract::{Extension, Json, Path, Query};
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{Expr, LikeExpr};
+use sea_orm::{Condition, QueryOrder, QuerySelect};
+use tracing::instrument;
+
+use crate::database::entity::nar::Entity as Nar;
+use crate::database::entity::object::{self, Entity as Object};
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::{RequestState, State};
+use attic::api::v1::list_objects::{ListObjectsQuery, ListObjectsResponse, ObjectInfo};
+use attic::cache::CacheName;
+use attic::hash::Hash;
+
+/// The number of objects returned if the client does not specify a limit.
+const DEFAULT_LIMIT: usize = 100;
+
+/// The maximum number of objects returned in a page.
+const MAX_LIMIT: usize = 1000;
+
+/// Lists objects in a cache.
+///
+/// The cursor is the ID of the last object in the previous page. Since
+/// object IDs only ever increase, objects uploaded while a client is
+/// paging through the results will not shift the pages.
+#[instrument(skip_all, fields(cache_name, query))]
+pub(crate) async fn list_objects(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path(cache_name): Path<CacheName>,
+    Query(query): Query<ListObjectsQuery>,
+) -> ServerResult<Json<ListObjectsResponse>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut condition = Condition::all().add(object::Column::CacheId.eq(cache.id));
+
+    if let Some(cursor) = &query.cursor {
+        let cursor: i64 = cursor
+            .parse()
+            .map_err(|_| ErrorKind::RequestError(anyhow!("Invalid cursor")))?;
+        condition = condition.add(object::Column::Id.lt(cursor));
+    }
+
+    if let Some(name) = &query.name {
+        let pattern = format!("%{}%", escape_like(name));
+        condition = condition.add(
+            Expr::col((object::Entity, object::Column::StorePath))
+                .like(LikeExpr::new(pattern).escape('\\')),
+        );
+    }
+
+    if let Some(created_by) = &query.created_by {
+        condition = condition.add(object::Column::CreatedBy.eq(created_by.as_str()));
+    }
+
+    if let Some(system) = &query.system {
+        condition = condition.add(object::Column::System.eq(system.as_str()));
+    }
+
+    if let Some(created_after) = query.created_after {
+        condition = condition.add(object::Column::CreatedAt.gte(created_after));
+    }
+
+    if let Some(created_before) = query.created_before {
+        condition = condition.add(object::Column::CreatedAt.lt(created_before));
+    }
+
+    if let Some(accessed_after) = query.accessed_after {
+        condition = condition.add(object::Column::LastAccessedAt.gte(accessed_after));
+    }
+
+    if let Some(accessed_before) = query.accessed_before {
+        condition = condition.add(
+            Condition::any()
+                .add(object::Column::LastAccessedAt.is_null())
+                .add(object::Column::LastAccessedAt.lt(accessed_before)),
+        );
+    }
+
+    // Fetch one more object to find out whether there is a next page
+    let mut results = Object::find()
+        .filter(condition)
+        .find_also_related(Nar)
+        .order_by_desc(object::Column::Id)
+        .limit(limit as u64 + 1)
+        .all(database)
+        .await
+        .map_err(ServerError::database_error)?;
+
+    let next_cursor = if results.len() > limit {
+        results.truncate(limit);
+        results.last().map(|(object, _)| object.id.to_string())
+    } else {
+        None
+    };
+
+    let objects = results
+        .into_iter()
+        .map(|(object, nar)| {
+            let nar = nar.ok_or_else(|| {
+                ErrorKind::DatabaseError(anyhow!("Object {} has no NAR", object.id))
+            })?;
+
+            Ok(ObjectInfo {
+                store_path: object.store_path,
+                nar_hash: Hash::from_typed(&nar.nar_hash)?,
+                nar_size: nar.nar_size as u64,
+                system: object.system,
+                deriver: object.deriver,
+                created_at: object.created_at,
+                last_accessed_at: object.last_accessed_at,
+                created_by: object.created_by,
+            })
+        })
+        .collect::<ServerResult<Vec<ObjectInfo>>>()?;
+
+    Ok(Json(ListObjectsResponse {
+        objects,
+        next_cursor,
+    }))
+}
+
+/// Escapes the wildcards in a LIKE pattern.
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
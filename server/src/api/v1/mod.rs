@@ -1,6 +1,19 @@
 mod cache_config;
+mod cache_events;
+mod cache_stats;
+mod copy_paths;
+mod delete_paths;
+mod get_missing_chunks;
 mod get_missing_paths;
-mod upload_path;
+mod list_caches;
+mod list_objects;
+mod narinfo_batch;
+mod pin;
+mod possession_challenge;
+mod tag;
+mod upload_log;
+pub(crate) mod upload_path;
+mod upload_realisation;
 
 use axum::{
     routing::{delete, get, patch, post, put},
@@ -13,7 +26,32 @@ pub(crate) fn get_router() -> Router {
             "/_api/v1/get-missing-paths",
             post(get_missing_paths::get_missing_paths),
         )
+        .route("/_api/v1/copy-paths", post(copy_paths::copy_paths))
+        .route("/_api/v1/delete-paths", post(delete_paths::delete_paths))
+        .route(
+            "/_api/v1/get-missing-chunks",
+            post(get_missing_chunks::get_missing_chunks),
+        )
+        .route(
+            "/_api/v1/get-possession-challenge",
+            post(possession_challenge::get_possession_challenge),
+        )
+        .route(
+            "/_api/v1/narinfo-batch",
+            post(narinfo_batch::get_nar_info_batch),
+        )
+        .route("/_api/v1/pin-paths", post(pin::pin_paths))
+        .route("/_api/v1/unpin-paths", post(pin::unpin_paths))
         .route("/_api/v1/upload-path", put(upload_path::upload_path))
+        .route(
+            "/_api/v1/upload-chunked-path",
+            put(upload_path::upload_chunked_path),
+        )
+        .route("/_api/v1/upload-log", put(upload_log::upload_log))
+        .route(
+            "/_api/v1/upload-realisation",
+            put(upload_realisation::upload_realisation),
+        )
         .route(
             "/:cache/attic-cache-info",
             get(cache_config::get_cache_config),
@@ -34,4 +72,26 @@ pub(crate) fn get_router() -> Router {
             "/_api/v1/cache-config/:cache",
             delete(cache_config::destroy_cache),
         )
+        .route(
+            "/_api/v1/cache/:cache/events",
+            get(cache_events::get_cache_events),
+        )
+        .route(
+            "/_api/v1/cache/:cache/stats",
+            get(cache_stats::get_cache_stats),
+        )
+        .route("/_api/v1/cache/:cache/pins", get(pin::list_pins))
+        .route(
+            "/_api/v1/cache/:cache/tags/*tag",
+            get(tag::get_tag).put(tag::set_tag),
+        )
+        .route(
+            "/_api/v1/cache/:cache/tag-history/*tag",
+            get(tag::get_tag_history),
+        )
+        .route("/_api/v1/caches", get(list_caches::list_caches))
+        .route(
+            "/_api/v1/cache/:cache/objects",
+            get(list_objects::list_objects),
+        )
 }
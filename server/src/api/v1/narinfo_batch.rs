@@ -0,0 +1,103 @@
+use std::collections::{HashMap, HashSet};
+use std::iter;
+
+use anyhow::anyhow;
+use axum::extract::{Extension, Json};
+use tracing::instrument;
+
+use crate::database::AtticDatabase;
+use crate::error::{ErrorKind, ServerResult};
+use crate::narinfo::NarInfo;
+use crate::signing::{sign_nar_info, signing_keys};
+use crate::{RequestState, State};
+use attic::api::v1::narinfo_batch::{
+    self, NarInfoBatchRequest, NarInfoBatchResponse, MAX_STORE_PATH_HASHES,
+};
+use attic::cache::CacheName;
+use attic::nix_store::StorePathHash;
+
+/// Gets the NAR information of many paths in a cache.
+///
+/// Like `.narinfo` requests, the members of virtual caches are
+/// searched in order. Upstream caches are not consulted.
+///
+/// The number of paths is limited since each narinfo is signed
+/// separately, which may involve an external signer.
+#[instrument(skip_all, fields(payload))]
+pub(crate) async fn get_nar_info_batch(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Json(payload): Json<NarInfoBatchRequest>,
+) -> ServerResult<Json<NarInfoBatchResponse>> {
+    if payload.store_path_hashes.len() > MAX_STORE_PATH_HASHES {
+        return Err(ErrorKind::RequestError(anyhow!(
+            "At most {} paths can be queried at once",
+            MAX_STORE_PATH_HASHES
+        ))
+        .into());
+    }
+
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &payload.cache, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let keys = signing_keys(&state, &cache)?;
+    let members = req_state.auth.pullable_members(database, &cache).await?;
+
+    let mut remaining: HashSet<StorePathHash> = payload.store_path_hashes.into_iter().collect();
+    let mut narinfos = HashMap::new();
+
+    for source in iter::once(&cache).chain(members.iter()) {
+        if remaining.is_empty() {
+            break;
+        }
+
+        let source_name: CacheName = source.name.parse()?;
+        let source_keys = signing_keys(&state, source)?;
+        let hashes: Vec<StorePathHash> = remaining.iter().cloned().collect();
+
+        for (object, _, nar) in database
+            .find_objects_by_store_path_hashes(&source_name, &hashes)
+            .await?
+        {
+            let mut narinfo = object.to_nar_info(&nar)?;
+
+            sign_nar_info(&source_keys, &mut narinfo).await?;
+
+            if source.id != cache.id {
+                sign_nar_info(&keys, &mut narinfo).await?;
+            }
+
+            let store_path_hash = StorePathHash::new(object.store_path_hash)?;
+            remaining.remove(&store_path_hash);
+            narinfos.insert(store_path_hash, to_api_nar_info(narinfo));
+        }
+    }
+
+    Ok(Json(NarInfoBatchResponse {
+        narinfos,
+        missing_paths: remaining.into_iter().collect(),
+    }))
+}
+
+fn to_api_nar_info(narinfo: NarInfo) -> narinfo_batch::NarInfo {
+    narinfo_batch::NarInfo {
+        store_path: narinfo.store_path.to_string_lossy().into_owned(),
+        url: narinfo.url,
+        compression: narinfo.compression.as_str().to_owned(),
+        file_hash: narinfo.file_hash,
+        file_size: narinfo.file_size,
+        nar_hash: narinfo.nar_hash,
+        nar_size: narinfo.nar_size,
+        references: narinfo.references,
+        system: narinfo.system,
+        deriver: narinfo.deriver,
+        sigs: narinfo.signatures,
+        ca: narinfo.ca,
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use axum::extract::{Extension, Json, Path};
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, Condition, TransactionTrait};
+use tracing::instrument;
+
+use crate::database::entity::pin::{self, Entity as Pin, InsertExt};
+use crate::database::AtticDatabase;
+use crate::error::{ServerError, ServerResult};
+use crate::{RequestState, State};
+use attic::api::v1::pin::{
+    ListPinsResponse, PinInfo, PinPathsRequest, PinPathsResponse, UnpinPathsRequest,
+    UnpinPathsResponse,
+};
+use attic::cache::CacheName;
+use attic::nix_store::StorePathHash;
+
+/// Pins paths in a cache.
+///
+/// Only paths that are in the cache can be pinned. Their closures
+/// are resolved during garbage collection, so paths pushed later
+/// are protected as well.
+#[instrument(skip_all, fields(payload))]
+pub(crate) async fn pin_paths(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Json(payload): Json<PinPathsRequest>,
+) -> ServerResult<Json<PinPathsResponse>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &payload.cache, |cache, permission| {
+            permission.require_configure_cache_retention()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let objects = database
+        .find_objects_in_closure(cache.id, &payload.store_path_hashes, false)
+        .await?;
+
+    let found: HashSet<&str> = objects
+        .iter()
+        .map(|object| object.store_path_hash.as_str())
+        .collect();
+
+    let missing_paths = payload
+        .store_path_hashes
+        .iter()
+        .filter(|hash| !found.contains(hash.as_str()))
+        .cloned()
+        .collect::<Vec<StorePathHash>>();
+
+    let username = req_state.auth.username().map(str::to_string);
+    let now = Utc::now();
+
+    let txn = database
+        .begin()
+        .await
+        .map_err(ServerError::database_error)?;
+
+    for object in &objects {
+        Pin::insert(pin::ActiveModel {
+            cache_id: Set(cache.id),
+            store_path_hash: Set(object.store_path_hash.clone()),
+            store_path: Set(object.store_path.clone()),
+            name: Set(payload.name.clone()),
+            created_at: Set(now),
+            expires_at: Set(payload.expires_at),
+            created_by: Set(username.clone()),
+            ..Default::default()
+        })
+        .on_conflict_do_update()
+        .exec(&txn)
+        .await
+        .map_err(ServerError::database_error)?;
+    }
+
+    txn.commit().await.map_err(ServerError::database_error)?;
+
+    Ok(Json(PinPathsResponse {
+        pinned_paths: objects
+            .into_iter()
+            .map(|object| object.store_path)
+            .collect(),
+        missing_paths,
+    }))
+}
+
+/// Unpins paths in a cache.
+#[instrument(skip_all, fields(payload))]
+pub(crate) async fn unpin_paths(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Json(payload): Json<UnpinPathsRequest>,
+) -> ServerResult<Json<UnpinPathsResponse>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &payload.cache, |cache, permission| {
+            permission.require_configure_cache_retention()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let mut condition = Condition::any().add(
+        pin::Column::StorePathHash
+            .is_in(payload.store_path_hashes.iter().map(StorePathHash::as_str)),
+    );
+
+    if let Some(name) = &payload.name {
+        condition = condition.add(pin::Column::Name.eq(name.as_str()));
+    }
+
+    let pins = Pin::find()
+        .filter(pin::Column::CacheId.eq(cache.id))
+        .filter(condition)
+        .all(database)
+        .await
+        .map_err(ServerError::database_error)?;
+
+    if !pins.is_empty() {
+        Pin::delete_many()
+            .filter(pin::Column::Id.is_in(pins.iter().map(|pin| pin.id)))
+            .exec(database)
+            .await
+            .map_err(ServerError::database_error)?;
+    }
+
+    Ok(Json(UnpinPathsResponse {
+        unpinned_paths: pins.into_iter().map(|pin| pin.store_path).collect(),
+    }))
+}
+
+/// Lists pins in a cache.
+///
+/// Expired pins that haven't been deleted by garbage collection
+/// are omitted.
+#[instrument(skip_all, fields(cache_name))]
+pub(crate) async fn list_pins(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path(cache_name): Path<CacheName>,
+) -> ServerResult<Json<ListPinsResponse>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let pins = database
+        .find_active_pins(cache.id)
+        .await?
+        .into_iter()
+        .map(|pin| PinInfo {
+            store_path: pin.store_path,
+            name: pin.name,
+            created_at: pin.created_at,
+            expires_at: pin.expires_at,
+            created_by: pin.created_by,
+        })
+        .collect();
+
+    Ok(Json(ListPinsResponse { pins }))
+}
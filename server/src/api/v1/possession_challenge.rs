@@ -0,0 +1,118 @@
+use axum::extract::{Extension, Json};
+use chrono::{Duration, Utc};
+use rand::Rng;
+use sea_orm::entity::prelude::*;
+use sea_orm::ActiveValue::Set;
+use tracing::instrument;
+
+use crate::database::entity::possession_challenge::{
+    self, Entity as PossessionChallengeEntity, PossessionChallengeModel,
+};
+use crate::database::entity::Json as DbJson;
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::{RequestState, State};
+use attic::api::v1::possession_challenge::{
+    ByteRange, GetPossessionChallengeRequest, GetPossessionChallengeResponse, PossessionChallenge,
+};
+
+/// Number of byte ranges in a challenge.
+const CHALLENGE_RANGES: usize = 16;
+
+/// Length of each byte range in a challenge.
+const CHALLENGE_RANGE_LENGTH: u64 = 4096;
+
+/// How long a challenge can be answered.
+///
+/// The client may need to dump a large NAR to answer it.
+const CHALLENGE_LIFETIME: Duration = Duration::hours(1);
+
+/// Issues a challenge to prove possession of a NAR.
+///
+/// Requires "push" permission. A challenge is issued whether or not
+/// the NAR exists.
+#[instrument(skip_all, fields(payload))]
+pub(crate) async fn get_possession_challenge(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Json(payload): Json<GetPossessionChallengeRequest>,
+) -> ServerResult<Json<GetPossessionChallengeResponse>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &payload.cache, |cache, permission| {
+            permission.require_push()?;
+            Ok(cache)
+        })
+        .await?;
+
+    if !state.config.require_proof_of_possession {
+        return Ok(Json(GetPossessionChallengeResponse { challenge: None }));
+    }
+
+    let nar_size = i64::try_from(payload.nar_size).map_err(ServerError::request_error)?;
+    let challenge = new_challenge(payload.nar_size as u64);
+
+    PossessionChallengeEntity::insert(possession_challenge::ActiveModel {
+        nonce: Set(challenge.nonce.clone()),
+        cache_id: Set(cache.id),
+        nar_hash: Set(payload.nar_hash.to_typed_base16()),
+        nar_size: Set(nar_size),
+        ranges: Set(DbJson(challenge.ranges.clone())),
+        expires_at: Set(challenge.expires_at),
+        ..Default::default()
+    })
+    .exec(database)
+    .await
+    .map_err(ServerError::database_error)?;
+
+    Ok(Json(GetPossessionChallengeResponse {
+        challenge: Some(challenge),
+    }))
+}
+
+/// Takes an unexpired challenge so it can't be answered again.
+pub(crate) async fn take_challenge(
+    database: &DatabaseConnection,
+    nonce: &str,
+) -> ServerResult<PossessionChallengeModel> {
+    let challenge = PossessionChallengeEntity::find()
+        .filter(possession_challenge::Column::Nonce.eq(nonce))
+        .one(database)
+        .await
+        .map_err(ServerError::database_error)?
+        .ok_or(ErrorKind::PossessionProofRejected)?;
+
+    let deletion = PossessionChallengeEntity::delete_by_id(challenge.id)
+        .exec(database)
+        .await
+        .map_err(ServerError::database_error)?;
+
+    // Someone else took it first
+    if deletion.rows_affected == 0 || challenge.expires_at < Utc::now() {
+        return Err(ErrorKind::PossessionProofRejected.into());
+    }
+
+    Ok(challenge)
+}
+
+/// Returns a new challenge for a NAR of some size.
+fn new_challenge(nar_size: u64) -> PossessionChallenge {
+    let mut rng = rand::thread_rng();
+
+    let nonce = hex::encode(rng.gen::<[u8; 32]>());
+
+    let length = CHALLENGE_RANGE_LENGTH.min(nar_size);
+    let mut ranges: Vec<ByteRange> = (0..CHALLENGE_RANGES)
+        .map(|_| ByteRange {
+            offset: rng.gen_range(0..=nar_size - length),
+            length,
+        })
+        .collect();
+    ranges.sort_by_key(|range| range.offset);
+
+    PossessionChallenge {
+        nonce,
+        ranges,
+        expires_at: Utc::now() + CHALLENGE_LIFETIME,
+    }
+}
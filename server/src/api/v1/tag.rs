@@ -0,0 +1,167 @@
+use anyhow::anyhow;
+use axum::extract::{Extension, Json, Path};
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, QueryOrder};
+use tracing::instrument;
+
+use crate::database::entity::tag::{self, Entity as Tag, TagModel};
+use crate::database::AtticDatabase;
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::{RequestState, State};
+use attic::api::v1::tag::{SetTagRequest, TagHistoryResponse, TagInfo};
+use attic::cache::CacheName;
+
+/// The maximum length of a tag name.
+const MAX_TAG_NAME_LEN: usize = 128;
+
+/// Gets the current target of a tag.
+#[instrument(skip_all, fields(cache_name, tag_name))]
+pub(crate) async fn get_tag(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path((cache_name, tag_name)): Path<(CacheName, String)>,
+) -> ServerResult<Json<TagInfo>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let tag = find_current_tag(database, cache.id, &tag_name)
+        .await?
+        .ok_or(ErrorKind::NoSuchTag)?;
+
+    Ok(Json(to_api_tag(tag)))
+}
+
+/// Points a tag at a store path.
+///
+/// The previous target is kept in the history of the tag. Setting
+/// a tag to its current target is a no-op.
+#[instrument(skip_all, fields(cache_name, tag_name, payload))]
+pub(crate) async fn set_tag(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path((cache_name, tag_name)): Path<(CacheName, String)>,
+    Json(payload): Json<SetTagRequest>,
+) -> ServerResult<Json<TagInfo>> {
+    validate_tag_name(&tag_name)?;
+
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_push()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let object = database
+        .find_objects_in_closure(cache.id, &[payload.store_path_hash], false)
+        .await?
+        .pop()
+        .ok_or(ErrorKind::NoSuchObject)?;
+
+    if let Some(current) = find_current_tag(database, cache.id, &tag_name).await? {
+        if current.store_path_hash == object.store_path_hash {
+            return Ok(Json(to_api_tag(current)));
+        }
+    }
+
+    let tag = Tag::insert(tag::ActiveModel {
+        cache_id: Set(cache.id),
+        name: Set(tag_name),
+        store_path_hash: Set(object.store_path_hash),
+        store_path: Set(object.store_path),
+        created_at: Set(Utc::now()),
+        created_by: Set(req_state.auth.username().map(str::to_string)),
+        ..Default::default()
+    })
+    .exec_with_returning(database)
+    .await
+    .map_err(ServerError::database_error)?;
+
+    Ok(Json(to_api_tag(tag)))
+}
+
+/// Gets the history of a tag.
+#[instrument(skip_all, fields(cache_name, tag_name))]
+pub(crate) async fn get_tag_history(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Path((cache_name, tag_name)): Path<(CacheName, String)>,
+) -> ServerResult<Json<TagHistoryResponse>> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &cache_name, |cache, permission| {
+            permission.require_pull()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let history: Vec<TagInfo> = Tag::find()
+        .filter(tag::Column::CacheId.eq(cache.id))
+        .filter(tag::Column::Name.eq(tag_name.as_str()))
+        .order_by_desc(tag::Column::Id)
+        .all(database)
+        .await
+        .map_err(ServerError::database_error)?
+        .into_iter()
+        .map(to_api_tag)
+        .collect();
+
+    if history.is_empty() {
+        return Err(ErrorKind::NoSuchTag.into());
+    }
+
+    Ok(Json(TagHistoryResponse { history }))
+}
+
+async fn find_current_tag(
+    database: &DatabaseConnection,
+    cache_id: i64,
+    tag_name: &str,
+) -> ServerResult<Option<TagModel>> {
+    Tag::find()
+        .filter(tag::Column::CacheId.eq(cache_id))
+        .filter(tag::Column::Name.eq(tag_name))
+        .order_by_desc(tag::Column::Id)
+        .one(database)
+        .await
+        .map_err(ServerError::database_error)
+}
+
+/// Validates a tag name.
+///
+/// Tag names consist of segments of alphanumeric characters, `.`,
+/// `_` and `-` separated by `/`.
+fn validate_tag_name(name: &str) -> ServerResult<()> {
+    let valid_segment = |segment: &str| {
+        !segment.is_empty()
+            && segment != "."
+            && segment != ".."
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    };
+
+    if name.len() > MAX_TAG_NAME_LEN || !name.split('/').all(valid_segment) {
+        return Err(ErrorKind::RequestError(anyhow!("Invalid tag name \"{}\"", name)).into());
+    }
+
+    Ok(())
+}
+
+fn to_api_tag(tag: TagModel) -> TagInfo {
+    TagInfo {
+        name: tag.name,
+        store_path: tag.store_path,
+        created_at: tag.created_at,
+        created_by: tag.created_by,
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::anyhow;
+use axum::{body::Body, extract::Extension, http::HeaderMap};
+use tracing::instrument;
+
+use crate::buildlog;
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::{RequestState, State};
+use attic::api::v1::upload_log::{UploadLogInfo, ATTIC_BUILD_LOG_INFO};
+
+/// Uploads the build log of a derivation.
+///
+/// The derivation doesn't need to exist in the cache. An existing
+/// log of the same derivation is replaced.
+#[instrument(skip_all)]
+pub(crate) async fn upload_log(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    headers: HeaderMap,
+    body: Body,
+) -> ServerResult<()> {
+    let upload_info: UploadLogInfo = {
+        let info_bytes = headers.get(ATTIC_BUILD_LOG_INFO).ok_or_else(|| {
+            ErrorKind::RequestError(anyhow!("{} must be set", ATTIC_BUILD_LOG_INFO))
+        })?;
+
+        serde_json::from_slice(info_bytes.as_bytes()).map_err(ServerError::request_error)?
+    };
+
+    let drv_path = buildlog::parse_drv_path(&upload_info.drv_path)?;
+    let stream = buildlog::request_body(&headers, body)?;
+
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &upload_info.cache, |cache, permission| {
+            permission.require_push()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let username = req_state.auth.username().map(str::to_string);
+
+    buildlog::upload_log(&state, cache.id, drv_path, stream, username).await
+}
@@ -1,3 +1,4 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::io;
 
 use std::io::Cursor;
@@ -10,17 +11,18 @@ use async_compression::Level as CompressionLevel;
 use axum::{
     body::Body,
     extract::{Extension, Json},
-    http::HeaderMap,
+    http::{HeaderMap, HeaderValue},
 };
 use bytes::{Bytes, BytesMut};
 use chrono::Utc;
 use digest::Output as DigestOutput;
 use futures::future::join_all;
-use futures::StreamExt;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use sea_orm::entity::prelude::*;
 use sea_orm::sea_query::Expr;
 use sea_orm::ActiveValue::Set;
 use sea_orm::{QuerySelect, TransactionTrait};
+use serde::de::DeserializeOwned;
 use sha2::{Digest, Sha256};
 use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt, BufReader};
 use tokio::sync::{OnceCell, Semaphore};
@@ -29,23 +31,30 @@ use tokio_util::io::StreamReader;
 use tracing::instrument;
 use uuid::Uuid;
 
-use crate::config::CompressionType;
+use super::possession_challenge::take_challenge;
+use crate::config::{ChunkingConfig, CompressionType};
 use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::nar::{collect_nar_ranges, stream_nar_decompressed};
 use crate::narinfo::Compression;
+use crate::quota::{check_quota, Addition};
 use crate::{RequestState, State};
+use attic::api::v1::possession_challenge::{PossessionProof, RangeCollector};
 use attic::api::v1::upload_path::{
-    UploadPathNarInfo, UploadPathResult, UploadPathResultKind, ATTIC_NAR_INFO,
-    ATTIC_NAR_INFO_PREAMBLE_SIZE,
+    NarChunk, UploadChunkedPathInfo, UploadPathNarInfo, UploadPathResult, UploadPathResultKind,
+    ATTIC_NAR_INFO, ATTIC_NAR_INFO_PREAMBLE_SIZE, ATTIC_POSSESSION_PROOF,
 };
+use attic::api::v1::webhook::WebhookEventData;
 use attic::chunking::chunk_stream;
 use attic::hash::Hash;
+use attic::nar::{Error as NarError, NarListing, NarListingStream, ReferenceScanStream};
 use attic::stream::{read_chunk_async, StreamHasher};
 use attic::util::Finally;
 
 use crate::database::entity::cache;
-use crate::database::entity::chunk::{self, ChunkState, Entity as Chunk};
+use crate::database::entity::chunk::{self, ChunkModel, ChunkState, Entity as Chunk};
 use crate::database::entity::chunkref::{self, Entity as ChunkRef};
 use crate::database::entity::nar::{self, Entity as Nar, NarState};
+use crate::database::entity::narlisting::{self, Entity as NarListingEntity};
 use crate::database::entity::object::{self, Entity as Object, InsertExt};
 use crate::database::entity::Json as DbJson;
 use crate::database::{AtticDatabase, ChunkGuard, NarGuard};
@@ -60,6 +69,20 @@ const CONCURRENT_CHUNK_UPLOADS: usize = 10;
 /// TODO: Make this configurable
 const MAX_NAR_INFO_SIZE: usize = 1 * 1024 * 1024; // 1 MiB
 
+/// The maximum size of the upload info JSON of a chunked upload.
+///
+/// The chunk list of a large NAR can be several MiBs.
+const MAX_CHUNKED_NAR_INFO_SIZE: usize = 32 * 1024 * 1024; // 32 MiB
+
+/// Number of chunk references to insert at once.
+const CHUNK_REF_BATCH_SIZE: usize = 1000;
+
+/// Store path hash of placeholder paths, which are never references.
+///
+/// Paths like `/nix/store/00000000000000000000000000000000-foo` are
+/// commonly embedded in place of paths that are filled in later.
+const PLACEHOLDER_HASH: &str = "00000000000000000000000000000000";
+
 type CompressorFn<C> = Box<dyn FnOnce(C) -> Box<dyn AsyncRead + Unpin + Send> + Send>;
 
 /// Data of a chunk.
@@ -104,6 +127,15 @@ struct CompressionStream {
     file_compute: Arc<OnceCell<(DigestOutput<Sha256>, usize)>>,
 }
 
+/// A NAR being inspected as it's streamed.
+///
+/// The NAR is always parsed to build the file listing. If the cache
+/// validates NARs, it's also scanned for references.
+struct NarInspection {
+    listing: Arc<OnceCell<Result<NarListing, NarError>>>,
+    references: Option<Arc<OnceCell<BTreeSet<String>>>>,
+}
+
 trait UploadPathNarInfoExt {
     fn to_active_model(&self) -> object::ActiveModel;
 }
@@ -131,39 +163,7 @@ pub(crate) async fn upload_path(
     let upload_info: UploadPathNarInfo = {
         if let Some(preamble_size_bytes) = headers.get(ATTIC_NAR_INFO_PREAMBLE_SIZE) {
             // Read from the beginning of the PUT body
-            let preamble_size: usize = preamble_size_bytes
-                .to_str()
-                .map_err(|_| {
-                    ErrorKind::RequestError(anyhow!(
-                        "{} has invalid encoding",
-                        ATTIC_NAR_INFO_PREAMBLE_SIZE
-                    ))
-                })?
-                .parse()
-                .map_err(|_| {
-                    ErrorKind::RequestError(anyhow!(
-                        "{} must be a valid unsigned integer",
-                        ATTIC_NAR_INFO_PREAMBLE_SIZE
-                    ))
-                })?;
-
-            if preamble_size > MAX_NAR_INFO_SIZE {
-                return Err(ErrorKind::RequestError(anyhow!("Upload info is too large")).into());
-            }
-
-            let buf = BytesMut::with_capacity(preamble_size);
-            let preamble = read_chunk_async(&mut stream, buf)
-                .await
-                .map_err(|e| ErrorKind::RequestError(e.into()))?;
-
-            if preamble.len() != preamble_size {
-                return Err(ErrorKind::RequestError(anyhow!(
-                    "Upload info doesn't match specified size"
-                ))
-                .into());
-            }
-
-            serde_json::from_slice(&preamble).map_err(ServerError::request_error)?
+            read_preamble(&mut stream, preamble_size_bytes, MAX_NAR_INFO_SIZE).await?
         } else if let Some(nar_info_bytes) = headers.get(ATTIC_NAR_INFO) {
             // Read from X-Attic-Nar-Info header
             serde_json::from_slice(nar_info_bytes.as_bytes()).map_err(ServerError::request_error)?
@@ -184,54 +184,257 @@ pub(crate) async fn upload_path(
 
     let username = req_state.auth.username().map(str::to_string);
 
+    let cache_name = cache_name.clone();
+    let cache_id = cache.id;
+    let webhooks = cache.webhooks.0.clone();
+    let event = path_pushed_event(&upload_info, username.clone());
+
+    let result = if let Some(proof) = headers.get(ATTIC_POSSESSION_PROOF) {
+        let proof: PossessionProof =
+            serde_json::from_slice(proof.as_bytes()).map_err(ServerError::request_error)?;
+
+        upload_path_with_proof(&state, cache, upload_info, proof, username).await?
+    } else {
+        upload_path_to_cache(&state, cache, upload_info, stream, username).await?
+    };
+
+    state
+        .webhooks
+        .notify(database, cache_id, &cache_name, &webhooks, event)
+        .await;
+
+    Ok(result)
+}
+
+/// Uploads an object to an authorized cache.
+///
+/// This is also used to ingest NARs fetched from upstream caches.
+pub(crate) async fn upload_path_to_cache(
+    state: &State,
+    cache: cache::Model,
+    upload_info: UploadPathNarInfo,
+    stream: impl AsyncRead + Send + Unpin + 'static,
+    username: Option<String>,
+) -> ServerResult<Json<UploadPathResult>> {
+    let database = state.database().await?;
+
+    check_quota(state, &cache, &upload_addition(&upload_info)).await?;
+
     // Try to acquire a lock on an existing NAR
-    let existing_nar = database.find_and_lock_nar(&upload_info.nar_hash).await?;
-    match existing_nar {
+    match find_and_lock_complete_nar(database, &upload_info.nar_hash).await? {
         Some(existing_nar) => {
-            // Deduplicate?
-            let missing_chunk = ChunkRef::find()
-                .filter(chunkref::Column::NarId.eq(existing_nar.id))
-                .filter(chunkref::Column::ChunkId.is_null())
-                .limit(1)
-                .one(database)
-                .await
-                .map_err(ServerError::database_error)?;
-
-            if missing_chunk.is_some() {
-                // Need to repair
-                upload_path_new(username, cache, upload_info, stream, database, &state).await
-            } else {
-                // Can actually be deduplicated
-                upload_path_dedup(
-                    username,
-                    cache,
-                    upload_info,
-                    stream,
-                    database,
-                    &state,
-                    existing_nar,
-                )
-                .await
-            }
+            // Can actually be deduplicated
+            upload_path_dedup(
+                username,
+                cache,
+                upload_info,
+                stream,
+                state,
+                existing_nar,
+                false,
+            )
+            .await
         }
         None => {
-            // New NAR
-            upload_path_new(username, cache, upload_info, stream, database, &state).await
+            // New NAR, or the existing NAR needs to be repaired
+            upload_path_new(username, cache, upload_info, stream, database, state).await
         }
     }
 }
 
+/// Uploads an object by proving possession of an existing NAR.
+///
+/// The same error is returned whether the NAR doesn't exist or the
+/// proof is incorrect. Checking a proof against an existing NAR takes
+/// longer, so the response time still reveals whether the NAR is in
+/// the global cache.
+async fn upload_path_with_proof(
+    state: &State,
+    cache: cache::Model,
+    upload_info: UploadPathNarInfo,
+    proof: PossessionProof,
+    username: Option<String>,
+) -> ServerResult<Json<UploadPathResult>> {
+    let database = state.database().await?;
+
+    check_quota(state, &cache, &upload_addition(&upload_info)).await?;
+
+    let challenge = take_challenge(database, &proof.nonce).await?;
+    if challenge.cache_id != cache.id
+        || challenge.nar_hash != upload_info.nar_hash.to_typed_base16()
+        || challenge.nar_size as usize != upload_info.nar_size
+    {
+        return Err(ErrorKind::PossessionProofRejected.into());
+    }
+
+    let Some(existing_nar) = find_and_lock_complete_nar(database, &upload_info.nar_hash).await?
+    else {
+        return Err(ErrorKind::PossessionProofRejected.into());
+    };
+
+    let Some(chunks) = database
+        .find_chunks_by_nar_id(existing_nar.id)
+        .await?
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Err(ErrorKind::PossessionProofRejected.into());
+    };
+
+    let storage = state.storage().await?.clone();
+    let mut collector = RangeCollector::new(&challenge.ranges.0);
+    collect_nar_ranges(storage, chunks, &mut collector).await?;
+
+    if existing_nar.nar_size as usize != upload_info.nar_size
+        || collector.finish(&challenge.nonce) != proof.response
+    {
+        return Err(ErrorKind::PossessionProofRejected.into());
+    }
+
+    upload_path_dedup(
+        username,
+        cache,
+        upload_info,
+        tokio::io::empty(),
+        state,
+        existing_nar,
+        true,
+    )
+    .await
+}
+
+/// Uploads a new object to the cache as a list of chunks.
+///
+/// The client chunks the NAR locally with the chunking parameters
+/// advertised in the cache config, and only sends the chunks that
+/// `get-missing-chunks` reports as missing. The NAR is assembled from
+/// the new and existing chunks, then verified against the NAR hash.
+///
+/// If proof of possession is required, only existing chunks that are
+/// part of NARs in the cache can be referenced without sending them.
+/// An existing NAR with the same chunks is then reused.
+#[instrument(skip_all)]
+pub(crate) async fn upload_chunked_path(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    headers: HeaderMap,
+    body: Body,
+) -> ServerResult<Json<UploadPathResult>> {
+    let stream = body.into_data_stream();
+    let mut stream = StreamReader::new(
+        stream.map(|r| r.map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))),
+    );
+
+    let preamble_size_bytes = headers.get(ATTIC_NAR_INFO_PREAMBLE_SIZE).ok_or_else(|| {
+        ErrorKind::RequestError(anyhow!("{} must be set", ATTIC_NAR_INFO_PREAMBLE_SIZE))
+    })?;
+    let upload_info: UploadChunkedPathInfo =
+        read_preamble(&mut stream, preamble_size_bytes, MAX_CHUNKED_NAR_INFO_SIZE).await?;
+
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(
+            database,
+            &upload_info.nar_info.cache,
+            |cache, permission| {
+                permission.require_push()?;
+                Ok(cache)
+            },
+        )
+        .await?;
+
+    let username = req_state.auth.username().map(str::to_string);
+
+    validate_chunk_list(&state.config.chunking, &upload_info)?;
+    check_quota(&state, &cache, &upload_addition(&upload_info.nar_info)).await?;
+
+    let cache_name = upload_info.nar_info.cache.clone();
+    let cache_id = cache.id;
+    let webhooks = cache.webhooks.0.clone();
+    let event = path_pushed_event(&upload_info.nar_info, username.clone());
+
+    let existing_nar = find_and_lock_complete_nar(database, &upload_info.nar_info.nar_hash).await?;
+
+    let result = match existing_nar {
+        // Without proof of possession, there's no need to look at the chunks
+        Some(existing_nar) if !state.config.require_proof_of_possession => {
+            upload_path_dedup(
+                username,
+                cache,
+                upload_info.nar_info,
+                tokio::io::empty(),
+                &state,
+                existing_nar,
+                false,
+            )
+            .await?
+        }
+        Some(existing_nar)
+            if nar_has_chunks(database, &existing_nar, &upload_info.chunks).await? =>
+        {
+            upload_chunked_path_with_proof(
+                username,
+                cache,
+                upload_info,
+                stream,
+                &state,
+                existing_nar,
+            )
+            .await?
+        }
+        _ => {
+            upload_chunked_path_new(username, cache, upload_info, stream, database, &state).await?
+        }
+    };
+
+    state
+        .webhooks
+        .notify(database, cache_id, &cache_name, &webhooks, event)
+        .await;
+
+    Ok(result)
+}
+
+/// Returns the object added to a cache by an upload.
+fn upload_addition(upload_info: &UploadPathNarInfo) -> Addition<'_> {
+    Addition {
+        objects: 1,
+        nar_size: upload_info.nar_size as u64,
+        store_path_hashes: vec![upload_info.store_path_hash.as_str()],
+    }
+}
+
+/// Returns the event of pushing a path.
+fn path_pushed_event(
+    upload_info: &UploadPathNarInfo,
+    username: Option<String>,
+) -> WebhookEventData {
+    WebhookEventData::PathPushed {
+        store_path: upload_info.store_path.clone(),
+        nar_hash: upload_info.nar_hash.to_typed_base32(),
+        nar_size: upload_info.nar_size as u64,
+        pushed_by: username,
+    }
+}
+
 /// Uploads a path when there is already a matching NAR in the global cache.
+///
+/// If proof of possession is required and the client hasn't proven
+/// possession otherwise, the NAR is read from the stream and verified.
 async fn upload_path_dedup(
     username: Option<String>,
     cache: cache::Model,
     upload_info: UploadPathNarInfo,
-    stream: impl AsyncRead + Unpin,
-    database: &DatabaseConnection,
+    stream: impl AsyncRead + Send + Unpin + 'static,
     state: &State,
     existing_nar: NarGuard,
+    possession_proven: bool,
 ) -> ServerResult<Json<UploadPathResult>> {
-    if state.config.require_proof_of_possession {
+    let database = state.database().await?;
+
+    if state.config.require_proof_of_possession && !possession_proven {
+        let (stream, inspection) = inspect_nar(&cache, stream);
         let (mut stream, nar_compute) = StreamHasher::new(stream, Sha256::new());
         tokio::io::copy(&mut stream, &mut tokio::io::sink())
             .await
@@ -248,6 +451,19 @@ async fn upload_path_dedup(
         {
             return Err(ErrorKind::RequestError(anyhow!("Bad NAR Hash or Size")).into());
         }
+
+        inspection.validate(&upload_info)?;
+    } else if cache.validate_nars {
+        // The references depend on the store directory of the cache,
+        // so we can't rely on an earlier validation of the same NAR
+        let chunks = database
+            .find_chunks_by_nar_id(existing_nar.id)
+            .await?
+            .into_iter()
+            .collect::<Option<VecDeque<_>>>()
+            .ok_or(ErrorKind::IncompleteNar)?;
+
+        validate_stored_nar(state, &cache, &upload_info, chunks).await?;
     }
 
     // Finally...
@@ -287,12 +503,17 @@ async fn upload_path_dedup(
 
     txn.commit().await.map_err(ServerError::database_error)?;
 
+    state.events.path_pushed(cache.id, &upload_info);
+
+    // A missing file hash is backfilled when the NAR is first downloaded
+    let file_size = existing_nar.file_size.map(|size| size as usize);
+
     // Ensure it's not unlocked earlier
     drop(existing_nar);
 
     Ok(Json(UploadPathResult {
         kind: UploadPathResultKind::Deduplicated,
-        file_size: None, // TODO: Sum the chunks
+        file_size,
         frac_deduplicated: None,
     }))
 }
@@ -376,6 +597,7 @@ async fn upload_path_new_chunked(
     });
 
     let stream = stream.take(upload_info.nar_size as u64);
+    let (stream, inspection) = inspect_nar(&cache, stream);
     let (stream, nar_compute) = StreamHasher::new(stream, Sha256::new());
     let mut chunks = chunk_stream(
         stream,
@@ -443,6 +665,8 @@ async fn upload_path_new_chunked(
         return Err(ErrorKind::RequestError(anyhow!("Bad NAR Hash or Size")).into());
     }
 
+    inspection.validate(&upload_info)?;
+
     // Wait for all uploads to complete
     let chunks: Vec<UploadChunkResult> = join_all(futures)
         .await
@@ -464,6 +688,317 @@ async fn upload_path_new_chunked(
                 )
             });
 
+    // Finally...
+    let txn = database
+        .begin()
+        .await
+        .map_err(ServerError::database_error)?;
+
+    // Set num_chunks and mark the NAR as Valid
+    //
+    // A single-chunk NAR is served as-is, so its file hash is that of the
+    // chunk. Otherwise, the hash is recorded when the NAR is first served.
+    let file_hash = match chunks.as_slice() {
+        [chunk] => chunk.guard.file_hash.clone(),
+        _ => None,
+    };
+
+    Nar::update(nar::ActiveModel {
+        id: Set(nar_id),
+        state: Set(NarState::Valid),
+        num_chunks: Set(chunks.len() as i32),
+        file_hash: Set(file_hash),
+        file_size: Set(Some(file_size as i64)),
+        ..Default::default()
+    })
+    .exec(&txn)
+    .await
+    .map_err(ServerError::database_error)?;
+
+    // Save the file listing
+    if let Some(listing) = to_nar_listing_model(nar_id, inspection.listing.get()) {
+        NarListingEntity::insert(listing)
+            .exec(&txn)
+            .await
+            .map_err(ServerError::database_error)?;
+    }
+
+    // Create a mapping granting the local cache access to the NAR
+    Object::insert({
+        let mut new_object = upload_info.to_active_model();
+        new_object.cache_id = Set(cache.id);
+        new_object.nar_id = Set(nar_id);
+        new_object.created_at = Set(Utc::now());
+        new_object.created_by = Set(username);
+        new_object
+    })
+    .on_conflict_do_update()
+    .exec(&txn)
+    .await
+    .map_err(ServerError::database_error)?;
+
+    txn.commit().await.map_err(ServerError::database_error)?;
+
+    state.events.path_pushed(cache.id, &upload_info);
+
+    cleanup.cancel();
+
+    Ok(Json(UploadPathResult {
+        kind: UploadPathResultKind::Uploaded,
+        file_size: Some(file_size),
+
+        // Currently, frac_deduplicated is computed from size before compression
+        frac_deduplicated: Some(deduplicated_size as f64 / *nar_size as f64),
+    }))
+}
+
+/// Uploads a path from a list of chunks making up an existing NAR.
+///
+/// The client proves possession of the NAR by sending the chunks that
+/// aren't part of NARs in the cache. Since the chunks are the same as
+/// those of the existing NAR, their hashes and sizes prove the NAR hash
+/// without reading the NAR from storage.
+async fn upload_chunked_path_with_proof(
+    username: Option<String>,
+    cache: cache::Model,
+    upload_info: UploadChunkedPathInfo,
+    mut stream: impl AsyncRead + Send + Unpin + 'static,
+    state: &State,
+    existing_nar: NarGuard,
+) -> ServerResult<Json<UploadPathResult>> {
+    let database = state.database().await?;
+    let compression: Compression = state.config.compression.r#type.into();
+
+    let UploadChunkedPathInfo {
+        nar_info: upload_info,
+        chunks,
+    } = upload_info;
+
+    let reused = reused_chunks(&chunks);
+    let existing = database
+        .find_existing_chunk_hashes(&reused, compression, Some(cache.id))
+        .await?;
+
+    if let Some(hash) = reused
+        .iter()
+        .find(|hash| !existing.contains(&hash.to_typed_base16()))
+    {
+        return Err(missing_chunk_error(hash));
+    }
+
+    for chunk in chunks.iter().filter(|chunk| chunk.included) {
+        read_included_chunk(&mut stream, chunk).await?;
+    }
+
+    upload_path_dedup(
+        username,
+        cache,
+        upload_info,
+        tokio::io::empty(),
+        state,
+        existing_nar,
+        true,
+    )
+    .await
+}
+
+/// Uploads a path from a list of new and existing chunks.
+async fn upload_chunked_path_new(
+    username: Option<String>,
+    cache: cache::Model,
+    upload_info: UploadChunkedPathInfo,
+    mut stream: impl AsyncRead + Send + Unpin + 'static,
+    database: &DatabaseConnection,
+    state: &State,
+) -> ServerResult<Json<UploadPathResult>> {
+    let compression_config = &state.config.compression;
+    let compression_type = compression_config.r#type;
+    let compression_level = compression_config.level();
+    let compression: Compression = compression_type.into();
+
+    let UploadChunkedPathInfo {
+        nar_info: upload_info,
+        chunks,
+    } = upload_info;
+
+    let nar_size_db = i64::try_from(upload_info.nar_size).map_err(ServerError::request_error)?;
+
+    // Lock the existing chunks that the client didn't send
+    let reused = reused_chunks(&chunks);
+
+    let cache_id = if state.config.require_proof_of_possession {
+        Some(cache.id)
+    } else {
+        None
+    };
+    let existing = &database
+        .find_existing_chunk_hashes(&reused, compression, cache_id)
+        .await?;
+
+    let mut guards: HashMap<String, ChunkGuard> = stream::iter(reused)
+        .map(|hash| async move {
+            let guard = if existing.contains(&hash.to_typed_base16()) {
+                database.find_and_lock_chunk(&hash, compression).await?
+            } else {
+                None
+            };
+
+            guard
+                .map(|guard| (guard.chunk_hash.clone(), guard))
+                .ok_or_else(|| missing_chunk_error(&hash))
+        })
+        .buffer_unordered(CONCURRENT_CHUNK_UPLOADS)
+        .try_collect()
+        .await?;
+
+    // Create a pending NAR entry
+    let nar_id = {
+        let model = nar::ActiveModel {
+            state: Set(NarState::PendingUpload),
+            compression: Set(compression.to_string()),
+
+            nar_hash: Set(upload_info.nar_hash.to_typed_base16()),
+            nar_size: Set(nar_size_db),
+
+            num_chunks: Set(0),
+
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        };
+
+        let insertion = Nar::insert(model)
+            .exec(database)
+            .await
+            .map_err(ServerError::database_error)?;
+
+        insertion.last_insert_id
+    };
+
+    let cleanup = Finally::new({
+        let database = database.clone();
+        let nar_model = nar::ActiveModel {
+            id: Set(nar_id),
+            ..Default::default()
+        };
+
+        async move {
+            tracing::warn!("Error occurred - Cleaning up NAR entry");
+
+            if let Err(e) = Nar::delete(nar_model).exec(&database).await {
+                tracing::warn!("Failed to unregister failed NAR: {}", e);
+            }
+        }
+    });
+
+    // Upload the chunks that the client sent
+    let upload_chunk_limit = Arc::new(Semaphore::new(CONCURRENT_CHUNK_UPLOADS));
+    let mut futures = Vec::new();
+
+    for chunk in chunks.iter().filter(|chunk| chunk.included) {
+        let data = read_included_chunk(&mut stream, chunk).await?;
+
+        // Wait for a permit before spawning
+        let permit = upload_chunk_limit.clone().acquire_owned().await.unwrap();
+        futures.push({
+            let database = database.clone();
+            let state = state.clone();
+            let require_proof_of_possession = state.config.require_proof_of_possession;
+
+            spawn(async move {
+                let chunk = upload_chunk(
+                    data,
+                    compression_type,
+                    compression_level,
+                    database,
+                    state,
+                    require_proof_of_possession,
+                )
+                .await?;
+
+                drop(permit);
+                Ok(chunk)
+            })
+        });
+    }
+
+    // Wait for all uploads to complete
+    let uploaded: Vec<UploadChunkResult> = join_all(futures)
+        .await
+        .into_iter()
+        .map(|join_result| join_result.unwrap())
+        .collect::<ServerResult<Vec<_>>>()?;
+
+    let mut new_chunks = HashSet::new();
+    for chunk in uploaded {
+        if !chunk.deduplicated {
+            new_chunks.insert(chunk.guard.chunk_hash.clone());
+        }
+
+        guards.insert(chunk.guard.chunk_hash.clone(), chunk.guard);
+    }
+
+    // Create mappings from the NAR to the chunks
+    let nar_chunks: VecDeque<ChunkModel> = chunks
+        .iter()
+        .map(|chunk| (*guards[&chunk.hash.to_typed_base16()]).clone())
+        .collect();
+
+    let chunkrefs: Vec<chunkref::ActiveModel> = nar_chunks
+        .iter()
+        .enumerate()
+        .map(|(seq, chunk)| chunkref::ActiveModel {
+            nar_id: Set(nar_id),
+            seq: Set(seq as i32),
+            chunk_id: Set(Some(chunk.id)),
+            chunk_hash: Set(chunk.chunk_hash.clone()),
+            compression: Set(chunk.compression.clone()),
+            ..Default::default()
+        })
+        .collect();
+
+    for batch in chunkrefs.chunks(CHUNK_REF_BATCH_SIZE) {
+        ChunkRef::insert_many(batch.iter().cloned())
+            .exec(database)
+            .await
+            .map_err(ServerError::database_error)?;
+    }
+
+    let file_size: usize = nar_chunks
+        .iter()
+        .map(|chunk| chunk.file_size.unwrap() as usize)
+        .sum();
+
+    // A single-chunk NAR is served as-is, so its file hash is that of the
+    // chunk. Otherwise, the hash is recorded when the NAR is first served.
+    let file_hash = match nar_chunks.len() {
+        1 => nar_chunks[0].file_hash.clone(),
+        _ => None,
+    };
+
+    let deduplicated_size: usize = chunks
+        .iter()
+        .filter(|chunk| !new_chunks.contains(&chunk.hash.to_typed_base16()))
+        .map(|chunk| chunk.size)
+        .sum();
+
+    // Confirm that the NAR Hash and Size are correct
+    let storage = state.storage().await?.clone();
+    let stream = StreamReader::new(stream_nar_decompressed(storage, nar_chunks));
+    let (stream, inspection) = inspect_nar(&cache, stream);
+    let (mut stream, nar_compute) = StreamHasher::new(stream, Sha256::new());
+    tokio::io::copy(&mut stream, &mut tokio::io::sink())
+        .await
+        .map_err(ServerError::storage_error)?;
+
+    let (nar_hash, nar_size) = nar_compute.get().unwrap();
+    let nar_hash = Hash::Sha256(nar_hash.as_slice().try_into().unwrap());
+
+    if nar_hash != upload_info.nar_hash || *nar_size != upload_info.nar_size {
+        return Err(ErrorKind::RequestError(anyhow!("Bad NAR Hash or Size")).into());
+    }
+
+    inspection.validate(&upload_info)?;
+
     // Finally...
     let txn = database
         .begin()
@@ -475,12 +1010,22 @@ async fn upload_path_new_chunked(
         id: Set(nar_id),
         state: Set(NarState::Valid),
         num_chunks: Set(chunks.len() as i32),
+        file_hash: Set(file_hash),
+        file_size: Set(Some(file_size as i64)),
         ..Default::default()
     })
     .exec(&txn)
     .await
     .map_err(ServerError::database_error)?;
 
+    // Save the file listing
+    if let Some(listing) = to_nar_listing_model(nar_id, inspection.listing.get()) {
+        NarListingEntity::insert(listing)
+            .exec(&txn)
+            .await
+            .map_err(ServerError::database_error)?;
+    }
+
     // Create a mapping granting the local cache access to the NAR
     Object::insert({
         let mut new_object = upload_info.to_active_model();
@@ -497,8 +1042,13 @@ async fn upload_path_new_chunked(
 
     txn.commit().await.map_err(ServerError::database_error)?;
 
+    state.events.path_pushed(cache.id, &upload_info);
+
     cleanup.cancel();
 
+    // Ensure they're not unlocked earlier
+    drop(guards);
+
     Ok(Json(UploadPathResult {
         kind: UploadPathResultKind::Uploaded,
         file_size: Some(file_size),
@@ -525,6 +1075,7 @@ async fn upload_path_new_unchunked(
 
     // Upload the entire NAR as a single chunk
     let stream = stream.take(upload_info.nar_size as u64);
+    let (stream, inspection) = inspect_nar(&cache, stream);
     let data = ChunkData::Stream(
         Box::new(stream),
         upload_info.nar_hash.clone(),
@@ -541,6 +1092,15 @@ async fn upload_path_new_unchunked(
     .await?;
     let file_size = chunk.guard.file_size.unwrap() as usize;
 
+    // The stream isn't consumed if the chunk was deduplicated without
+    // proof of possession, in which case we validate the stored chunk
+    if inspection.is_complete() {
+        inspection.validate(&upload_info)?;
+    } else {
+        let chunks = VecDeque::from([(*chunk.guard).clone()]);
+        validate_stored_nar(state, &cache, &upload_info, chunks).await?;
+    }
+
     // Finally...
     let txn = database
         .begin()
@@ -556,6 +1116,9 @@ async fn upload_path_new_unchunked(
             nar_hash: Set(upload_info.nar_hash.to_typed_base16()),
             nar_size: Set(chunk.guard.chunk_size),
 
+            file_hash: Set(chunk.guard.file_hash.clone()),
+            file_size: Set(chunk.guard.file_size),
+
             num_chunks: Set(1),
 
             created_at: Set(Utc::now()),
@@ -570,6 +1133,18 @@ async fn upload_path_new_unchunked(
         insertion.last_insert_id
     };
 
+    // Save the file listing
+    //
+    // The listing is only available if the stream was consumed, which
+    // isn't the case if the chunk was deduplicated without proof of
+    // possession.
+    if let Some(listing) = to_nar_listing_model(nar_id, inspection.listing.get()) {
+        NarListingEntity::insert(listing)
+            .exec(&txn)
+            .await
+            .map_err(ServerError::database_error)?;
+    }
+
     // Create a mapping from the NAR to the chunk
     ChunkRef::insert(chunkref::ActiveModel {
         nar_id: Set(nar_id),
@@ -599,6 +1174,8 @@ async fn upload_path_new_unchunked(
 
     txn.commit().await.map_err(ServerError::database_error)?;
 
+    state.events.path_pushed(cache.id, &upload_info);
+
     Ok(Json(UploadPathResult {
         kind: UploadPathResultKind::Uploaded,
         file_size: Some(file_size),
@@ -772,6 +1349,237 @@ async fn upload_chunk(
     })
 }
 
+/// Reads the upload info at the beginning of the PUT body.
+async fn read_preamble<T: DeserializeOwned>(
+    stream: &mut (impl AsyncRead + Send + Unpin),
+    preamble_size_bytes: &HeaderValue,
+    max_size: usize,
+) -> ServerResult<T> {
+    let preamble_size: usize = preamble_size_bytes
+        .to_str()
+        .map_err(|_| {
+            ErrorKind::RequestError(anyhow!(
+                "{} has invalid encoding",
+                ATTIC_NAR_INFO_PREAMBLE_SIZE
+            ))
+        })?
+        .parse()
+        .map_err(|_| {
+            ErrorKind::RequestError(anyhow!(
+                "{} must be a valid unsigned integer",
+                ATTIC_NAR_INFO_PREAMBLE_SIZE
+            ))
+        })?;
+
+    if preamble_size > max_size {
+        return Err(ErrorKind::RequestError(anyhow!("Upload info is too large")).into());
+    }
+
+    let buf = BytesMut::with_capacity(preamble_size);
+    let preamble = read_chunk_async(stream, buf)
+        .await
+        .map_err(|e| ErrorKind::RequestError(e.into()))?;
+
+    if preamble.len() != preamble_size {
+        return Err(
+            ErrorKind::RequestError(anyhow!("Upload info doesn't match specified size")).into(),
+        );
+    }
+
+    serde_json::from_slice(&preamble).map_err(ServerError::request_error)
+}
+
+/// Checks that a chunk list can make up the NAR.
+fn validate_chunk_list(
+    chunking_config: &ChunkingConfig,
+    upload_info: &UploadChunkedPathInfo,
+) -> ServerResult<()> {
+    if chunking_config.nar_size_threshold == 0 {
+        return Err(ErrorKind::RequestError(anyhow!("Chunking is disabled on this server")).into());
+    }
+
+    let mut total_size: usize = 0;
+    for chunk in &upload_info.chunks {
+        if chunk.size == 0 || chunk.size > chunking_config.max_size {
+            return Err(ErrorKind::RequestError(anyhow!(
+                "Chunk {} has an invalid size",
+                chunk.hash.to_typed_base16()
+            ))
+            .into());
+        }
+
+        total_size = total_size.saturating_add(chunk.size);
+    }
+
+    if total_size != upload_info.nar_info.nar_size {
+        return Err(
+            ErrorKind::RequestError(anyhow!("The chunks don't add up to the NAR size")).into(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the distinct chunks that the client didn't send.
+fn reused_chunks(chunks: &[NarChunk]) -> Vec<Hash> {
+    let included: HashSet<String> = chunks
+        .iter()
+        .filter(|chunk| chunk.included)
+        .map(|chunk| chunk.hash.to_typed_base16())
+        .collect();
+
+    let mut seen = HashSet::new();
+    chunks
+        .iter()
+        .map(|chunk| &chunk.hash)
+        .filter(|hash| {
+            let hash = hash.to_typed_base16();
+            !included.contains(&hash) && seen.insert(hash)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Reads a chunk that the client sent and checks its hash and size.
+async fn read_included_chunk(
+    stream: &mut (impl AsyncRead + Send + Unpin),
+    chunk: &NarChunk,
+) -> ServerResult<ChunkData> {
+    let buf = BytesMut::with_capacity(chunk.size);
+    let bytes = read_chunk_async(stream, buf)
+        .await
+        .map_err(ServerError::request_error)?;
+
+    let data = ChunkData::Bytes(bytes);
+    if data.size() != chunk.size || data.hash() != chunk.hash {
+        return Err(ErrorKind::RequestError(anyhow!("Bad chunk hash or size")).into());
+    }
+
+    Ok(data)
+}
+
+/// Returns whether a NAR is made up of the given chunks, in order.
+async fn nar_has_chunks(
+    database: &DatabaseConnection,
+    nar: &NarGuard,
+    chunks: &[NarChunk],
+) -> ServerResult<bool> {
+    let nar_chunks = database.find_chunks_by_nar_id(nar.id).await?;
+
+    Ok(nar_chunks.len() == chunks.len()
+        && nar_chunks.iter().zip(chunks).all(|(nar_chunk, chunk)| {
+            nar_chunk.as_ref().is_some_and(|nar_chunk| {
+                nar_chunk.chunk_hash == chunk.hash.to_typed_base16()
+                    && nar_chunk.chunk_size as usize == chunk.size
+            })
+        }))
+}
+
+/// Returns the error for a chunk that the client should have sent.
+fn missing_chunk_error(hash: &Hash) -> ServerError {
+    ErrorKind::RequestError(anyhow!("Chunk {} is missing", hash.to_typed_base16())).into()
+}
+
+/// Retrieves and locks a NAR that can be deduplicated against.
+///
+/// NARs with missing chunks need to be repaired and are not returned.
+async fn find_and_lock_complete_nar(
+    database: &DatabaseConnection,
+    nar_hash: &Hash,
+) -> ServerResult<Option<NarGuard>> {
+    let Some(existing_nar) = database.find_and_lock_nar(nar_hash).await? else {
+        return Ok(None);
+    };
+
+    let missing_chunk = ChunkRef::find()
+        .filter(chunkref::Column::NarId.eq(existing_nar.id))
+        .filter(chunkref::Column::ChunkId.is_null())
+        .limit(1)
+        .one(database)
+        .await
+        .map_err(ServerError::database_error)?;
+
+    if missing_chunk.is_some() {
+        Ok(None)
+    } else {
+        Ok(Some(existing_nar))
+    }
+}
+
+/// Inspects a NAR as it's streamed.
+fn inspect_nar(
+    cache: &cache::Model,
+    stream: impl AsyncRead + Send + Unpin + 'static,
+) -> (Box<dyn AsyncRead + Send + Unpin>, NarInspection) {
+    let (stream, listing) = NarListingStream::new(stream);
+
+    if cache.validate_nars {
+        let (stream, references) = ReferenceScanStream::new(stream, &cache.store_dir);
+        let inspection = NarInspection {
+            listing,
+            references: Some(references),
+        };
+
+        (Box::new(stream), inspection)
+    } else {
+        let inspection = NarInspection {
+            listing,
+            references: None,
+        };
+
+        (Box::new(stream), inspection)
+    }
+}
+
+/// Validates a NAR in the storage backend if the cache validates NARs.
+async fn validate_stored_nar(
+    state: &State,
+    cache: &cache::Model,
+    upload_info: &UploadPathNarInfo,
+    chunks: VecDeque<ChunkModel>,
+) -> ServerResult<()> {
+    if !cache.validate_nars {
+        return Ok(());
+    }
+
+    let storage = state.storage().await?.clone();
+    let stream = StreamReader::new(stream_nar_decompressed(storage, chunks));
+    let (mut stream, inspection) = inspect_nar(cache, stream);
+    tokio::io::copy(&mut stream, &mut tokio::io::sink())
+        .await
+        .map_err(ServerError::storage_error)?;
+
+    inspection.validate(upload_info)
+}
+
+/// Returns the model of a NAR listing, if the listing was built successfully.
+fn to_nar_listing_model(
+    nar_id: i64,
+    listing: Option<&Result<NarListing, NarError>>,
+) -> Option<narlisting::ActiveModel> {
+    let listing = match listing? {
+        Ok(listing) => listing,
+        Err(e) => {
+            tracing::warn!("Failed to build NAR listing: {}", e);
+            return None;
+        }
+    };
+
+    let listing = match serde_json::to_string(listing) {
+        Ok(listing) => listing,
+        Err(e) => {
+            tracing::warn!("Failed to serialize NAR listing: {}", e);
+            return None;
+        }
+    };
+
+    Some(narlisting::ActiveModel {
+        nar_id: Set(nar_id),
+        listing: Set(listing),
+        ..Default::default()
+    })
+}
+
 /// Returns a compressor function that takes some stream as input.
 fn get_compressor_fn<C: AsyncBufRead + Unpin + Send + 'static>(
     ctype: CompressionType,
@@ -890,6 +1698,58 @@ impl CompressionStream {
     }
 }
 
+impl NarInspection {
+    /// Returns whether the entire NAR has been inspected.
+    fn is_complete(&self) -> bool {
+        self.listing.initialized()
+    }
+
+    /// Validates the NAR if the cache validates NARs.
+    ///
+    /// The NAR must be well-formed, and all store paths it references
+    /// must be declared. A self-reference and placeholder paths don't
+    /// need to be declared.
+    fn validate(&self, upload_info: &UploadPathNarInfo) -> ServerResult<()> {
+        let Some(references) = &self.references else {
+            return Ok(());
+        };
+
+        if let Some(Err(e)) = self.listing.get() {
+            return Err(ErrorKind::MalformedNar(e.clone()).into());
+        }
+
+        let references = references
+            .get()
+            .expect("The NAR hasn't been fully inspected");
+
+        let declared: HashSet<&str> = upload_info
+            .references
+            .iter()
+            .map(|base_name| {
+                base_name
+                    .split_once('-')
+                    .map_or(base_name.as_str(), |(hash, _)| hash)
+            })
+            .chain([upload_info.store_path_hash.as_str(), PLACEHOLDER_HASH])
+            .collect();
+
+        let undeclared: Vec<&str> = references
+            .iter()
+            .map(String::as_str)
+            .filter(|hash| !declared.contains(hash))
+            .collect();
+
+        if !undeclared.is_empty() {
+            return Err(ErrorKind::UndeclaredReferences {
+                paths: undeclared.join(", "),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
 impl UploadPathNarInfoExt for UploadPathNarInfo {
     fn to_active_model(&self) -> object::ActiveModel {
         object::ActiveModel {
@@ -903,3 +1763,85 @@ impl UploadPathNarInfoExt for UploadPathNarInfo {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use attic::nix_store::StorePathHash;
+
+    const DEP_HASH: &str = "7wq2j1dkrfqyz0zslf91gp07g1l3xivz";
+
+    /// Returns a NAR with a single regular file.
+    fn nar_file(contents: &[u8]) -> Vec<u8> {
+        let mut nar = Vec::new();
+        for s in [
+            b"nix-archive-1".as_slice(),
+            b"(",
+            b"type",
+            b"regular",
+            b"contents",
+            contents,
+            b")",
+        ] {
+            nar.extend_from_slice(&(s.len() as u64).to_le_bytes());
+            nar.extend_from_slice(s);
+            nar.resize(nar.len().next_multiple_of(8), 0);
+        }
+        nar
+    }
+
+    async fn inspect(nar: Vec<u8>) -> NarInspection {
+        let (stream, listing) = NarListingStream::new(Cursor::new(nar));
+        let (mut stream, references) = ReferenceScanStream::new(stream, "/nix/store");
+        tokio::io::copy(&mut stream, &mut tokio::io::sink())
+            .await
+            .unwrap();
+
+        NarInspection {
+            listing,
+            references: Some(references),
+        }
+    }
+
+    fn upload_info(references: Vec<String>) -> UploadPathNarInfo {
+        UploadPathNarInfo {
+            cache: "test".parse().unwrap(),
+            store_path_hash: StorePathHash::new("ia9bs5ilwwqv3lbdxmydm5bwwcbfw1a3".to_string())
+                .unwrap(),
+            store_path: "/nix/store/ia9bs5ilwwqv3lbdxmydm5bwwcbfw1a3-test".to_string(),
+            references,
+            system: None,
+            deriver: None,
+            sigs: Vec::new(),
+            ca: None,
+            nar_hash: Hash::Sha256([0; 32]),
+            nar_size: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_references() {
+        let dep = format!("{DEP_HASH}-dep");
+        let script = format!(
+            "#!/nix/store/{dep}/bin/sh\n\
+             exec /nix/store/ia9bs5ilwwqv3lbdxmydm5bwwcbfw1a3-test/bin/test\n"
+        );
+        let inspection = inspect(nar_file(script.as_bytes())).await;
+
+        let e = inspection.validate(&upload_info(Vec::new())).unwrap_err();
+        assert!(matches!(
+            e.kind(),
+            ErrorKind::UndeclaredReferences { paths } if paths == DEP_HASH
+        ));
+
+        inspection
+            .validate(&upload_info(vec![dep.clone()]))
+            .unwrap();
+
+        // Placeholders don't need to be declared
+        let placeholder = format!("/nix/store/{PLACEHOLDER_HASH}-placeholder/bin/sh\n{script}");
+        let inspection = inspect(nar_file(placeholder.as_bytes())).await;
+        inspection.validate(&upload_info(vec![dep])).unwrap();
+    }
+}
@@ -0,0 +1,37 @@
+use axum::extract::{Extension, Json};
+use tracing::instrument;
+
+use crate::database::AtticDatabase;
+use crate::error::ServerResult;
+use crate::{RequestState, State};
+use attic::api::v1::upload_realisation::UploadRealisationRequest;
+
+/// Uploads realisations of derivation outputs.
+///
+/// The store paths the realisations point to don't need to exist
+/// in the cache.
+#[instrument(skip_all, fields(payload))]
+pub(crate) async fn upload_realisation(
+    Extension(state): Extension<State>,
+    Extension(req_state): Extension<RequestState>,
+    Json(payload): Json<UploadRealisationRequest>,
+) -> ServerResult<()> {
+    let database = state.database().await?;
+    let cache = req_state
+        .auth
+        .auth_cache(database, &payload.cache, |cache, permission| {
+            permission.require_push()?;
+            Ok(cache)
+        })
+        .await?;
+
+    let username = req_state.auth.username().map(str::to_string);
+
+    for realisation in payload.realisations {
+        database
+            .upsert_realisation(cache.id, realisation, username.clone())
+            .await?;
+    }
+
+    Ok(())
+}
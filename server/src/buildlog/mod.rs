@@ -0,0 +1,276 @@
+//! Build log storage.
+//!
+//! Build logs are stored as single files in the storage backend,
+//! compressed with the server-configured compression. When serving
+//! a log, we pass the compressed file through if the compression is a
+//! content coding the client accepts and decompress it on the fly
+//! otherwise.
+
+#[cfg(test)]
+mod tests;
+
+use std::io::Error as IoError;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use async_compression::tokio::bufread::{BrotliEncoder, XzEncoder, ZstdEncoder};
+use async_compression::Level as CompressionLevel;
+use axum::{
+    body::Body,
+    http::{header, HeaderMap},
+};
+use chrono::Utc;
+use futures::StreamExt;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, QuerySelect, TransactionTrait};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, BufReader};
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+use crate::config::CompressionType;
+use crate::database::entity::buildlog::{self, Entity as BuildLog};
+use crate::database::entity::Json as DbJson;
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::narinfo::Compression;
+use crate::storage::Download;
+use crate::State;
+use attic::nix_store::StorePath;
+use attic::stream::StreamHasher;
+
+type BoxedRead = Box<dyn AsyncRead + Unpin + Send>;
+
+/// Validates the base name of a derivation.
+pub(crate) fn parse_drv_path(drv_path: &str) -> ServerResult<String> {
+    if !drv_path.ends_with(".drv") {
+        return Err(ErrorKind::RequestError(anyhow!("Build logs are keyed by derivations")).into());
+    }
+
+    StorePath::from_base_name(PathBuf::from(drv_path)).map_err(ServerError::request_error)?;
+
+    Ok(drv_path.to_string())
+}
+
+/// Returns the uncompressed log in a request body.
+///
+/// The body may be compressed as indicated by `Content-Encoding`,
+/// which is what Nix does with `log-compression`.
+pub(crate) fn request_body(headers: &HeaderMap, body: Body) -> ServerResult<BoxedRead> {
+    let compression = request_compression(headers)?;
+
+    let stream = body.into_data_stream();
+    let stream = StreamReader::new(stream.map(|r| r.map_err(|e| IoError::other(e.to_string()))));
+
+    compression.decompress(stream)
+}
+
+/// Returns the compression a client applied to the request body.
+///
+/// This is indicated by the `Content-Encoding` header.
+fn request_compression(headers: &HeaderMap) -> ServerResult<Compression> {
+    let Some(encoding) = headers.get(header::CONTENT_ENCODING) else {
+        return Ok(Compression::None);
+    };
+
+    let encoding = encoding
+        .to_str()
+        .map_err(|_| ErrorKind::RequestError(anyhow!("Content-Encoding has invalid encoding")))?
+        .trim();
+
+    if encoding.is_empty() || encoding.eq_ignore_ascii_case("identity") {
+        return Ok(Compression::None);
+    }
+
+    Compression::from_str(&encoding.to_ascii_lowercase())
+        .map_err(|_| ErrorKind::RequestError(anyhow!("Unsupported Content-Encoding")).into())
+}
+
+/// Returns whether a client accepts a content encoding.
+///
+/// An encoding is accepted if `Accept-Encoding` lists it, or `*`
+/// if it's not listed, without `q=0`.
+pub(crate) fn accepts_encoding(headers: &HeaderMap, encoding: &str) -> bool {
+    let mut wildcard = false;
+
+    for item in headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let mut params = item.split(';').map(str::trim);
+        let coding = params.next().unwrap_or_default();
+        let accepted = !params.any(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .map(|q| q == 0.0)
+                .unwrap_or(false)
+        });
+
+        if coding.eq_ignore_ascii_case(encoding) {
+            return accepted;
+        } else if coding == "*" {
+            wildcard = accepted;
+        }
+    }
+
+    wildcard
+}
+
+/// Returns the HTTP content coding of a compression, if there is one.
+///
+/// Only registered content codings are passed through, since clients
+/// won't decode anything else (e.g., `xz`) even if they accept `*`.
+pub(crate) fn content_encoding(compression: &Compression) -> Option<&'static str> {
+    match compression {
+        Compression::Brotli | Compression::Zstd => Some(compression.as_str()),
+        _ => None,
+    }
+}
+
+/// Wraps a stream with a compressor.
+fn compress<R>(stream: R, ctype: CompressionType, level: CompressionLevel) -> BoxedRead
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let stream = BufReader::new(stream);
+
+    match ctype {
+        CompressionType::None => Box::new(stream),
+        CompressionType::Brotli => Box::new(BrotliEncoder::with_quality(stream, level)),
+        CompressionType::Zstd => Box::new(ZstdEncoder::with_quality(stream, level)),
+        CompressionType::Xz => Box::new(XzEncoder::with_quality(stream, level)),
+    }
+}
+
+/// Stores the build log of a derivation, replacing any existing one.
+///
+/// The stream must be uncompressed.
+pub(crate) async fn upload_log<R>(
+    state: &State,
+    cache_id: i64,
+    drv_path: String,
+    stream: R,
+    created_by: Option<String>,
+) -> ServerResult<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let database = state.database().await?;
+    let storage = state.storage().await?;
+
+    let compression_config = &state.config.compression;
+    let compression_type = compression_config.r#type;
+    let compression: Compression = compression_type.into();
+
+    let stream = compress(stream, compression_type, compression_config.level());
+    let (mut stream, file_compute) = StreamHasher::new(stream, Sha256::new());
+
+    let key = format!("{}.log", Uuid::new_v4());
+    let remote_file = storage.upload_file(key.clone(), &mut stream).await?;
+    let remote_file_id = remote_file.remote_file_id();
+
+    let file_size = file_compute
+        .get()
+        .ok_or_else(|| {
+            ServerError::from(ErrorKind::RequestError(anyhow!(
+                "Log stream ended prematurely"
+            )))
+        })?
+        .1;
+
+    let model = buildlog::ActiveModel {
+        cache_id: Set(cache_id),
+        drv_path: Set(drv_path),
+        compression: Set(compression.to_string()),
+        file_size: Set(file_size as i64),
+        remote_file: Set(DbJson(remote_file)),
+        remote_file_id: Set(remote_file_id),
+        created_at: Set(Utc::now()),
+        created_by: Set(created_by),
+        ..Default::default()
+    };
+
+    let existing = match replace_log(database, model).await {
+        Ok(existing) => existing,
+        Err(e) => {
+            if let Err(e) = storage.delete_file(key).await {
+                tracing::warn!("Failed to delete uploaded build log: {}", e);
+            }
+            return Err(ServerError::database_error(e));
+        }
+    };
+
+    // The old log is no longer referenced
+    if let Some(existing) = existing {
+        if let Err(e) = storage.delete_file_db(&existing.remote_file.0).await {
+            tracing::warn!("Failed to delete replaced build log: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Inserts or replaces the build log of a derivation.
+///
+/// The existing log is locked while it's replaced, so that exactly
+/// one upload gets to delete its file. Returns the replaced log.
+async fn replace_log(
+    database: &DatabaseConnection,
+    model: buildlog::ActiveModel,
+) -> Result<Option<buildlog::Model>, DbErr> {
+    loop {
+        let txn = database.begin().await?;
+
+        let existing = BuildLog::find()
+            .filter(buildlog::Column::CacheId.eq(model.cache_id.clone().unwrap()))
+            .filter(buildlog::Column::DrvPath.eq(model.drv_path.clone().unwrap()))
+            .lock_exclusive()
+            .one(&txn)
+            .await?;
+
+        if let Some(existing) = existing {
+            let mut model = model.clone();
+            model.id = Set(existing.id);
+            BuildLog::update(model).exec(&txn).await?;
+            txn.commit().await?;
+
+            return Ok(Some(existing));
+        }
+
+        let insertion = BuildLog::insert(model.clone())
+            .on_conflict(
+                OnConflict::columns([buildlog::Column::CacheId, buildlog::Column::DrvPath])
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec(&txn)
+            .await;
+
+        match insertion {
+            Ok(_) => {
+                txn.commit().await?;
+                return Ok(None);
+            }
+            // Someone else uploaded a log in the meantime, so replace it
+            Err(DbErr::RecordNotInserted) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Opens the compressed file of a build log.
+pub(crate) async fn open_log(state: &State, log: &buildlog::Model) -> ServerResult<BoxedRead> {
+    let storage = state.storage().await?;
+
+    match storage.download_file_db(&log.remote_file.0, true).await? {
+        Download::Url(_) => Err(ServerError::storage_error(IoError::other(
+            "URLs not supported for build logs",
+        ))),
+        Download::AsyncRead(stream) => Ok(stream),
+    }
+}
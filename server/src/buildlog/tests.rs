@@ -0,0 +1,70 @@
+use super::*;
+
+use axum::http::HeaderValue;
+
+fn headers(name: header::HeaderName, value: &'static str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(name, HeaderValue::from_static(value));
+    headers
+}
+
+#[test]
+fn test_parse_drv_path() {
+    parse_drv_path("ia70ss13m22znbl8khrf2hq72qmh5drr-hello-2.12.1.drv").unwrap();
+
+    // not a derivation
+    parse_drv_path("ia70ss13m22znbl8khrf2hq72qmh5drr-hello-2.12.1").unwrap_err();
+
+    // invalid store path
+    parse_drv_path("hello-2.12.1.drv").unwrap_err();
+    parse_drv_path("../ia70ss13m22znbl8khrf2hq72qmh5drr-hello.drv").unwrap_err();
+}
+
+#[test]
+fn test_accepts_encoding() {
+    let accept = |value| headers(header::ACCEPT_ENCODING, value);
+
+    assert!(accepts_encoding(&accept("zstd"), "zstd"));
+    assert!(accepts_encoding(&accept("gzip, br;q=0.5, zstd"), "br"));
+    assert!(accepts_encoding(&accept("gzip, ZSTD"), "zstd"));
+    assert!(accepts_encoding(&accept("*"), "xz"));
+
+    assert!(!accepts_encoding(&HeaderMap::new(), "zstd"));
+    assert!(!accepts_encoding(&accept("gzip, deflate"), "zstd"));
+    assert!(!accepts_encoding(&accept("zstd;q=0"), "zstd"));
+    assert!(!accepts_encoding(&accept("*, br;q=0"), "br"));
+}
+
+#[test]
+fn test_content_encoding() {
+    assert_eq!(Some("br"), content_encoding(&Compression::Brotli));
+    assert_eq!(Some("zstd"), content_encoding(&Compression::Zstd));
+
+    // not registered content codings
+    assert_eq!(None, content_encoding(&Compression::Xz));
+    assert_eq!(None, content_encoding(&Compression::None));
+}
+
+#[test]
+fn test_request_compression() {
+    let encoding = |value| headers(header::CONTENT_ENCODING, value);
+
+    assert_eq!(
+        Compression::None,
+        request_compression(&HeaderMap::new()).unwrap()
+    );
+    assert_eq!(
+        Compression::None,
+        request_compression(&encoding("identity")).unwrap()
+    );
+    assert_eq!(
+        Compression::Brotli,
+        request_compression(&encoding("br")).unwrap()
+    );
+    assert_eq!(
+        Compression::Zstd,
+        request_compression(&encoding("zstd")).unwrap()
+    );
+
+    request_compression(&encoding("gzip")).unwrap_err();
+}
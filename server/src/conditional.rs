@@ -0,0 +1,79 @@
+//! Conditional requests.
+//!
+//! Binary cache responses carry strong validators so that HTTP
+//! caches and CDNs in front of the server can revalidate them
+//! with `If-None-Match`.
+
+use axum::http::{header, HeaderMap};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::database::entity::nar::NarModel;
+
+/// Returns the entity tag of a response body.
+pub(crate) fn body_etag(body: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(Sha256::digest(body)))
+}
+
+/// Returns the entity tag of a NAR file.
+///
+/// The same NAR may be stored more than once with different
+/// compression, so the NAR hash is qualified with the NAR ID.
+/// The bytes of a stored NAR never change.
+pub(crate) fn nar_etag(nar: &NarModel) -> String {
+    format!("\"{}-{}\"", nar.nar_hash, nar.id)
+}
+
+/// Formats a timestamp for `Last-Modified`.
+pub(crate) fn http_date(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Returns whether `If-None-Match` matches an entity tag.
+///
+/// If this is true, `304 Not Modified` should be returned.
+pub(crate) fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| etag_list_matches(value, etag))
+}
+
+/// Returns whether a list of entity tags matches an entity tag.
+///
+/// `If-None-Match` uses the weak comparison function.
+fn etag_list_matches(list: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+
+    list.split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_etag_list_matches() {
+        let etag = "\"abc\"";
+
+        assert!(etag_list_matches("\"abc\"", etag));
+        assert!(etag_list_matches("W/\"abc\"", etag));
+        assert!(etag_list_matches("\"xyz\", \"abc\"", etag));
+        assert!(etag_list_matches("*", etag));
+
+        assert!(!etag_list_matches("\"xyz\"", etag));
+        assert!(!etag_list_matches("abc", etag));
+        assert!(!etag_list_matches("", etag));
+    }
+
+    #[test]
+    fn test_http_date() {
+        let timestamp = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
+        assert_eq!("Mon, 02 Jan 2023 03:04:05 GMT", http_date(&timestamp));
+    }
+}
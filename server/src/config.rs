@@ -1,6 +1,6 @@
 //! Server configuration.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
@@ -19,6 +19,7 @@ use crate::access::{
     decode_token_rs256_secret_base64, HS256Key, RS256KeyPair, RS256PublicKey,
 };
 use crate::narinfo::Compression as NixCompression;
+use crate::signing::SignerConfig;
 use crate::storage::{BunnyStorageConfig, LocalStorageConfig, S3StorageConfig};
 
 /// Application prefix in XDG base directories.
@@ -95,15 +96,36 @@ pub struct Config {
     #[serde(default = "default_soft_delete_caches")]
     pub soft_delete_caches: bool,
 
-    /// Whether to require fully uploading a NAR if it exists in the global cache.
+    /// Whether to require proof of possession of a NAR if it exists in the global cache.
     ///
-    /// If set to false, simply knowing the NAR hash is enough for
-    /// an uploader to gain access to an existing NAR in the global
-    /// cache.
+    /// The uploader must either upload the entire NAR or answer a
+    /// challenge on its contents. If set to false, simply knowing the
+    /// NAR hash is enough for an uploader to gain access to an existing
+    /// NAR in the global cache.
     #[serde(rename = "require-proof-of-possession")]
     #[serde(default = "default_require_proof_of_possession")]
     pub require_proof_of_possession: bool,
 
+    /// Whether webhooks can notify private addresses.
+    ///
+    /// By default, webhooks can't be sent to loopback, private,
+    /// link-local and other non-public addresses, so that cache
+    /// administrators can't make the server send requests to the
+    /// internal network.
+    #[serde(rename = "allow-private-webhooks")]
+    #[serde(default = "default_allow_private_webhooks")]
+    pub allow_private_webhooks: bool,
+
+    /// Upstream caches that caches can pull through from.
+    ///
+    /// Each entry is a URL prefix like `https://cache.nixos.org`. An
+    /// upstream cache is allowed if it has the same origin as an entry
+    /// and its path is under the path of the entry. If unconfigured or
+    /// the list is empty, caches can't have upstream caches.
+    #[serde(rename = "allowed-upstreams")]
+    #[serde(default = "Vec::new")]
+    pub allowed_upstreams: Vec<String>,
+
     /// Database connection.
     pub database: DatabaseConfig,
 
@@ -126,6 +148,13 @@ pub struct Config {
     #[serde(default = "Default::default")]
     pub jwt: JWTConfig,
 
+    /// External signers.
+    ///
+    /// Caches can use keypairs held by external signers, in which
+    /// case the secret keys are never stored in the database.
+    #[serde(default = "HashMap::new")]
+    pub signers: HashMap<String, SignerConfig>,
+
     /// (Deprecated Stub)
     ///
     /// This simply results in an error telling the user to update
@@ -554,6 +583,10 @@ fn default_require_proof_of_possession() -> bool {
     true
 }
 
+fn default_allow_private_webhooks() -> bool {
+    false
+}
+
 fn default_gc_interval() -> Duration {
     Duration::from_secs(43200)
 }
@@ -0,0 +1,67 @@
+//! A build log of a derivation in a local cache.
+//!
+//! Build logs are served to clients as `log/{drvPath}` so that
+//! `nix log` can retrieve them from the cache.
+
+use sea_orm::entity::prelude::*;
+
+use super::Json;
+use crate::storage::RemoteFile;
+
+pub type BuildLogModel = Model;
+
+/// A build log in a binary cache.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "buildlog")]
+pub struct Model {
+    /// Unique numeric ID of the build log.
+    #[sea_orm(primary_key)]
+    pub id: i64,
+
+    /// ID of the binary cache the build log belongs to.
+    #[sea_orm(indexed)]
+    pub cache_id: i64,
+
+    /// The base name of the derivation the log was produced by.
+    ///
+    /// For example, `xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx-hello-2.12.1.drv`.
+    pub drv_path: String,
+
+    /// The type of compression in use.
+    #[sea_orm(column_type = "String(Some(10))")]
+    pub compression: String,
+
+    /// The size of the compressed log.
+    pub file_size: i64,
+
+    /// The remote file backing this build log.
+    pub remote_file: Json<RemoteFile>,
+
+    /// Unique string identifying the remote file.
+    #[sea_orm(unique)]
+    pub remote_file_id: String,
+
+    /// Timestamp when the build log is created.
+    pub created_at: ChronoDateTimeUtc,
+
+    /// The uploader of the build log.
+    pub created_by: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::cache::Entity",
+        from = "Column::CacheId",
+        to = "super::cache::Column::Id"
+    )]
+    Cache,
+}
+
+impl Related<super::cache::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Cache.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,10 +1,13 @@
 //! A binary cache.
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use super::Json;
+use attic::api::v1::cache_config::{CachePublicKey, KeypairState, WebhookConfig};
+use attic::cache::CacheName;
 use attic::error::AtticResult;
-use attic::signing::NixKeypair;
+use attic::signing::{NixKeypair, NixPublicKey};
 
 pub type CacheModel = Model;
 
@@ -21,8 +24,22 @@ pub struct Model {
     pub name: String,
 
     /// Signing keypair for the cache.
+    ///
+    /// If the keypair is held by an external signer, this is
+    /// only the public key.
     pub keypair: String,
 
+    /// The external signer holding the keypair.
+    ///
+    /// This refers to a signer in the server configuration.
+    pub signer: Option<String>,
+
+    /// Previous signing keypairs of the cache.
+    ///
+    /// When the keypair is replaced, the previous keypair is kept
+    /// here and continues to sign objects until it's revoked.
+    pub previous_keypairs: Json<Vec<PreviousKeypair>>,
+
     /// Whether the cache is public or not.
     ///
     /// Anonymous clients are implicitly granted the "pull"
@@ -49,6 +66,58 @@ pub struct Model {
 
     /// The retention period of the cache, in seconds.
     pub retention_period: Option<i32>,
+
+    /// A list of upstream binary caches to pull through.
+    ///
+    /// The upstreams are consulted in order when a store path
+    /// doesn't exist in the cache.
+    pub upstream_urls: Json<Vec<String>>,
+
+    /// Whether to ingest NARs fetched from upstream caches.
+    pub ingest_upstream: bool,
+
+    /// A list of member caches to layer.
+    ///
+    /// A cache with members is virtual. Store paths that don't
+    /// exist in the cache itself are looked up in the members
+    /// in order.
+    pub member_caches: Json<Vec<CacheName>>,
+
+    /// A list of webhooks notified of events in the cache.
+    pub webhooks: Json<Vec<WebhookConfig>>,
+
+    /// The maximum total NAR size of objects in the cache, in bytes.
+    pub quota_bytes: Option<i64>,
+
+    /// The maximum number of objects in the cache.
+    pub quota_objects: Option<i64>,
+
+    /// Whether to validate the structure and references of uploaded NARs.
+    pub validate_nars: bool,
+}
+
+/// A previous signing keypair of a cache.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviousKeypair {
+    /// The public key, in the canonical format used by Nix.
+    pub public_key: String,
+
+    /// The keypair.
+    ///
+    /// This is discarded when the keypair is revoked, and is
+    /// never set if the keypair is held by an external signer.
+    pub keypair: Option<String>,
+
+    /// The external signer holding the keypair.
+    ///
+    /// This is discarded when the keypair is revoked.
+    #[serde(default)]
+    pub signer: Option<String>,
+
+    /// The state of the keypair.
+    ///
+    /// This is never `Active`.
+    pub state: KeypairState,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -61,6 +130,40 @@ impl Model {
     pub fn keypair(&self) -> AtticResult<NixKeypair> {
         NixKeypair::from_str(&self.keypair)
     }
+
+    /// Returns the public key of the current keypair.
+    pub fn public_key(&self) -> AtticResult<String> {
+        if self.signer.is_some() {
+            Ok(NixPublicKey::from_str(&self.keypair)?.export())
+        } else {
+            Ok(self.keypair()?.export_public_key())
+        }
+    }
+
+    /// Returns the public keys of all keypairs.
+    pub fn public_keys(&self) -> AtticResult<Vec<CachePublicKey>> {
+        let mut public_keys = vec![CachePublicKey {
+            public_key: self.public_key()?,
+            state: KeypairState::Active,
+        }];
+
+        public_keys.extend(
+            self.previous_keypairs
+                .0
+                .iter()
+                .map(|previous| CachePublicKey {
+                    public_key: previous.public_key.clone(),
+                    state: previous.state,
+                }),
+        );
+
+        Ok(public_keys)
+    }
+
+    /// Returns whether the cache is a virtual cache layering other caches.
+    pub fn is_virtual(&self) -> bool {
+        !self.member_caches.0.is_empty()
+    }
 }
 
 impl Related<super::object::Entity> for Entity {
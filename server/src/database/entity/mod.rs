@@ -2,11 +2,18 @@
 //!
 //! We use SeaORM and target PostgreSQL (production) and SQLite (development).
 
+pub mod buildlog;
 pub mod cache;
 pub mod chunk;
 pub mod chunkref;
 pub mod nar;
+pub mod narlisting;
 pub mod object;
+pub mod pin;
+pub mod possession_challenge;
+pub mod realisation;
+pub mod tag;
+pub mod webhook_delivery;
 
 use sea_orm::entity::Value;
 use sea_orm::sea_query::{ArrayType, ColumnType, ValueType, ValueTypeErr};
@@ -93,6 +93,22 @@ pub struct Model {
     #[sea_orm(column_type = "String(Some(10))")]
     pub compression: String,
 
+    /// The hash of the compressed file served to clients.
+    ///
+    /// This is the hash of the concatenation of all compressed
+    /// chunks. It always begins with "sha256:" with the hash in
+    /// the hexadecimal format.
+    ///
+    /// This field may not be available for NARs with multiple
+    /// chunks until the hash has been computed.
+    pub file_hash: Option<String>,
+
+    /// The size of the compressed file served to clients.
+    ///
+    /// This field may not be available if the NAR was uploaded
+    /// with missing chunks.
+    pub file_size: Option<i64>,
+
     /// Number of chunks that make up this NAR.
     pub num_chunks: i32,
 
@@ -126,6 +142,9 @@ pub enum Relation {
 
     #[sea_orm(has_many = "super::chunkref::Entity")]
     ChunkRef,
+
+    #[sea_orm(has_one = "super::narlisting::Entity")]
+    NarListing,
 }
 
 impl ActiveModelBehavior for ActiveModel {}
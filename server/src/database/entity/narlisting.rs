@@ -0,0 +1,47 @@
+//! A file listing of a NAR.
+//!
+//! The listing is built while the NAR is being uploaded and is
+//! served to clients as `{storePathHash}.ls`, allowing them to
+//! browse the contents of a store path without downloading the
+//! entire NAR.
+
+use sea_orm::entity::prelude::*;
+
+pub type NarListingModel = Model;
+
+/// A file listing of a NAR.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "narlisting")]
+pub struct Model {
+    /// Unique numeric ID of the listing.
+    #[sea_orm(primary_key)]
+    pub id: i64,
+
+    /// ID of the NAR.
+    #[sea_orm(unique, indexed)]
+    pub nar_id: i64,
+
+    /// The listing in the JSON format used by Nix.
+    ///
+    /// This is served to clients verbatim.
+    #[sea_orm(column_type = "Text")]
+    pub listing: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::nar::Entity",
+        from = "Column::NarId",
+        to = "super::nar::Column::Id"
+    )]
+    Nar,
+}
+
+impl Related<super::nar::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Nar.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
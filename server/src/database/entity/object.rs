@@ -115,6 +115,17 @@ impl InsertExt for Insert<ActiveModel> {
 }
 
 impl Model {
+    /// Returns the deduplicated client-supplied signatures of this object.
+    pub fn signatures(&self) -> Vec<String> {
+        let mut signatures: Vec<String> = Vec::with_capacity(self.sigs.0.len());
+        for signature in self.sigs.0.iter() {
+            if !signatures.contains(signature) {
+                signatures.push(signature.to_owned());
+            }
+        }
+        signatures
+    }
+
     /// Converts this object to a NarInfo.
     pub fn to_nar_info(&self, nar: &NarModel) -> ServerResult<NarInfo> {
         let nar_size = nar
@@ -127,14 +138,18 @@ impl Model {
             url: format!("nar/{}.nar", self.store_path_hash.as_str()),
 
             compression: Compression::from_str(&nar.compression)?,
-            file_hash: None, // FIXME
-            file_size: None, // FIXME
+            file_hash: nar.file_hash.as_deref().map(Hash::from_typed).transpose()?,
+            file_size: nar
+                .file_size
+                .map(usize::try_from)
+                .transpose()
+                .map_err(ServerError::database_error)?,
             nar_hash: Hash::from_typed(&nar.nar_hash)?,
             nar_size,
             system: self.system.to_owned(),
             references: self.references.0.to_owned(),
             deriver: self.deriver.to_owned(),
-            signature: None,
+            signatures: self.signatures(),
             ca: self.ca.to_owned(),
         })
     }
@@ -0,0 +1,76 @@
+//! A pinned store path in a local cache.
+//!
+//! The object of a pinned store path and all objects in its closure
+//! in the cache are exempt from time-based garbage collection.
+
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::Insert;
+
+pub type PinModel = Model;
+
+pub trait InsertExt {
+    fn on_conflict_do_update(self) -> Self;
+}
+
+/// A pin in a binary cache.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "pin")]
+pub struct Model {
+    /// Unique numeric ID of the pin.
+    #[sea_orm(primary_key)]
+    pub id: i64,
+
+    /// ID of the binary cache the pin belongs to.
+    #[sea_orm(indexed)]
+    pub cache_id: i64,
+
+    /// The hash portion of the pinned store path.
+    #[sea_orm(column_type = "String(Some(32))")]
+    pub store_path_hash: String,
+
+    /// The full pinned store path, including the store directory.
+    pub store_path: String,
+
+    /// The name of the pin.
+    pub name: Option<String>,
+
+    /// Timestamp when the pin is created.
+    pub created_at: ChronoDateTimeUtc,
+
+    /// Timestamp when the pin expires.
+    ///
+    /// Expired pins are deleted during garbage collection.
+    pub expires_at: Option<ChronoDateTimeUtc>,
+
+    /// The user who created the pin.
+    pub created_by: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::cache::Entity",
+        from = "Column::CacheId",
+        to = "super::cache::Column::Id"
+    )]
+    Cache,
+}
+
+impl InsertExt for Insert<ActiveModel> {
+    fn on_conflict_do_update(self) -> Self {
+        self.on_conflict(
+            OnConflict::columns([Column::CacheId, Column::StorePathHash])
+                .update_columns([
+                    Column::StorePath,
+                    Column::Name,
+                    Column::CreatedAt,
+                    Column::ExpiresAt,
+                    Column::CreatedBy,
+                ])
+                .to_owned(),
+        )
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,46 @@
+//! A challenge to prove possession of a NAR.
+//!
+//! Challenges are stored in the database so they can be answered to
+//! any API server. Rows are deleted when the challenge is answered,
+//! and expired ones are deleted during garbage collection.
+
+use sea_orm::entity::prelude::*;
+
+use super::Json;
+use attic::api::v1::possession_challenge::ByteRange;
+
+pub type PossessionChallengeModel = Model;
+
+/// A challenge to prove possession of a NAR.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "possession_challenge")]
+pub struct Model {
+    /// Unique numeric ID of the challenge.
+    #[sea_orm(primary_key)]
+    pub id: i64,
+
+    /// The random nonce identifying the challenge.
+    #[sea_orm(unique)]
+    pub nonce: String,
+
+    /// ID of the cache the challenge was issued for.
+    pub cache_id: i64,
+
+    /// The claimed NAR hash.
+    pub nar_hash: String,
+
+    /// The claimed NAR size.
+    pub nar_size: i64,
+
+    /// The byte ranges of the NAR to hash.
+    pub ranges: Json<Vec<ByteRange>>,
+
+    /// Timestamp when the challenge expires.
+    #[sea_orm(indexed)]
+    pub expires_at: ChronoDateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
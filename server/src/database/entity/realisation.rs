@@ -0,0 +1,104 @@
+//! A realisation of a derivation output in a local cache.
+//!
+//! Realisations are served to clients as
+//! `realisations/{drvOutput}.doi` so that the outputs of
+//! content-addressed derivations can be substituted.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::Insert;
+
+use super::Json;
+use crate::error::ServerResult;
+use attic::nix_store::StorePath;
+use attic::realisation::{DrvOutput, Realisation};
+
+pub type RealisationModel = Model;
+
+pub trait InsertExt {
+    fn on_conflict_do_update(self) -> Self;
+}
+
+/// A realisation in a binary cache.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "realisation")]
+pub struct Model {
+    /// Unique numeric ID of the realisation.
+    #[sea_orm(primary_key)]
+    pub id: i64,
+
+    /// ID of the binary cache the realisation belongs to.
+    #[sea_orm(indexed)]
+    pub cache_id: i64,
+
+    /// The derivation output.
+    ///
+    /// This is in the canonical `{drvHash}!{outputName}` format
+    /// with the hash in the hexadecimal format.
+    pub drv_output: String,
+
+    /// The base name of the store path the output was built into.
+    pub out_path: String,
+
+    /// Client-supplied signatures of this realisation.
+    pub signatures: Json<Vec<String>>,
+
+    /// Realisations of the dependencies of the derivation.
+    pub dependent_realisations: Json<BTreeMap<String, String>>,
+
+    /// Timestamp when the realisation is created.
+    pub created_at: ChronoDateTimeUtc,
+
+    /// The uploader of the realisation.
+    pub created_by: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::cache::Entity",
+        from = "Column::CacheId",
+        to = "super::cache::Column::Id"
+    )]
+    Cache,
+}
+
+impl InsertExt for Insert<ActiveModel> {
+    fn on_conflict_do_update(self) -> Self {
+        self.on_conflict(
+            OnConflict::columns([Column::CacheId, Column::DrvOutput])
+                .update_columns([
+                    Column::OutPath,
+                    Column::Signatures,
+                    Column::DependentRealisations,
+                    Column::CreatedAt,
+                    Column::CreatedBy,
+                ])
+                .to_owned(),
+        )
+    }
+}
+
+impl Model {
+    /// Converts this model to a realisation.
+    pub fn to_realisation(&self) -> ServerResult<Realisation> {
+        Ok(Realisation {
+            id: DrvOutput::from_str(&self.drv_output)?,
+            out_path: StorePath::from_base_name(PathBuf::from(&self.out_path))?,
+            signatures: self.signatures.0.to_owned(),
+            dependent_realisations: self.dependent_realisations.0.to_owned(),
+        })
+    }
+}
+
+impl Related<super::cache::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Cache.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
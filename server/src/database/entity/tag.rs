@@ -0,0 +1,52 @@
+//! A tag in a local cache.
+//!
+//! Tags are named, mutable pointers to store paths. Each time a tag
+//! is set, a new row is inserted, and the most recent row is the
+//! current target. Previous rows make up the history of the tag.
+
+use sea_orm::entity::prelude::*;
+
+pub type TagModel = Model;
+
+/// A target of a tag in a binary cache.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "tag")]
+pub struct Model {
+    /// Unique numeric ID of the tag target.
+    #[sea_orm(primary_key)]
+    pub id: i64,
+
+    /// ID of the binary cache the tag belongs to.
+    #[sea_orm(indexed)]
+    pub cache_id: i64,
+
+    /// The name of the tag.
+    ///
+    /// For example, `myapp/latest`.
+    pub name: String,
+
+    /// The hash portion of the target store path.
+    #[sea_orm(column_type = "String(Some(32))")]
+    pub store_path_hash: String,
+
+    /// The full target store path, including the store directory.
+    pub store_path: String,
+
+    /// Timestamp when the tag is set to the target.
+    pub created_at: ChronoDateTimeUtc,
+
+    /// The user who set the tag to the target.
+    pub created_by: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::cache::Entity",
+        from = "Column::CacheId",
+        to = "super::cache::Column::Id"
+    )]
+    Cache,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
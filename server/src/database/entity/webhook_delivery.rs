@@ -0,0 +1,55 @@
+//! A pending webhook notification.
+//!
+//! Notifications are queued in the database so they survive restarts.
+//! Rows are deleted once the notification is delivered or given up on.
+//! Deliveries don't reference caches with a foreign key, so notifications
+//! of destroyed caches are still delivered.
+
+use sea_orm::entity::prelude::*;
+
+pub type WebhookDeliveryModel = Model;
+
+/// A pending webhook notification.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "webhook_delivery")]
+pub struct Model {
+    /// Unique numeric ID of the delivery.
+    #[sea_orm(primary_key)]
+    pub id: i64,
+
+    /// The ID of the cache the event happened in.
+    ///
+    /// This is null for deliveries queued before it was recorded.
+    pub cache_id: Option<i64>,
+
+    /// The URL the notification is sent to.
+    pub url: String,
+
+    /// The secret used to sign the notification.
+    pub secret: Option<String>,
+
+    /// The type of the event.
+    pub event: String,
+
+    /// The JSON payload.
+    #[sea_orm(column_type = "Text")]
+    pub payload: String,
+
+    /// The number of failed attempts.
+    pub attempts: i32,
+
+    /// Timestamp when the next attempt is due.
+    #[sea_orm(indexed)]
+    pub next_attempt_at: ChronoDateTimeUtc,
+
+    /// Timestamp when the notification was queued.
+    pub created_at: ChronoDateTimeUtc,
+
+    /// The error of the last failed attempt.
+    pub last_error: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,80 @@
+use sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+use crate::database::entity::nar::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261015_000002_add_nar_file_hash"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .add_column(ColumnDef::new(Column::FileHash).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .add_column(ColumnDef::new(Column::FileSize).big_integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        eprintln!("* Populating NAR file sizes and hashes...");
+
+        // The file size is the sum of the compressed chunk sizes,
+        // provided that all chunks are available.
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                r#"
+                UPDATE nar SET file_size = (
+                    SELECT SUM(chunk.file_size) FROM chunkref
+                    INNER JOIN chunk ON chunkref.chunk_id = chunk.id
+                    WHERE chunkref.nar_id = nar.id
+                )
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM chunkref
+                    WHERE chunkref.nar_id = nar.id AND chunkref.chunk_id IS NULL
+                )
+                "#
+                .to_owned(),
+            ))
+            .await?;
+
+        // The file hash of a single-chunk NAR is that of the chunk.
+        //
+        // For multi-chunk NARs, it's computed the next time the NAR
+        // is downloaded in full.
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                r#"
+                UPDATE nar SET file_hash = (
+                    SELECT chunk.file_hash FROM chunkref
+                    INNER JOIN chunk ON chunkref.chunk_id = chunk.id
+                    WHERE chunkref.nar_id = nar.id
+                )
+                WHERE num_chunks = 1
+                "#
+                .to_owned(),
+            ))
+            .await?;
+
+        Ok(())
+    }
+}
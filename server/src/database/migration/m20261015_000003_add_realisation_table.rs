@@ -0,0 +1,68 @@
+use sea_orm_migration::prelude::*;
+
+use crate::database::entity::cache;
+use crate::database::entity::realisation::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261015_000003_add_realisation_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entity)
+                    .col(
+                        ColumnDef::new(Column::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Column::CacheId).big_integer().not_null())
+                    .col(ColumnDef::new(Column::DrvOutput).string().not_null())
+                    .col(ColumnDef::new(Column::OutPath).string().not_null())
+                    .col(ColumnDef::new(Column::Signatures).string().not_null())
+                    .col(
+                        ColumnDef::new(Column::DependentRealisations)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Column::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Column::CreatedBy).string())
+                    .foreign_key(
+                        ForeignKeyCreateStatement::new()
+                            .name("fk_realisation_cache")
+                            .from_tbl(Entity)
+                            .from_col(Column::CacheId)
+                            .to_tbl(cache::Entity)
+                            .to_col(cache::Column::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-realisation-cache-drv-output")
+                    .table(Entity)
+                    .col(Column::CacheId)
+                    .col(Column::DrvOutput)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,27 @@
+use sea_orm_migration::prelude::*;
+
+use crate::database::entity::cache::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261015_000008_add_cache_signer"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .add_column(ColumnDef::new(Column::Signer).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+use crate::database::entity::possession_challenge::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261015_000014_add_possession_challenge_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entity)
+                    .col(
+                        ColumnDef::new(Column::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Column::Nonce)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Column::CacheId).big_integer().not_null())
+                    .col(ColumnDef::new(Column::NarHash).string().not_null())
+                    .col(ColumnDef::new(Column::NarSize).big_integer().not_null())
+                    .col(ColumnDef::new(Column::Ranges).string().not_null())
+                    .col(
+                        ColumnDef::new(Column::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-possession-challenge-expires-at")
+                    .table(Entity)
+                    .col(Column::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -14,6 +14,21 @@ mod m20230112_000003_add_nar_num_chunks;
 mod m20230112_000004_migrate_nar_remote_files_to_chunks;
 mod m20230112_000005_drop_old_nar_columns;
 mod m20230112_000006_add_nar_completeness_hint;
+mod m20261015_000001_add_narlisting_table;
+mod m20261015_000002_add_nar_file_hash;
+mod m20261015_000003_add_realisation_table;
+mod m20261015_000004_add_buildlog_table;
+mod m20261015_000005_add_cache_upstreams;
+mod m20261015_000006_add_cache_members;
+mod m20261015_000007_add_cache_previous_keypairs;
+mod m20261015_000008_add_cache_signer;
+mod m20261015_000009_add_pin_table;
+mod m20261015_000010_add_tag_table;
+mod m20261015_000011_add_webhooks;
+mod m20261015_000012_add_cache_quotas;
+mod m20261015_000013_add_cache_nar_validation;
+mod m20261015_000014_add_possession_challenge_table;
+mod m20261015_000015_add_webhook_delivery_cache_id;
 
 pub struct Migrator;
 
@@ -33,6 +48,21 @@ impl MigratorTrait for Migrator {
             Box::new(m20230112_000004_migrate_nar_remote_files_to_chunks::Migration),
             Box::new(m20230112_000005_drop_old_nar_columns::Migration),
             Box::new(m20230112_000006_add_nar_completeness_hint::Migration),
+            Box::new(m20261015_000001_add_narlisting_table::Migration),
+            Box::new(m20261015_000002_add_nar_file_hash::Migration),
+            Box::new(m20261015_000003_add_realisation_table::Migration),
+            Box::new(m20261015_000004_add_buildlog_table::Migration),
+            Box::new(m20261015_000005_add_cache_upstreams::Migration),
+            Box::new(m20261015_000006_add_cache_members::Migration),
+            Box::new(m20261015_000007_add_cache_previous_keypairs::Migration),
+            Box::new(m20261015_000008_add_cache_signer::Migration),
+            Box::new(m20261015_000009_add_pin_table::Migration),
+            Box::new(m20261015_000010_add_tag_table::Migration),
+            Box::new(m20261015_000011_add_webhooks::Migration),
+            Box::new(m20261015_000012_add_cache_quotas::Migration),
+            Box::new(m20261015_000013_add_cache_nar_validation::Migration),
+            Box::new(m20261015_000014_add_possession_challenge_table::Migration),
+            Box::new(m20261015_000015_add_webhook_delivery_cache_id::Migration),
         ]
     }
 }
@@ -1,6 +1,7 @@
 pub mod entity;
 pub mod migration;
 
+use std::collections::{BTreeMap, HashSet};
 use std::ops::Deref;
 
 use anyhow::anyhow;
@@ -17,12 +18,26 @@ use crate::error::{ErrorKind, ServerError, ServerResult};
 use crate::narinfo::Compression;
 use attic::cache::CacheName;
 use attic::hash::Hash;
-use attic::nix_store::StorePathHash;
+use attic::nix_store::{StorePathHash, STORE_PATH_HASH_LEN};
+use attic::realisation::DrvOutput;
+use entity::buildlog::{self, BuildLogModel, Entity as BuildLog};
 use entity::cache::{self, CacheModel, Entity as Cache};
 use entity::chunk::{self, ChunkModel, ChunkState, Entity as Chunk};
-use entity::chunkref;
+use entity::chunkref::{self, Entity as ChunkRef};
 use entity::nar::{self, Entity as Nar, NarModel, NarState};
 use entity::object::{self, Entity as Object, ObjectModel};
+use entity::pin::{self, Entity as Pin, PinModel};
+use entity::realisation::{self, Entity as Realisation, InsertExt as _, RealisationModel};
+use entity::tag::{self, Entity as Tag, TagModel};
+use entity::Json;
+
+/// Number of chunk hashes to look up in a single query.
+///
+/// This keeps us below the limits on the number of bind parameters.
+const CHUNK_HASH_BATCH_SIZE: usize = 1000;
+
+/// Number of store path hashes to look up in a single query.
+const STORE_PATH_HASH_BATCH_SIZE: usize = 1000;
 
 // quintuple join time
 const SELECT_OBJECT: &str = "O_";
@@ -42,9 +57,42 @@ pub trait AtticDatabase: Send + Sync {
         include_chunks: bool,
     ) -> ServerResult<(ObjectModel, CacheModel, NarModel, Vec<Option<ChunkModel>>)>;
 
+    /// Retrieves objects in a binary cache by their store path hashes.
+    ///
+    /// This is the bulk variant of `find_object_and_chunks_by_store_path_hash`
+    /// without chunks. Objects that don't exist are omitted.
+    async fn find_objects_by_store_path_hashes(
+        &self,
+        cache: &CacheName,
+        store_path_hashes: &[StorePathHash],
+    ) -> ServerResult<Vec<(ObjectModel, CacheModel, NarModel)>>;
+
+    /// Retrieves objects in a binary cache by their store path hashes,
+    /// optionally following their references.
+    ///
+    /// Only references to objects in the same cache are followed.
+    /// Objects that don't exist are omitted.
+    async fn find_objects_in_closure(
+        &self,
+        cache_id: i64,
+        store_path_hashes: &[StorePathHash],
+        closure: bool,
+    ) -> ServerResult<Vec<ObjectModel>>;
+
+    /// Retrieves the pins in a binary cache that haven't expired.
+    async fn find_active_pins(&self, cache_id: i64) -> ServerResult<Vec<PinModel>>;
+
+    /// Retrieves the current targets of all tags in a binary cache.
+    async fn find_current_tags(&self, cache_id: i64) -> ServerResult<Vec<TagModel>>;
+
     /// Retrieves a binary cache.
     async fn find_cache(&self, cache: &CacheName) -> ServerResult<CacheModel>;
 
+    /// Retrieves multiple binary caches.
+    ///
+    /// Caches that don't exist are omitted.
+    async fn find_caches(&self, caches: &[CacheName]) -> ServerResult<Vec<CacheModel>>;
+
     /// Retrieves and locks a valid NAR matching a NAR Hash.
     async fn find_and_lock_nar(&self, nar_hash: &Hash) -> ServerResult<Option<NarGuard>>;
 
@@ -55,8 +103,45 @@ pub trait AtticDatabase: Send + Sync {
         compression: Compression,
     ) -> ServerResult<Option<ChunkGuard>>;
 
+    /// Retrieves the chunks of a NAR in order.
+    ///
+    /// Missing chunks are returned as `None`.
+    async fn find_chunks_by_nar_id(&self, nar_id: i64) -> ServerResult<Vec<Option<ChunkModel>>>;
+
+    /// Finds which of the chunks exist, returning their typed base16 hashes.
+    ///
+    /// If `cache_id` is specified, only chunks that are part of NARs of
+    /// objects in the cache are considered.
+    async fn find_existing_chunk_hashes(
+        &self,
+        chunk_hashes: &[Hash],
+        compression: Compression,
+        cache_id: Option<i64>,
+    ) -> ServerResult<HashSet<String>>;
+
     /// Bumps the last accessed timestamp of an object.
     async fn bump_object_last_accessed(&self, object_id: i64) -> ServerResult<()>;
+
+    /// Retrieves a realisation in a binary cache.
+    async fn find_realisation(
+        &self,
+        cache_id: i64,
+        drv_output: &DrvOutput,
+    ) -> ServerResult<RealisationModel>;
+
+    /// Inserts or replaces a realisation in a binary cache.
+    ///
+    /// If the existing realisation points to the same store path,
+    /// the signatures are merged.
+    async fn upsert_realisation(
+        &self,
+        cache_id: i64,
+        realisation: attic::realisation::Realisation,
+        created_by: Option<String>,
+    ) -> ServerResult<()>;
+
+    /// Retrieves the build log of a derivation in a binary cache.
+    async fn find_build_log(&self, cache_id: i64, drv_path: &str) -> ServerResult<BuildLogModel>;
 }
 
 pub struct NarGuard {
@@ -223,6 +308,121 @@ impl AtticDatabase for DatabaseConnection {
         Ok((object, cache, nar, chunks))
     }
 
+    async fn find_objects_by_store_path_hashes(
+        &self,
+        cache: &CacheName,
+        store_path_hashes: &[StorePathHash],
+    ) -> ServerResult<Vec<(ObjectModel, CacheModel, NarModel)>> {
+        let stmt = build_cache_object_nar_query(false)
+            .filter(cache::Column::Name.eq(cache.as_str()))
+            .filter(cache::Column::DeletedAt.is_null())
+            .filter(
+                object::Column::StorePathHash
+                    .is_in(store_path_hashes.iter().map(StorePathHash::as_str)),
+            )
+            .filter(nar::Column::State.eq(NarState::Valid))
+            .build(self.get_database_backend());
+
+        let results = self
+            .query_all(stmt)
+            .await
+            .map_err(ServerError::database_error)?;
+
+        results
+            .iter()
+            .map(|row| {
+                let object = object::Model::from_query_result(row, SELECT_OBJECT)
+                    .map_err(ServerError::database_error)?;
+                let cache = cache::Model::from_query_result(row, SELECT_CACHE)
+                    .map_err(ServerError::database_error)?;
+                let nar = nar::Model::from_query_result(row, SELECT_NAR)
+                    .map_err(ServerError::database_error)?;
+
+                Ok((object, cache, nar))
+            })
+            .collect()
+    }
+
+    async fn find_objects_in_closure(
+        &self,
+        cache_id: i64,
+        store_path_hashes: &[StorePathHash],
+        closure: bool,
+    ) -> ServerResult<Vec<ObjectModel>> {
+        // Store path hash -> Object
+        let mut found: BTreeMap<String, ObjectModel> = BTreeMap::new();
+        let mut queue: HashSet<String> = store_path_hashes
+            .iter()
+            .map(|h| h.as_str().to_owned())
+            .collect();
+
+        while !queue.is_empty() {
+            let frontier: Vec<String> = queue.drain().collect();
+
+            for batch in frontier.chunks(STORE_PATH_HASH_BATCH_SIZE) {
+                let objects = Object::find()
+                    .filter(object::Column::CacheId.eq(cache_id))
+                    .filter(object::Column::StorePathHash.is_in(batch.iter().map(String::as_str)))
+                    .all(self)
+                    .await
+                    .map_err(ServerError::database_error)?;
+
+                for object in objects {
+                    if closure {
+                        // References are base names of store paths
+                        let references = object
+                            .references
+                            .0
+                            .iter()
+                            .filter_map(|reference| reference.get(..STORE_PATH_HASH_LEN));
+
+                        for hash in references {
+                            if !found.contains_key(hash) {
+                                queue.insert(hash.to_owned());
+                            }
+                        }
+                    }
+
+                    found.insert(object.store_path_hash.clone(), object);
+                }
+            }
+
+            queue.retain(|hash| !found.contains_key(hash));
+        }
+
+        Ok(found.into_values().collect())
+    }
+
+    async fn find_active_pins(&self, cache_id: i64) -> ServerResult<Vec<PinModel>> {
+        Pin::find()
+            .filter(pin::Column::CacheId.eq(cache_id))
+            .filter(
+                pin::Column::ExpiresAt
+                    .is_null()
+                    .or(pin::Column::ExpiresAt.gt(Utc::now())),
+            )
+            .order_by_asc(pin::Column::StorePath)
+            .all(self)
+            .await
+            .map_err(ServerError::database_error)
+    }
+
+    async fn find_current_tags(&self, cache_id: i64) -> ServerResult<Vec<TagModel>> {
+        let current_ids = Query::select()
+            .from(Tag)
+            .expr(tag::Column::Id.max())
+            .and_where(tag::Column::CacheId.eq(cache_id))
+            .group_by_col(tag::Column::Name)
+            .to_owned();
+
+        Tag::find()
+            .filter(tag::Column::Id.in_subquery(current_ids))
+            .order_by_asc(tag::Column::Name)
+            .all(self)
+            .await
+            .map_err(ServerError::database_error)
+    }
+
     async fn find_cache(&self, cache: &CacheName) -> ServerResult<CacheModel> {
         Cache::find()
             .filter(cache::Column::Name.eq(cache.as_str()))
@@ -233,6 +433,15 @@ impl AtticDatabase for DatabaseConnection {
             .ok_or_else(|| ErrorKind::NoSuchCache.into())
     }
 
+    async fn find_caches(&self, caches: &[CacheName]) -> ServerResult<Vec<CacheModel>> {
+        Cache::find()
+            .filter(cache::Column::Name.is_in(caches.iter().map(CacheName::as_str)))
+            .filter(cache::Column::DeletedAt.is_null())
+            .all(self)
+            .await
+            .map_err(ServerError::database_error)
+    }
+
     async fn find_and_lock_nar(&self, nar_hash: &Hash) -> ServerResult<Option<NarGuard>> {
         let one = Value::Unsigned(Some(1));
         let matched_ids = Query::select()
@@ -305,6 +514,63 @@ impl AtticDatabase for DatabaseConnection {
         Ok(guard)
     }
 
+    async fn find_chunks_by_nar_id(&self, nar_id: i64) -> ServerResult<Vec<Option<ChunkModel>>> {
+        let chunks = ChunkRef::find()
+            .filter(chunkref::Column::NarId.eq(nar_id))
+            .order_by_asc(chunkref::Column::Seq)
+            .find_also_related(Chunk)
+            .all(self)
+            .await
+            .map_err(ServerError::database_error)?
+            .into_iter()
+            .map(|(_, chunk)| chunk)
+            .collect();
+
+        Ok(chunks)
+    }
+
+    async fn find_existing_chunk_hashes(
+        &self,
+        chunk_hashes: &[Hash],
+        compression: Compression,
+        cache_id: Option<i64>,
+    ) -> ServerResult<HashSet<String>> {
+        #[derive(FromQueryResult)]
+        struct ChunkHashOnly {
+            chunk_hash: String,
+        }
+
+        let mut existing = HashSet::new();
+
+        for batch in chunk_hashes.chunks(CHUNK_HASH_BATCH_SIZE) {
+            let mut query = Chunk::find()
+                .select_only()
+                .column(chunk::Column::ChunkHash)
+                .distinct()
+                .filter(chunk::Column::ChunkHash.is_in(batch.iter().map(Hash::to_typed_base16)))
+                .filter(chunk::Column::State.eq(ChunkState::Valid))
+                .filter(chunk::Column::Compression.eq(compression.as_str()));
+
+            if let Some(cache_id) = cache_id {
+                query = query
+                    .join(JoinType::InnerJoin, chunk::Relation::ChunkRef.def())
+                    .join(JoinType::InnerJoin, chunkref::Relation::Nar.def())
+                    .join(JoinType::InnerJoin, nar::Relation::Object.def())
+                    .filter(object::Column::CacheId.eq(cache_id));
+            }
+
+            let rows = query
+                .into_model::<ChunkHashOnly>()
+                .all(self)
+                .await
+                .map_err(ServerError::database_error)?;
+
+            existing.extend(rows.into_iter().map(|row| row.chunk_hash));
+        }
+
+        Ok(existing)
+    }
+
     async fn bump_object_last_accessed(&self, object_id: i64) -> ServerResult<()> {
         let now = Utc::now();
 
@@ -319,6 +585,73 @@ impl AtticDatabase for DatabaseConnection {
 
         Ok(())
     }
+
+    async fn find_realisation(
+        &self,
+        cache_id: i64,
+        drv_output: &DrvOutput,
+    ) -> ServerResult<RealisationModel> {
+        Realisation::find()
+            .filter(realisation::Column::CacheId.eq(cache_id))
+            .filter(realisation::Column::DrvOutput.eq(drv_output.to_string()))
+            .one(self)
+            .await
+            .map_err(ServerError::database_error)?
+            .ok_or_else(|| ErrorKind::NoSuchObject.into())
+    }
+
+    async fn upsert_realisation(
+        &self,
+        cache_id: i64,
+        mut realisation: attic::realisation::Realisation,
+        created_by: Option<String>,
+    ) -> ServerResult<()> {
+        let out_path = realisation
+            .out_path
+            .as_os_str()
+            .to_string_lossy()
+            .into_owned();
+
+        let existing = Realisation::find()
+            .filter(realisation::Column::CacheId.eq(cache_id))
+            .filter(realisation::Column::DrvOutput.eq(realisation.id.to_string()))
+            .one(self)
+            .await
+            .map_err(ServerError::database_error)?;
+
+        if let Some(existing) = existing {
+            if existing.out_path == out_path {
+                realisation.merge_signatures(&existing.signatures.0);
+            }
+        }
+
+        Realisation::insert(realisation::ActiveModel {
+            cache_id: Set(cache_id),
+            drv_output: Set(realisation.id.to_string()),
+            out_path: Set(out_path),
+            signatures: Set(Json(realisation.signatures)),
+            dependent_realisations: Set(Json(realisation.dependent_realisations)),
+            created_at: Set(Utc::now()),
+            created_by: Set(created_by),
+            ..Default::default()
+        })
+        .on_conflict_do_update()
+        .exec(self)
+        .await
+        .map_err(ServerError::database_error)?;
+
+        Ok(())
+    }
+
+    async fn find_build_log(&self, cache_id: i64, drv_path: &str) -> ServerResult<BuildLogModel> {
+        BuildLog::find()
+            .filter(buildlog::Column::CacheId.eq(cache_id))
+            .filter(buildlog::Column::DrvPath.eq(drv_path))
+            .one(self)
+            .await
+            .map_err(ServerError::database_error)?
+            .ok_or_else(|| ErrorKind::NoSuchObject.into())
+    }
 }
 
 impl Deref for NarGuard {
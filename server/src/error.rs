@@ -51,18 +51,39 @@ pub enum ErrorKind {
     /// The requested object does not exist.
     NoSuchObject,
 
+    /// The requested tag does not exist.
+    NoSuchTag,
+
     /// Invalid compression type "{name}".
     InvalidCompressionType { name: String },
 
     /// The requested NAR has missing chunks and needs to be repaired.
     IncompleteNar,
 
+    /// The cache would exceed its quota of {quota}.
+    QuotaExceeded { quota: String },
+
+    /// The request alone exceeds the quota of {quota} of the cache.
+    RequestExceedsQuota { quota: String },
+
+    /// The NAR is malformed: {0}
+    MalformedNar(attic::nar::Error),
+
+    /// The NAR references undeclared store paths: {paths}
+    UndeclaredReferences { paths: String },
+
+    /// The proof of possession was rejected.
+    PossessionProofRejected,
+
     /// Database error: {0:#}
     DatabaseError(AnyError),
 
     /// Storage error: {0:#}
     StorageError(AnyError),
 
+    /// Signer error: {0:#}
+    SignerError(AnyError),
+
     /// Manifest serialization error: {0}
     ManifestSerializationError(super::nix_manifest::Error),
 
@@ -96,6 +117,11 @@ impl ServerError {
         ErrorKind::RequestError(AnyError::new(error)).into()
     }
 
+    /// Returns the kind of the error.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
     pub fn set_discovery_permission(&mut self, perm: bool) {
         self.discovery_permission = perm;
     }
@@ -140,6 +166,7 @@ impl IntoResponse for ServerError {
             self.kind,
             ErrorKind::DatabaseError(_)
                 | ErrorKind::StorageError(_)
+                | ErrorKind::SignerError(_)
                 | ErrorKind::ManifestSerializationError(_)
                 | ErrorKind::AtticError(_)
         ) {
@@ -174,13 +201,20 @@ impl ErrorKind {
             Self::InternalServerError => "InternalServerError",
 
             Self::NoSuchObject => "NoSuchObject",
+            Self::NoSuchTag => "NoSuchTag",
             Self::NoSuchCache => "NoSuchCache",
             Self::CacheAlreadyExists => "CacheAlreadyExists",
             Self::InvalidCompressionType { .. } => "InvalidCompressionType",
             Self::IncompleteNar => "IncompleteNar",
+            Self::QuotaExceeded { .. } => "QuotaExceeded",
+            Self::RequestExceedsQuota { .. } => "RequestExceedsQuota",
+            Self::MalformedNar(_) => "MalformedNar",
+            Self::UndeclaredReferences { .. } => "UndeclaredReferences",
+            Self::PossessionProofRejected => "PossessionProofRejected",
             Self::AtticError(e) => e.name(),
             Self::DatabaseError(_) => "DatabaseError",
             Self::StorageError(_) => "StorageError",
+            Self::SignerError(_) => "SignerError",
             Self::ManifestSerializationError(_) => "ManifestSerializationError",
             Self::AccessError(_) => "AccessError",
             Self::RequestError(_) => "RequestError",
@@ -193,6 +227,7 @@ impl ErrorKind {
         match self {
             Self::NoSuchCache => Self::Unauthorized,
             Self::NoSuchObject => Self::Unauthorized,
+            Self::NoSuchTag => Self::Unauthorized,
             Self::AccessError(_) => Self::Unauthorized,
 
             _ => self,
@@ -206,6 +241,7 @@ impl ErrorKind {
 
             Self::DatabaseError(_) => Self::InternalServerError,
             Self::StorageError(_) => Self::InternalServerError,
+            Self::SignerError(_) => Self::InternalServerError,
             Self::ManifestSerializationError(_) => Self::InternalServerError,
 
             _ => self,
@@ -221,8 +257,14 @@ impl ErrorKind {
             Self::AccessError(_) => StatusCode::FORBIDDEN,
             Self::NoSuchCache => StatusCode::NOT_FOUND,
             Self::NoSuchObject => StatusCode::NOT_FOUND,
+            Self::NoSuchTag => StatusCode::NOT_FOUND,
             Self::CacheAlreadyExists => StatusCode::BAD_REQUEST,
             Self::IncompleteNar => StatusCode::SERVICE_UNAVAILABLE,
+            Self::QuotaExceeded { .. } => StatusCode::INSUFFICIENT_STORAGE,
+            Self::RequestExceedsQuota { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::MalformedNar(_) => StatusCode::BAD_REQUEST,
+            Self::UndeclaredReferences { .. } => StatusCode::BAD_REQUEST,
+            Self::PossessionProofRejected => StatusCode::BAD_REQUEST,
             Self::ManifestSerializationError(_) => StatusCode::BAD_REQUEST,
             Self::RequestError(_) => StatusCode::BAD_REQUEST,
             Self::InvalidCompressionType { .. } => StatusCode::BAD_REQUEST,
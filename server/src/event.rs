@@ -0,0 +1,71 @@
+//! Live events in caches.
+//!
+//! Events are broadcast in-process to subscribers of the event stream
+//! endpoint. They aren't persisted, so subscribers only see events that
+//! happen in the same API server while they are connected.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use tokio::sync::broadcast;
+
+use attic::api::v1::cache_events::PathPushedEvent;
+use attic::api::v1::upload_path::UploadPathNarInfo;
+
+/// Number of events buffered for each subscriber.
+///
+/// Subscribers that fall further behind miss events.
+const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// State for broadcasting events.
+#[derive(Debug)]
+pub(crate) struct Events {
+    sender: broadcast::Sender<Arc<CacheEvent>>,
+}
+
+/// An event in a cache.
+#[derive(Debug)]
+pub(crate) struct CacheEvent {
+    /// ID of the cache the event happened in.
+    pub cache_id: i64,
+
+    /// The store path that was pushed.
+    pub data: PathPushedEvent,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+
+        Self { sender }
+    }
+
+    /// Broadcasts that a store path was pushed to a cache.
+    pub fn path_pushed(&self, cache_id: i64, upload_info: &UploadPathNarInfo) {
+        self.send(
+            cache_id,
+            PathPushedEvent {
+                store_path_hash: upload_info.store_path_hash.clone(),
+                store_path: upload_info.store_path.clone(),
+                references: upload_info.references.clone(),
+                system: upload_info.system.clone(),
+                nar_hash: upload_info.nar_hash.to_typed_base32(),
+                nar_size: upload_info.nar_size as u64,
+                pushed_at: Utc::now(),
+            },
+        );
+    }
+
+    /// Broadcasts an event in a cache.
+    pub fn send(&self, cache_id: i64, data: PathPushedEvent) {
+        let event = CacheEvent { cache_id, data };
+
+        // Fails only if there are no subscribers
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    /// Subscribes to events in all caches.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<CacheEvent>> {
+        self.sender.subscribe()
+    }
+}
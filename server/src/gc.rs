@@ -1,5 +1,6 @@
 //! Garbage collection.
 
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -9,24 +10,40 @@ use futures::future::join_all;
 use sea_orm::entity::prelude::*;
 use sea_orm::query::QuerySelect;
 use sea_orm::sea_query::{LockBehavior, LockType, Query};
-use sea_orm::{ConnectionTrait, FromQueryResult};
+use sea_orm::{Condition, ConnectionTrait, DatabaseConnection, FromQueryResult};
 use tokio::sync::Semaphore;
 use tokio::time;
 use tracing::instrument;
 
 use super::{State, StateInner};
 use crate::config::Config;
+use crate::database::entity::buildlog::{self, Entity as BuildLog};
 use crate::database::entity::cache::{self, Entity as Cache};
 use crate::database::entity::chunk::{self, ChunkState, Entity as Chunk};
 use crate::database::entity::chunkref::{self, Entity as ChunkRef};
 use crate::database::entity::nar::{self, Entity as Nar, NarState};
 use crate::database::entity::object::{self, Entity as Object};
+use crate::database::entity::pin::{self, Entity as Pin};
+use crate::database::entity::possession_challenge::{self, Entity as PossessionChallenge};
+use crate::database::entity::Json;
+use crate::database::AtticDatabase;
+use attic::api::v1::cache_config::WebhookConfig;
+use attic::api::v1::webhook::WebhookEventData;
+use attic::cache::CacheName;
+use attic::nix_store::StorePathHash;
+
+/// Number of objects to delete in a single statement.
+const OBJECT_DELETION_BATCH_SIZE: usize = 1000;
+
+/// Number of build logs of deleted caches to reap in a single run.
+const BUILD_LOG_REAP_LIMIT: u64 = 1000;
 
 #[derive(Debug, FromQueryResult)]
 struct CacheIdAndRetentionPeriod {
     id: i64,
     name: String,
     retention_period: i32,
+    webhooks: Json<Vec<WebhookConfig>>,
 }
 
 /// Runs garbage collection periodically.
@@ -57,6 +74,8 @@ pub async fn run_garbage_collection_once(config: Config) -> Result<()> {
     run_time_based_garbage_collection(&state).await?;
     run_reap_orphan_nars(&state).await?;
     run_reap_orphan_chunks(&state).await?;
+    run_reap_expired_challenges(&state).await?;
+    run_reap_deleted_cache_logs(&state).await?;
 
     Ok(())
 }
@@ -76,6 +95,7 @@ async fn run_time_based_garbage_collection(state: &State) -> Result<()> {
         .column(cache::Column::Id)
         .column(cache::Column::Name)
         .column_as(retention_period.clone(), "retention_period")
+        .column(cache::Column::Webhooks)
         .filter(retention_period.ne(0))
         .into_model::<CacheIdAndRetentionPeriod>()
         .all(db)
@@ -86,6 +106,13 @@ async fn run_time_based_garbage_collection(state: &State) -> Result<()> {
         caches.len()
     );
 
+    let pins_deleted = Pin::delete_many()
+        .filter(pin::Column::ExpiresAt.lt(now))
+        .exec(db)
+        .await?;
+
+    tracing::info!("Deleted {} expired pins", pins_deleted.rows_affected);
+
     let mut objects_deleted = 0;
 
     for cache in caches {
@@ -97,24 +124,63 @@ async fn run_time_based_garbage_collection(state: &State) -> Result<()> {
             )
         })?;
 
-        let deletion = Object::delete_many()
-            .filter(object::Column::CacheId.eq(cache.id))
-            .filter(object::Column::CreatedAt.lt(cutoff))
-            .filter(
+        let expired = Condition::all()
+            .add(object::Column::CacheId.eq(cache.id))
+            .add(object::Column::CreatedAt.lt(cutoff))
+            .add(
                 object::Column::LastAccessedAt
                     .is_null()
                     .or(object::Column::LastAccessedAt.lt(cutoff)),
-            )
-            .exec(db)
-            .await?;
+            );
+
+        // Pinned paths and tag targets are roots of protected closures
+        let mut roots: Vec<String> = db
+            .find_active_pins(cache.id)
+            .await?
+            .into_iter()
+            .map(|pin| pin.store_path_hash)
+            .collect();
+
+        roots.extend(
+            db.find_current_tags(cache.id)
+                .await?
+                .into_iter()
+                .map(|tag| tag.store_path_hash),
+        );
+
+        let rows_affected = if roots.is_empty() {
+            Object::delete_many()
+                .filter(expired)
+                .exec(db)
+                .await?
+                .rows_affected
+        } else {
+            delete_unprotected_objects(db, cache.id, expired, roots).await?
+        };
 
         tracing::info!(
             "Deleted {} objects from {} (ID {})",
-            deletion.rows_affected,
+            rows_affected,
             cache.name,
             cache.id
         );
-        objects_deleted += deletion.rows_affected;
+        objects_deleted += rows_affected;
+
+        if rows_affected > 0 {
+            let cache_name: CacheName = cache.name.parse()?;
+            state
+                .webhooks
+                .notify(
+                    db,
+                    cache.id,
+                    &cache_name,
+                    &cache.webhooks.0,
+                    WebhookEventData::GarbageCollected {
+                        objects_deleted: rows_affected,
+                    },
+                )
+                .await;
+        }
     }
 
     tracing::info!("Deleted {} objects in total", objects_deleted);
@@ -122,6 +188,53 @@ async fn run_time_based_garbage_collection(state: &State) -> Result<()> {
     Ok(())
 }
 
+/// Deletes objects matching a condition, except protected ones.
+///
+/// Objects in the closures of the roots in the cache are protected.
+async fn delete_unprotected_objects(
+    db: &DatabaseConnection,
+    cache_id: i64,
+    condition: Condition,
+    roots: Vec<String>,
+) -> Result<u64> {
+    let roots = roots
+        .into_iter()
+        .map(StorePathHash::new)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let protected: HashSet<i64> = db
+        .find_objects_in_closure(cache_id, &roots, true)
+        .await?
+        .into_iter()
+        .map(|object| object.id)
+        .collect();
+
+    let expired_ids: Vec<i64> = Object::find()
+        .select_only()
+        .column(object::Column::Id)
+        .filter(condition)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let deletable_ids: Vec<i64> = expired_ids
+        .into_iter()
+        .filter(|id| !protected.contains(id))
+        .collect();
+
+    let mut rows_affected = 0;
+    for batch in deletable_ids.chunks(OBJECT_DELETION_BATCH_SIZE) {
+        let deletion = Object::delete_many()
+            .filter(object::Column::Id.is_in(batch.iter().copied()))
+            .exec(db)
+            .await?;
+
+        rows_affected += deletion.rows_affected;
+    }
+
+    Ok(rows_affected)
+}
+
 #[instrument(skip_all)]
 async fn run_reap_orphan_nars(state: &State) -> Result<()> {
     let db = state.database().await?;
@@ -251,3 +364,61 @@ async fn run_reap_orphan_chunks(state: &State) -> Result<()> {
 
     Ok(())
 }
+
+#[instrument(skip_all)]
+async fn run_reap_expired_challenges(state: &State) -> Result<()> {
+    let db = state.database().await?;
+
+    let deletion = PossessionChallenge::delete_many()
+        .filter(possession_challenge::Column::ExpiresAt.lt(Utc::now()))
+        .exec(db)
+        .await?;
+
+    tracing::info!(
+        "Deleted {} expired possession challenges",
+        deletion.rows_affected
+    );
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+async fn run_reap_deleted_cache_logs(state: &State) -> Result<()> {
+    let db = state.database().await?;
+    let storage = state.storage().await?;
+
+    // Build logs are only removed with the cache when it's destroyed
+    // permanently, so the logs of soft-deleted caches are reaped here
+    let logs = BuildLog::find()
+        .inner_join(Cache)
+        .filter(cache::Column::DeletedAt.is_not_null())
+        .limit(BUILD_LOG_REAP_LIMIT)
+        .all(db)
+        .await?;
+
+    if logs.is_empty() {
+        return Ok(());
+    }
+
+    // Logs that failed to be deleted from the remote storage are
+    // retried in the next run
+    let mut deleted_log_ids = Vec::new();
+    for log in logs {
+        match storage.delete_file_db(&log.remote_file.0).await {
+            Ok(()) => deleted_log_ids.push(log.id),
+            Err(e) => tracing::warn!("Deletion failed: {}", e),
+        }
+    }
+
+    let deletion = BuildLog::delete_many()
+        .filter(buildlog::Column::Id.is_in(deleted_log_ids))
+        .exec(db)
+        .await?;
+
+    tracing::info!(
+        "Deleted {} build logs of deleted caches",
+        deletion.rows_affected
+    );
+
+    Ok(())
+}
@@ -15,15 +15,23 @@
 
 pub mod access;
 mod api;
+mod buildlog;
+mod conditional;
 pub mod config;
 pub mod database;
 pub mod error;
+mod event;
 pub mod gc;
 mod middleware;
+mod nar;
 mod narinfo;
 pub mod nix_manifest;
 pub mod oobe;
+mod quota;
+mod signing;
 mod storage;
+mod upstream;
+mod webhook;
 
 use std::future::IntoFuture;
 use std::net::SocketAddr;
@@ -49,8 +57,13 @@ use attic::cache::CacheName;
 use config::{Config, StorageConfig};
 use database::migration::{Migrator, MigratorTrait};
 use error::{ErrorKind, ServerError, ServerResult};
+use event::Events;
 use middleware::{init_request_state, restrict_host, set_visibility_header};
+use quota::QuotaUsage;
+use signing::SignatureCache;
 use storage::{BunnyBackend, LocalBackend, S3Backend, StorageBackend};
+use upstream::Upstreams;
+use webhook::{run_webhook_delivery, Webhooks};
 
 type State = Arc<StateInner>;
 type RequestState = Arc<RequestStateInner>;
@@ -66,6 +79,21 @@ pub struct StateInner {
 
     /// Handle to the storage backend.
     storage: OnceCell<Arc<Box<dyn StorageBackend>>>,
+
+    /// Access to upstream caches.
+    upstreams: Upstreams,
+
+    /// Delivery of webhook notifications.
+    webhooks: Webhooks,
+
+    /// Broadcasting of live events.
+    events: Events,
+
+    /// Signatures returned by external signers.
+    signatures: SignatureCache,
+
+    /// Usage of caches with quotas.
+    quota_usage: QuotaUsage,
 }
 
 /// Request state.
@@ -95,10 +123,17 @@ struct RequestStateInner {
 
 impl StateInner {
     async fn new(config: Config) -> State {
+        let webhooks = Webhooks::new(config.allow_private_webhooks);
+
         Arc::new(Self {
             config,
             database: OnceCell::new(),
             storage: OnceCell::new(),
+            upstreams: Upstreams::new(),
+            webhooks,
+            events: Events::new(),
+            signatures: SignatureCache::new(),
+            quota_usage: QuotaUsage::new(),
         })
     }
 
@@ -248,11 +283,19 @@ pub async fn run_api_server(cli_listen: Option<SocketAddr>, config: Config) -> R
 
     let listener = TcpListener::bind(&listen).await?;
 
-    let (server_ret, _) = tokio::join!(axum::serve(listener, rest).into_future(), async {
-        if state.config.database.heartbeat {
-            let _ = state.run_db_heartbeat().await;
-        }
-    },);
+    let (server_ret, _, _) = tokio::join!(
+        axum::serve(listener, rest).into_future(),
+        async {
+            if state.config.database.heartbeat {
+                let _ = state.run_db_heartbeat().await;
+            }
+        },
+        async {
+            if let Err(e) = run_webhook_delivery(&state).await {
+                tracing::error!("Webhook delivery stopped: {}", e);
+            }
+        },
+    );
 
     server_ret?;
 
@@ -4,7 +4,7 @@ use std::sync::Arc;
 use anyhow::anyhow;
 use axum::{
     extract::{Extension, Host, Request},
-    http::HeaderValue,
+    http::{header, HeaderValue, StatusCode},
     middleware::Next,
     response::Response,
 };
@@ -76,3 +76,38 @@ pub(crate) async fn set_visibility_header(
 
     Ok(response)
 }
+
+/// Sets the `Cache-Control` header in binary cache responses.
+///
+/// Responses from public caches may be stored by shared caches
+/// like CDNs, while responses from private caches may not. Either
+/// way, stored responses must be revalidated before use since
+/// objects can be deleted or replaced at any time. Errors are
+/// never stored.
+pub(crate) async fn set_cache_control_header(
+    Extension(req_state): Extension<RequestState>,
+    req: Request,
+    next: Next,
+) -> ServerResult<Response> {
+    let mut response = next.run(req).await;
+
+    if response.headers().contains_key(header::CACHE_CONTROL) {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    let cache_control = if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+        "no-store"
+    } else if req_state.public_cache.load(Ordering::Relaxed) {
+        "public, no-cache"
+    } else {
+        "private, no-cache"
+    };
+
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control),
+    );
+
+    Ok(response)
+}
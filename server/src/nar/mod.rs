@@ -0,0 +1,377 @@
+//! NAR reassembly.
+//!
+//! A NAR is stored as a sequence of chunks which are compressed
+//! individually. The file we serve to clients is the concatenation
+//! of all compressed chunks, which is a valid stream for all
+//! compression methods we support.
+//!
+//! Since we know the compressed size of each chunk, byte ranges of
+//! the file can be served by only fetching the chunks that overlap
+//! with the range. Likewise, byte ranges of the uncompressed NAR can
+//! be read using the uncompressed size of each chunk.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::VecDeque;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::sync::Arc;
+
+use async_stream::try_stream;
+use bytes::Bytes;
+use futures::stream::{BoxStream, Stream, StreamExt};
+use sea_orm::entity::prelude::*;
+use sea_orm::ActiveValue::Set;
+use sha2::{Digest, Sha256};
+use tokio_util::io::ReaderStream;
+
+use crate::database::entity::chunk::ChunkModel;
+use crate::database::entity::nar::{self, Entity as Nar};
+use crate::error::{ServerError, ServerResult};
+use crate::narinfo::Compression;
+use crate::storage::{Download, StorageBackend};
+use crate::State;
+use attic::api::v1::possession_challenge::RangeCollector;
+use attic::hash::Hash;
+use attic::stream::merge_chunks;
+
+/// Number of chunks to prefetch when reassembling a NAR.
+///
+/// TODO: Make num_prefetch configurable
+/// The ideal size depends on the average chunk size
+const NUM_PREFETCH: usize = 2;
+
+/// A satisfiable byte range of the compressed file.
+///
+/// Both ends are inclusive, as in the `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// The outcome of evaluating a `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RangeRequest {
+    /// The entire file should be served.
+    Full,
+
+    /// A single range of the file should be served.
+    Partial(ByteRange),
+
+    /// The range cannot be satisfied.
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Returns the number of bytes in the range.
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// Returns the value of the `Content-Range` header.
+    pub fn content_range(&self, file_size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, file_size)
+    }
+}
+
+/// Parses the value of a `Range` header for a file of the given size.
+///
+/// Only single byte ranges are supported. Malformed headers and
+/// requests for multiple ranges are ignored, in which case the
+/// entire file is served as permitted by RFC 9110.
+pub(crate) fn parse_range(header: &str, file_size: u64) -> RangeRequest {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let parse = |s: &str| -> Option<Option<u64>> {
+        if s.is_empty() {
+            Some(None)
+        } else if s.bytes().all(|b| b.is_ascii_digit()) {
+            s.parse().ok().map(Some)
+        } else {
+            None
+        }
+    };
+
+    let (Some(first), Some(last)) = (parse(first), parse(last)) else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (first, last) {
+        (Some(start), last) => {
+            if matches!(last, Some(end) if end < start) {
+                return RangeRequest::Full;
+            }
+
+            if start >= file_size {
+                return RangeRequest::Unsatisfiable;
+            }
+
+            let end = last.map_or(file_size - 1, |end| end.min(file_size - 1));
+            ByteRange { start, end }
+        }
+        (None, Some(suffix)) => {
+            if suffix == 0 || file_size == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+
+            ByteRange {
+                start: file_size.saturating_sub(suffix),
+                end: file_size - 1,
+            }
+        }
+        (None, None) => return RangeRequest::Full,
+    };
+
+    RangeRequest::Partial(range)
+}
+
+/// Returns the total size of the compressed file.
+///
+/// Returns `None` if the size of any of the chunks is unknown.
+pub(crate) fn file_size(chunks: &VecDeque<ChunkModel>) -> Option<u64> {
+    chunks
+        .iter()
+        .map(|chunk| chunk.file_size.map(|size| size as u64))
+        .sum()
+}
+
+/// Returns a stream of the compressed file reassembled from chunks.
+pub(crate) fn stream_nar(
+    storage: Arc<Box<dyn StorageBackend + 'static>>,
+    chunks: VecDeque<ChunkModel>,
+) -> impl Stream<Item = Result<Bytes, IoError>> {
+    let streamer = |chunk: ChunkModel, storage: Arc<Box<dyn StorageBackend + 'static>>| async move {
+        match storage
+            .download_file_db(&chunk.remote_file.0, true)
+            .await
+            .map_err(io_error)?
+        {
+            Download::Url(_) => Err(IoError::new(
+                IoErrorKind::Other,
+                "URLs not supported for NAR reassembly",
+            )),
+            Download::AsyncRead(stream) => {
+                let stream: BoxStream<_> = Box::pin(ReaderStream::new(stream));
+                Ok(stream)
+            }
+        }
+    };
+
+    merge_chunks(chunks, streamer, storage, NUM_PREFETCH)
+}
+
+/// Returns a stream of the uncompressed NAR reassembled from chunks.
+///
+/// Each chunk is decompressed individually, so the chunks don't
+/// need to share the same compression.
+pub(crate) fn stream_nar_decompressed(
+    storage: Arc<Box<dyn StorageBackend + 'static>>,
+    chunks: VecDeque<ChunkModel>,
+) -> impl Stream<Item = Result<Bytes, IoError>> {
+    let streamer = |chunk: ChunkModel, storage: Arc<Box<dyn StorageBackend + 'static>>| async move {
+        let compression: Compression = chunk.compression.parse().map_err(io_error)?;
+
+        match storage
+            .download_file_db(&chunk.remote_file.0, true)
+            .await
+            .map_err(io_error)?
+        {
+            Download::Url(_) => Err(IoError::other("URLs not supported for NAR reassembly")),
+            Download::AsyncRead(stream) => {
+                let stream = compression.decompress(stream).map_err(io_error)?;
+                let stream: BoxStream<_> = Box::pin(ReaderStream::new(stream));
+                Ok(stream)
+            }
+        }
+    };
+
+    merge_chunks(chunks, streamer, storage, NUM_PREFETCH)
+}
+
+/// Collects byte ranges of the uncompressed NAR reassembled from chunks.
+///
+/// Only chunks overlapping with the ranges are fetched, and each
+/// chunk is only decompressed up to the end of the last range in it.
+pub(crate) async fn collect_nar_ranges(
+    storage: Arc<Box<dyn StorageBackend + 'static>>,
+    chunks: Vec<ChunkModel>,
+    collector: &mut RangeCollector,
+) -> ServerResult<()> {
+    let mut offset = 0;
+
+    for chunk in chunks {
+        let chunk_end = offset + chunk.chunk_size as u64;
+
+        if collector.overlaps(offset, chunk_end - offset) {
+            let mut stream = Box::pin(stream_nar_decompressed(
+                storage.clone(),
+                VecDeque::from([chunk]),
+            ));
+
+            let mut position = offset;
+            while let Some(bytes) = stream.next().await {
+                let bytes = bytes.map_err(ServerError::storage_error)?;
+                collector.update(position, &bytes);
+                position += bytes.len() as u64;
+
+                if position >= chunk_end || !collector.overlaps(position, chunk_end - position) {
+                    break;
+                }
+            }
+        }
+
+        if collector.is_complete() {
+            break;
+        }
+
+        offset = chunk_end;
+    }
+
+    Ok(())
+}
+
+/// Returns a stream of a byte range of the compressed file.
+///
+/// Only chunks overlapping with the range are fetched. The size of
+/// all chunks must be known.
+pub(crate) fn stream_nar_range(
+    storage: Arc<Box<dyn StorageBackend + 'static>>,
+    chunks: VecDeque<ChunkModel>,
+    range: ByteRange,
+) -> impl Stream<Item = Result<Bytes, IoError>> {
+    let mut offset = 0;
+    let mut skip = 0;
+    let chunks: VecDeque<_> = chunks
+        .into_iter()
+        .filter(|chunk| {
+            let start = offset;
+            offset += chunk.file_size.expect("Chunk has unknown file size") as u64;
+
+            if offset <= range.start || start > range.end {
+                return false;
+            }
+
+            if start < range.start {
+                skip = range.start - start;
+            }
+
+            true
+        })
+        .collect();
+
+    slice_stream(stream_nar(storage, chunks), skip, range.len())
+}
+
+/// Returns a stream that skips some bytes then yields up to `len` bytes.
+///
+/// It's an error if the underlying stream ends before `len` bytes
+/// have been yielded.
+pub(crate) fn slice_stream<S>(
+    stream: S,
+    skip: u64,
+    len: u64,
+) -> impl Stream<Item = Result<Bytes, IoError>>
+where
+    S: Stream<Item = Result<Bytes, IoError>> + Unpin,
+{
+    try_stream! {
+        let mut stream = stream;
+        let mut skip = skip;
+        let mut remaining = len;
+
+        while remaining > 0 {
+            let Some(bytes) = stream.next().await.transpose()? else {
+                Err::<(), _>(IoError::new(
+                    IoErrorKind::UnexpectedEof,
+                    "Stream ended before the end of the range",
+                ))?;
+                break;
+            };
+
+            if skip >= bytes.len() as u64 {
+                skip -= bytes.len() as u64;
+                continue;
+            }
+
+            let bytes = bytes.slice(skip as usize..);
+            skip = 0;
+
+            let take = remaining.min(bytes.len() as u64);
+            remaining -= take;
+
+            yield bytes.slice(..take as usize);
+        }
+    }
+}
+
+/// Wraps a stream of the compressed file to record its hash and size.
+///
+/// The file hash and size are saved to the NAR before the last piece
+/// is yielded. The response body may not be polled past the expected
+/// length, so we can't wait for the end of the stream.
+pub(crate) fn record_file_hash<S>(
+    state: State,
+    nar_id: i64,
+    stream: S,
+) -> impl Stream<Item = Result<Bytes, IoError>>
+where
+    S: Stream<Item = Result<Bytes, IoError>> + Unpin,
+{
+    try_stream! {
+        let mut stream = stream;
+        let mut hasher = Sha256::new();
+        let mut file_size = 0;
+        let mut pending = stream.next().await.transpose()?;
+
+        while let Some(bytes) = pending {
+            hasher.update(&bytes);
+            file_size += bytes.len();
+
+            pending = stream.next().await.transpose()?;
+            if pending.is_none() {
+                let file_hash = Hash::Sha256(hasher.clone().finalize().into());
+                if let Err(e) = save_file_hash(&state, nar_id, file_hash, file_size).await {
+                    tracing::warn!("Failed to save file hash of NAR {}: {}", nar_id, e);
+                }
+            }
+
+            yield bytes;
+        }
+    }
+}
+
+async fn save_file_hash(
+    state: &State,
+    nar_id: i64,
+    file_hash: Hash,
+    file_size: usize,
+) -> ServerResult<()> {
+    let database = state.database().await?;
+
+    Nar::update(nar::ActiveModel {
+        id: Set(nar_id),
+        file_hash: Set(Some(file_hash.to_typed_base16())),
+        file_size: Set(Some(file_size as i64)),
+        ..Default::default()
+    })
+    .exec(database)
+    .await
+    .map_err(ServerError::database_error)?;
+
+    Ok(())
+}
+
+fn io_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> IoError {
+    IoError::new(IoErrorKind::Other, e)
+}
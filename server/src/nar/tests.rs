@@ -0,0 +1,66 @@
+use super::*;
+
+use futures::stream;
+
+fn range(start: u64, end: u64) -> RangeRequest {
+    RangeRequest::Partial(ByteRange { start, end })
+}
+
+async fn collect<S>(stream: S) -> Result<Vec<u8>, IoError>
+where
+    S: Stream<Item = Result<Bytes, IoError>>,
+{
+    let mut stream = Box::pin(stream);
+    let mut buf = Vec::new();
+
+    while let Some(bytes) = stream.next().await {
+        buf.extend_from_slice(&bytes?);
+    }
+
+    Ok(buf)
+}
+
+#[test]
+fn test_parse_range() {
+    assert_eq!(range(0, 99), parse_range("bytes=0-99", 1000));
+    assert_eq!(range(100, 999), parse_range("bytes=100-", 1000));
+    assert_eq!(range(900, 999), parse_range("bytes=-100", 1000));
+    assert_eq!(range(0, 999), parse_range("bytes=-5000", 1000));
+    assert_eq!(range(500, 999), parse_range("bytes=500-5000", 1000));
+    assert_eq!(range(999, 999), parse_range("bytes=999-999", 1000));
+
+    assert_eq!(
+        RangeRequest::Unsatisfiable,
+        parse_range("bytes=1000-", 1000)
+    );
+    assert_eq!(RangeRequest::Unsatisfiable, parse_range("bytes=-0", 1000));
+    assert_eq!(RangeRequest::Unsatisfiable, parse_range("bytes=0-", 0));
+
+    // ignored
+    assert_eq!(RangeRequest::Full, parse_range("bytes=0-1,5-6", 1000));
+    assert_eq!(RangeRequest::Full, parse_range("bytes=10-5", 1000));
+    assert_eq!(RangeRequest::Full, parse_range("bytes=-", 1000));
+    assert_eq!(RangeRequest::Full, parse_range("bytes=+1-2", 1000));
+    assert_eq!(RangeRequest::Full, parse_range("items=0-1", 1000));
+    assert_eq!(RangeRequest::Full, parse_range("bytes=abc", 1000));
+}
+
+#[tokio::test]
+async fn test_slice_stream() {
+    let data: Vec<u8> = (0..100).collect();
+    let chunks = || {
+        stream::iter(
+            data.chunks(7)
+                .map(|c| Ok(Bytes::copy_from_slice(c)))
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    for (skip, len) in [(0, 100), (0, 1), (3, 4), (7, 7), (6, 30), (99, 1)] {
+        let sliced = collect(slice_stream(chunks(), skip, len)).await.unwrap();
+        assert_eq!(&data[skip as usize..(skip + len) as usize], sliced);
+    }
+
+    let e = collect(slice_stream(chunks(), 90, 20)).await.unwrap_err();
+    assert_eq!(IoErrorKind::UnexpectedEof, e.kind());
+}
@@ -42,24 +42,25 @@ use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::string::ToString;
 
+use async_compression::tokio::bufread::{BrotliDecoder, XzDecoder, ZstdDecoder};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use serde::de;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use tokio::io::{AsyncRead, BufReader};
 
 use crate::error::{ErrorKind, ServerError, ServerResult};
 use crate::nix_manifest::{self, SpaceDelimitedList};
 use attic::hash::Hash;
 use attic::mime;
-use attic::signing::NixKeypair;
 
 #[cfg(test)]
 mod tests;
 
 /// NAR information.
 #[serde_as]
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct NarInfo {
     /// The full store path being cached, including the store directory.
     ///
@@ -134,13 +135,13 @@ pub struct NarInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub deriver: Option<String>,
 
-    /// The signature of the object.
+    /// The signatures of the object.
     ///
-    /// The `Sig` field can be duplicated to include multiple
-    /// signatures, but we only support one for now.
+    /// Each signature is a separate `Sig` field.
     #[serde(rename = "Sig")]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub signature: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<String>,
 
     /// The content address of the object.
     #[serde(rename = "CA")]
@@ -174,9 +175,9 @@ impl NarInfo {
         nix_manifest::to_string(self)
     }
 
-    /// Returns the signature of this object, if it exists.
-    pub fn signature(&self) -> Option<&String> {
-        self.signature.as_ref()
+    /// Returns the signatures of this object.
+    pub fn signatures(&self) -> &[String] {
+        &self.signatures
     }
 
     /// Returns the store directory of this object.
@@ -185,10 +186,13 @@ impl NarInfo {
         self.store_path.parent().unwrap()
     }
 
-    /// Signs the narinfo and adds the signature to the narinfo.
-    pub fn sign(&mut self, keypair: &NixKeypair) {
-        let signature = self.sign_readonly(keypair);
-        self.signature = Some(signature);
+    /// Adds a signature to the narinfo.
+    ///
+    /// The signature isn't added again if it already exists.
+    pub fn add_signature(&mut self, signature: String) {
+        if !self.signatures.contains(&signature) {
+            self.signatures.push(signature);
+        }
     }
 
     /// Returns the fingerprint of the object.
@@ -226,12 +230,6 @@ impl NarInfo {
 
         fingerprint
     }
-
-    /// Signs the narinfo with a keypair, returning the signature.
-    fn sign_readonly(&self, keypair: &NixKeypair) -> String {
-        let fingerprint = self.fingerprint();
-        keypair.sign(&fingerprint)
-    }
 }
 
 impl IntoResponse for NarInfo {
@@ -258,6 +256,27 @@ impl Compression {
             Self::Zstd => "zstd",
         }
     }
+
+    /// Wraps a stream with a decompressor.
+    pub fn decompress<R>(&self, stream: R) -> ServerResult<Box<dyn AsyncRead + Unpin + Send>>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let stream = BufReader::new(stream);
+
+        Ok(match self {
+            Self::None => Box::new(stream),
+            Self::Xz => Box::new(XzDecoder::new(stream)),
+            Self::Brotli => Box::new(BrotliDecoder::new(stream)),
+            Self::Zstd => Box::new(ZstdDecoder::new(stream)),
+            Self::Bzip2 => {
+                return Err(ErrorKind::InvalidCompressionType {
+                    name: self.to_string(),
+                }
+                .into());
+            }
+        })
+    }
 }
 
 impl FromStr for Compression {
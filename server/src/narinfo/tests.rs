@@ -2,7 +2,7 @@ use super::*;
 
 use std::path::Path;
 
-use attic::signing::NixPublicKey;
+use attic::signing::{NixKeypair, NixPublicKey};
 
 #[test]
 fn test_basic() {
@@ -53,7 +53,7 @@ Sig: cache.nixos.org-1:lo9EfNIL4eGRuNh7DTbAAffWPpI2SlYC/8uP7JnhgmfRIUNGhSbFe8qEa
             Some("vvb4wxmnjixmrkhmj2xb75z62hrr41i7-hello-2.10.drv".to_string()),
             narinfo.deriver
         );
-        assert_eq!(Some("cache.nixos.org-1:lo9EfNIL4eGRuNh7DTbAAffWPpI2SlYC/8uP7JnhgmfRIUNGhSbFe8qEaKN0mFS02TuhPpXFPNtRkFcCp0hGAQ==".to_string()), narinfo.signature);
+        assert_eq!(vec!["cache.nixos.org-1:lo9EfNIL4eGRuNh7DTbAAffWPpI2SlYC/8uP7JnhgmfRIUNGhSbFe8qEaKN0mFS02TuhPpXFPNtRkFcCp0hGAQ==".to_string()], narinfo.signatures);
     }
 
     verify_narinfo(&narinfo);
@@ -123,6 +123,39 @@ Sig: cache.nixos.org-1:lo9EfNIL4eGRuNh7DTbAAffWPpI2SlYC/8uP7JnhgmfRIUNGhSbFe8qEa
     assert_eq!(correct_fingerprint, fingerprint.as_slice());
 
     public_key
-        .verify(&narinfo.fingerprint(), narinfo.signature().unwrap())
+        .verify(&narinfo.fingerprint(), &narinfo.signatures()[0])
+        .expect("Could not verify signature");
+}
+
+#[test]
+fn test_multiple_signatures() {
+    let s = r#"
+StorePath: /nix/store/xcp9cav49dmsjbwdjlmkjxj10gkpx553-hello-2.10
+URL: nar/0nqgf15qfiacfxrgm2wkw0gwwncjqqzzalj8rs14w9srkydkjsk9.nar.xz
+Compression: xz
+NarHash: sha256:16mvl7v0ylzcg2n3xzjn41qhzbmgcn5iyarx16nn5l2r36n2kqci
+NarSize: 206104
+References: 563528481rvhc5kxwipjmg6rqrl95mdx-glibc-2.33-56 xcp9cav49dmsjbwdjlmkjxj10gkpx553-hello-2.10
+Sig: cache.nixos.org-1:lo9EfNIL4eGRuNh7DTbAAffWPpI2SlYC/8uP7JnhgmfRIUNGhSbFe8qEaKN0mFS02TuhPpXFPNtRkFcCp0hGAQ==
+Sig: release-1:AAAA
+    "#;
+
+    let mut narinfo = NarInfo::from_str(s).expect("Could not parse narinfo");
+    assert_eq!(2, narinfo.signatures().len());
+
+    let keypair = NixKeypair::generate("attic-test").expect("Could not generate key");
+    let signature = keypair.sign(&narinfo.fingerprint());
+    narinfo.add_signature(signature.clone());
+    narinfo.add_signature(signature);
+    assert_eq!(3, narinfo.signatures().len());
+
+    let round_trip = narinfo.to_string().expect("Could not serialize narinfo");
+    assert_eq!(3, round_trip.matches("\nSig: ").count());
+
+    let reparse = NarInfo::from_str(&round_trip).expect("Could not re-parse serialized narinfo");
+    assert_eq!(narinfo.signatures(), reparse.signatures());
+
+    keypair
+        .verify(&reparse.fingerprint(), &reparse.signatures()[2])
         .expect("Could not verify signature");
 }
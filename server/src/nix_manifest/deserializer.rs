@@ -4,6 +4,7 @@
 
 use std::ops::{AddAssign, MulAssign};
 
+use serde::de::value::SeqDeserializer;
 use serde::de::{DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
 use serde::{de, forward_to_deserialize_any};
 
@@ -12,6 +13,15 @@ use super::{Error, Result};
 /// The main deserializer.
 pub struct Deserializer<'de> {
     input: &'de str,
+
+    /// The key being deserialized.
+    current_key: Option<&'de str>,
+
+    /// Keys that have been deserialized as sequences.
+    ///
+    /// Subsequent occurrences of those keys have already been
+    /// consumed and are skipped.
+    seq_keys: Vec<&'de str>,
 }
 
 /// Deserializer for values.
@@ -19,7 +29,11 @@ pub struct ValueDeserializer<'a, 'de: 'a>(&'a mut Deserializer<'de>);
 
 impl<'de> Deserializer<'de> {
     pub fn from_str(input: &'de str) -> Self {
-        Deserializer { input }
+        Deserializer {
+            input,
+            current_key: None,
+            seq_keys: Vec::new(),
+        }
     }
 }
 
@@ -148,6 +162,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         let identifier = &self.input[..colon];
 
         self.input = &self.input[colon..];
+        self.current_key = Some(identifier);
         visitor.visit_borrowed_str(identifier)
     }
 }
@@ -159,10 +174,20 @@ impl<'de> MapAccess<'de> for Deserializer<'de> {
     where
         K: DeserializeSeed<'de>,
     {
-        self.consume_whitespace()?;
+        loop {
+            self.consume_whitespace()?;
+
+            if self.input.is_empty() {
+                return Ok(None);
+            }
 
-        if self.input.is_empty() {
-            return Ok(None);
+            let line = self.peek_until_eol()?;
+            match line.split_once(':') {
+                Some((key, _)) if self.seq_keys.contains(&key) => {
+                    self.parse_until_eol()?;
+                }
+                _ => break,
+            }
         }
 
         seed.deserialize(&mut *self).map(Some)
@@ -335,11 +360,25 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut ValueDeserializer<'a, 'de> {
         visitor.visit_newtype_struct(self)
     }
 
-    fn deserialize_seq<V>(self, _visitor: V) -> Result<V::Value>
+    /// Deserializes all occurrences of the current key as a sequence.
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        Err(Error::Unsupported("Sequence"))
+        let key = self.0.current_key.ok_or(Error::Unsupported("Sequence"))?;
+
+        let mut values = vec![self.0.parse_until_eol()?.trim_start()];
+        for line in self.0.input.lines() {
+            if let Some((k, v)) = line.trim_start().split_once(':') {
+                if k == key {
+                    values.push(v.trim_start());
+                }
+            }
+        }
+
+        self.0.seq_keys.push(key);
+
+        visitor.visit_seq(SeqDeserializer::new(values.into_iter()))
     }
 
     fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
@@ -10,6 +10,12 @@ use super::{Error, Result};
 pub struct Serializer {
     output: String,
     seen_map: bool,
+
+    /// The struct field being serialized and where its line starts.
+    current_field: Option<(&'static str, usize)>,
+
+    /// Whether we are serializing a sequence as repeated keys.
+    in_seq: bool,
 }
 
 impl Serializer {
@@ -17,6 +23,8 @@ impl Serializer {
         Self {
             output: String::new(),
             seen_map: false,
+            current_field: None,
+            in_seq: false,
         }
     }
 
@@ -158,8 +166,26 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     // Compund types
+
+    /// Serializes a sequence as repeated keys.
+    ///
+    /// This is only supported for struct fields. For example,
+    /// `sigs: vec!["a", "b"]` is serialized as:
+    ///
+    /// ```text
+    /// Sig: a
+    /// Sig: b
+    /// ```
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        Err(Error::Unsupported("Sequence"))
+        match self.current_field {
+            Some((_, line_start)) if !self.in_seq => {
+                // Remove the key that has already been written
+                self.output.truncate(line_start);
+                self.in_seq = true;
+                Ok(self)
+            }
+            _ => Err(Error::Unsupported("Sequence")),
+        }
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
@@ -213,16 +239,22 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     type Error = Error;
 
     // Serialize a single element of the sequence.
-    fn serialize_element<T>(&mut self, _value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        Err(Error::Unsupported("Sequence"))
+        let (key, _) = self.current_field.ok_or(Error::Unsupported("Sequence"))?;
+
+        key.serialize(&mut **self)?;
+        self.output += ": ";
+        value.serialize(&mut **self)?;
+        self.output += "\n";
+        Ok(())
     }
 
     // Close the sequence.
     fn end(self) -> Result<()> {
-        Err(Error::Unsupported("Sequence"))
+        Ok(())
     }
 }
 
@@ -307,10 +339,20 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
+        self.current_field = Some((key, self.output.len()));
+
         key.serialize(&mut **self)?;
         self.output += ": ";
         value.serialize(&mut **self)?;
-        self.output += "\n";
+
+        if self.in_seq {
+            // Each element has been written as a separate line
+            self.in_seq = false;
+        } else {
+            self.output += "\n";
+        }
+
+        self.current_field = None;
         Ok(())
     }
 
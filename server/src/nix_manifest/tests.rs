@@ -53,3 +53,54 @@ WantMassQuery: 1
     let parsed = super::from_str::<HypotheticalManifest>(manifest).unwrap();
     assert_eq!(parsed, expected);
 }
+
+#[test]
+fn test_repeated_keys() {
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct RepeatedManifest {
+        #[serde(rename = "StoreDir")]
+        store_dir: PathBuf,
+
+        #[serde(rename = "Sig")]
+        #[serde(default)]
+        sigs: Vec<String>,
+
+        #[serde(rename = "WantMassQuery")]
+        want_mass_query: bool,
+    }
+
+    let manifest = r#"
+StoreDir: /nix/store
+Sig: a
+Sig: b
+WantMassQuery: 1
+Sig: c
+    "#;
+
+    let expected = RepeatedManifest {
+        store_dir: PathBuf::from("/nix/store"),
+        sigs: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+        want_mass_query: true,
+    };
+
+    let parsed = super::from_str::<RepeatedManifest>(manifest).unwrap();
+    assert_eq!(parsed, expected);
+
+    let round_trip = super::to_string(&parsed).unwrap();
+    assert_eq!(
+        "StoreDir: /nix/store\nSig: a\nSig: b\nSig: c\nWantMassQuery: 1\n",
+        round_trip
+    );
+
+    let parsed2 = super::from_str::<RepeatedManifest>(&round_trip).unwrap();
+    assert_eq!(parsed2, expected);
+
+    // no occurrences
+    let empty = RepeatedManifest {
+        sigs: Vec::new(),
+        ..expected
+    };
+    let serialized = super::to_string(&empty).unwrap();
+    assert_eq!("StoreDir: /nix/store\nWantMassQuery: 1\n", serialized);
+    assert_eq!(empty, super::from_str(&serialized).unwrap());
+}
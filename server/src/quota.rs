@@ -0,0 +1,257 @@
+//! Storage quotas.
+//!
+//! A cache can have a quota on the total NAR size of its objects and
+//! on the number of its objects. The NAR size is counted even if the
+//! NAR is deduplicated or compressed, so the usage of a cache doesn't
+//! depend on what other caches contain.
+//!
+//! Quotas are checked before objects are added. Concurrent uploads
+//! are not serialized, so a cache can exceed its quota slightly.
+//!
+//! Counting the usage of a large cache is expensive, so the usage is
+//! remembered for a short while and only counted again when a cache
+//! gets close to one of its quotas. Objects added in between are added
+//! to the remembered usage, so it can only overestimate the usage of
+//! this server. Uploads through other API servers sharing the database
+//! are only noticed once the usage is counted again.
+
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::Alias;
+use sea_orm::{FromQueryResult, JoinType, QuerySelect, RelationTrait, Select};
+
+use crate::database::entity::cache::CacheModel;
+use crate::database::entity::nar;
+use crate::database::entity::object::{self, Entity as Object};
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::State;
+
+/// Time for which a counted usage is remembered.
+const USAGE_TTL: Duration = Duration::from_secs(60);
+
+/// Maximum number of caches to remember the usage of.
+const USAGE_CACHE_SIZE: usize = 1000;
+
+/// Number of store path hashes to look up in a single query.
+const LOOKUP_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Default, FromQueryResult)]
+struct Usage {
+    object_count: i64,
+    nar_size: Option<i64>,
+}
+
+/// The remembered usage of a cache.
+#[derive(Debug, Clone, Copy)]
+struct CachedUsage {
+    objects: u64,
+    nar_size: u64,
+    counted_at: Instant,
+}
+
+/// Usage of caches, remembered between uploads.
+#[derive(Debug)]
+pub(crate) struct QuotaUsage {
+    cache: Mutex<LruCache<i64, CachedUsage>>,
+}
+
+/// Objects to be added to a cache.
+#[derive(Debug)]
+pub(crate) struct Addition<'a> {
+    /// The number of objects.
+    pub objects: u64,
+
+    /// The total NAR size of the objects.
+    pub nar_size: u64,
+
+    /// Store path hashes of the objects.
+    ///
+    /// Existing objects with the same store paths are replaced,
+    /// so they don't count toward the usage.
+    pub store_path_hashes: Vec<&'a str>,
+}
+
+impl QuotaUsage {
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(NonZeroUsize::new(USAGE_CACHE_SIZE).unwrap())),
+        }
+    }
+
+    /// Adds objects to the remembered usage of a cache if it's far from its quotas.
+    ///
+    /// Returns false if the usage needs to be counted.
+    fn try_add(&self, cache: &CacheModel, addition: &Addition<'_>) -> bool {
+        let mut usage_cache = self.cache.lock().unwrap();
+
+        let Some(usage) = usage_cache.get_mut(&cache.id) else {
+            return false;
+        };
+
+        if usage.counted_at.elapsed() > USAGE_TTL {
+            return false;
+        }
+
+        let objects = usage.objects + addition.objects;
+        let nar_size = usage.nar_size + addition.nar_size;
+
+        if is_close(objects, cache.quota_objects) || is_close(nar_size, cache.quota_bytes) {
+            return false;
+        }
+
+        usage.objects = objects;
+        usage.nar_size = nar_size;
+
+        true
+    }
+
+    /// Remembers the counted usage of a cache, including objects to be added.
+    fn put(&self, cache_id: i64, objects: u64, nar_size: u64) {
+        self.cache.lock().unwrap().put(
+            cache_id,
+            CachedUsage {
+                objects,
+                nar_size,
+                counted_at: Instant::now(),
+            },
+        );
+    }
+}
+
+/// Checks that adding objects keeps a cache within its quotas.
+pub(crate) async fn check_quota(
+    state: &State,
+    cache: &CacheModel,
+    addition: &Addition<'_>,
+) -> ServerResult<()> {
+    if cache.quota_bytes.is_none() && cache.quota_objects.is_none() {
+        return Ok(());
+    }
+
+    let quota_bytes = cache.quota_bytes.map(|quota| quota as u64);
+    let quota_objects = cache.quota_objects.map(|quota| quota as u64);
+
+    if let Some(quota) = quota_bytes {
+        if addition.nar_size > quota {
+            return Err(ErrorKind::RequestExceedsQuota {
+                quota: format!("{} bytes", quota),
+            }
+            .into());
+        }
+    }
+
+    if let Some(quota) = quota_objects {
+        if addition.objects > quota {
+            return Err(ErrorKind::RequestExceedsQuota {
+                quota: format!("{} objects", quota),
+            }
+            .into());
+        }
+    }
+
+    if state.quota_usage.try_add(cache, addition) {
+        return Ok(());
+    }
+
+    let database = state.database().await?;
+
+    let usage = usage_query(cache.id)
+        .into_model::<Usage>()
+        .one(database)
+        .await
+        .map_err(ServerError::database_error)?
+        .unwrap_or_default();
+
+    let mut object_count = usage.object_count as u64;
+    let mut nar_size = usage.nar_size.unwrap_or(0) as u64;
+
+    let exceeds = |object_count: u64, nar_size: u64| {
+        quota_objects.is_some_and(|quota| object_count + addition.objects > quota)
+            || quota_bytes.is_some_and(|quota| nar_size + addition.nar_size > quota)
+    };
+
+    // Objects that would be replaced only matter if the cache
+    // would otherwise exceed its quota
+    if exceeds(object_count, nar_size) {
+        for batch in addition.store_path_hashes.chunks(LOOKUP_BATCH_SIZE) {
+            let replaced = usage_query(cache.id)
+                .filter(object::Column::StorePathHash.is_in(batch.iter().copied()))
+                .into_model::<Usage>()
+                .one(database)
+                .await
+                .map_err(ServerError::database_error)?
+                .unwrap_or_default();
+
+            object_count = object_count.saturating_sub(replaced.object_count as u64);
+            nar_size = nar_size.saturating_sub(replaced.nar_size.unwrap_or(0) as u64);
+        }
+    }
+
+    if let Some(quota) = quota_bytes {
+        if nar_size + addition.nar_size > quota {
+            return Err(ErrorKind::QuotaExceeded {
+                quota: format!("{} bytes", quota),
+            }
+            .into());
+        }
+    }
+
+    if let Some(quota) = quota_objects {
+        if object_count + addition.objects > quota {
+            return Err(ErrorKind::QuotaExceeded {
+                quota: format!("{} objects", quota),
+            }
+            .into());
+        }
+    }
+
+    state.quota_usage.put(
+        cache.id,
+        object_count + addition.objects,
+        nar_size + addition.nar_size,
+    );
+
+    Ok(())
+}
+
+/// Returns a query for the number and total NAR size of objects in a cache.
+fn usage_query(cache_id: i64) -> Select<Object> {
+    Object::find()
+        .select_only()
+        .column_as(object::Column::Id.count(), "object_count")
+        .column_as(
+            nar::Column::NarSize.sum().cast_as(Alias::new("BIGINT")),
+            "nar_size",
+        )
+        .join(JoinType::InnerJoin, object::Relation::Nar.def())
+        .filter(object::Column::CacheId.eq(cache_id))
+}
+
+/// Returns whether a usage is within a tenth of a quota.
+fn is_close(usage: u64, quota: Option<i64>) -> bool {
+    match quota {
+        Some(quota) => usage.saturating_mul(10) >= (quota as u64).saturating_mul(9),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_close() {
+        assert!(!is_close(0, None));
+        assert!(!is_close(u64::MAX, None));
+        assert!(!is_close(0, Some(1000)));
+        assert!(!is_close(899, Some(1000)));
+        assert!(is_close(900, Some(1000)));
+        assert!(is_close(2000, Some(1000)));
+        assert!(is_close(0, Some(0)));
+        assert!(!is_close(1, Some(i64::MAX)));
+    }
+}
@@ -0,0 +1,366 @@
+//! Signing of cache objects.
+//!
+//! The keypair of a cache is either stored in the database or held
+//! by an external signer configured on the server. External signers
+//! are handed the fingerprints of objects and return signatures, so
+//! the secret key never passes through us.
+//!
+//! Two kinds of external signers are supported:
+//!
+//! - `command`: A process is spawned for each signature. The fingerprint
+//!   is written to its standard input, and the signature is read from
+//!   its standard output.
+//! - `socket`: A connection is made to a Unix socket for each signature.
+//!   The fingerprint is written to the connection, after which the write
+//!   half is shut down. The signer responds with the signature and closes
+//!   the connection.
+//!
+//! In both cases, the signature is in the canonical format used by Nix
+//! (`name:base64`). Signatures returned by external signers are verified
+//! against the public key of the cache before use, and are cached so
+//! the signer isn't invoked every time an object is requested.
+//!
+//! An external signer can only be used by the caches listed in its
+//! configuration, since anyone who can create caches could otherwise
+//! have arbitrary paths signed with its keypair.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use lru::LruCache;
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::process::Command;
+use tokio::time;
+
+use crate::database::entity::cache::CacheModel;
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::narinfo::NarInfo;
+use crate::State;
+use attic::api::v1::cache_config::KeypairState;
+use attic::cache::{CacheName, CacheNamePattern};
+use attic::signing::{NixKeypair, NixPublicKey};
+
+/// The maximum time an external signer may take to sign.
+const SIGNER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum size of a signature returned by an external signer.
+const MAX_SIGNATURE_SIZE: u64 = 4096;
+
+/// The maximum number of signatures from external signers to cache.
+const SIGNATURE_CACHE_SIZE: usize = 16384;
+
+/// External signer configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum SignerConfig {
+    /// A command that signs fingerprints.
+    #[serde(rename = "command")]
+    Command {
+        /// The public key of the keypair held by the signer.
+        #[serde(rename = "public-key")]
+        public_key: String,
+
+        /// Caches that can use the signer.
+        caches: Vec<CacheNamePattern>,
+
+        /// The program and its arguments.
+        command: Vec<String>,
+    },
+
+    /// A signing service listening on a Unix socket.
+    #[serde(rename = "socket")]
+    Socket {
+        /// The public key of the keypair held by the signer.
+        #[serde(rename = "public-key")]
+        public_key: String,
+
+        /// Caches that can use the signer.
+        caches: Vec<CacheNamePattern>,
+
+        /// Path to the socket.
+        path: PathBuf,
+    },
+}
+
+/// A key that objects can be signed with.
+pub(crate) enum SigningKey<'a> {
+    /// A keypair stored in the database.
+    Local(NixKeypair),
+
+    /// A keypair held by an external signer.
+    External {
+        /// Name of the signer.
+        name: &'a str,
+
+        /// The signer.
+        signer: &'a SignerConfig,
+
+        /// The public key recorded for the cache.
+        public_key: NixPublicKey,
+
+        /// Signatures returned by external signers.
+        signatures: &'a SignatureCache,
+    },
+}
+
+/// Signatures returned by external signers.
+///
+/// Signatures are keyed by the public key and the signed message.
+#[derive(Debug)]
+pub(crate) struct SignatureCache {
+    cache: Mutex<LruCache<(String, Vec<u8>), String>>,
+}
+
+impl SignerConfig {
+    /// Returns the public key of the keypair held by the signer.
+    pub fn public_key(&self) -> &str {
+        match self {
+            Self::Command { public_key, .. } => public_key,
+            Self::Socket { public_key, .. } => public_key,
+        }
+    }
+
+    /// Returns whether a cache can use the signer.
+    pub fn allows_cache(&self, cache: &CacheName) -> bool {
+        let caches = match self {
+            Self::Command { caches, .. } => caches,
+            Self::Socket { caches, .. } => caches,
+        };
+
+        caches.iter().any(|pattern| pattern.matches(cache))
+    }
+
+    /// Asks the signer to sign a message.
+    async fn sign(&self, message: &[u8]) -> anyhow::Result<String> {
+        let signature = match self {
+            Self::Command { command, .. } => sign_with_command(command, message).await?,
+            Self::Socket { path, .. } => sign_with_socket(path, message).await?,
+        };
+
+        Ok(signature.trim().to_string())
+    }
+}
+
+impl SigningKey<'_> {
+    /// Signs a message, returning its canonical representation.
+    pub async fn sign(&self, message: &[u8]) -> ServerResult<String> {
+        match self {
+            Self::Local(keypair) => Ok(keypair.sign(message)),
+            Self::External {
+                name,
+                signer,
+                public_key,
+                signatures,
+            } => {
+                let key = (public_key.export(), message.to_vec());
+                if let Some(signature) = signatures.get(&key) {
+                    return Ok(signature);
+                }
+
+                let signature = time::timeout(SIGNER_TIMEOUT, signer.sign(message))
+                    .await
+                    .map_err(|_| anyhow!("Signer \"{}\" timed out", name))
+                    .and_then(|result| result)
+                    .map_err(|e| {
+                        ErrorKind::SignerError(e.context(format!("Signer \"{}\" failed", name)))
+                    })?;
+
+                public_key.verify(message, &signature).map_err(|e| {
+                    ErrorKind::SignerError(anyhow!(
+                        "Signer \"{}\" returned an invalid signature: {}",
+                        name,
+                        e
+                    ))
+                })?;
+
+                signatures.put(key, signature.clone());
+
+                Ok(signature)
+            }
+        }
+    }
+}
+
+impl SignatureCache {
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(SIGNATURE_CACHE_SIZE).unwrap(),
+            )),
+        }
+    }
+
+    fn get(&self, key: &(String, Vec<u8>)) -> Option<String> {
+        self.cache.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: (String, Vec<u8>), signature: String) {
+        self.cache.lock().unwrap().put(key, signature);
+    }
+}
+
+/// Returns a configured external signer that a cache can use.
+pub(crate) fn get_signer<'a>(
+    signers: &'a HashMap<String, SignerConfig>,
+    name: &str,
+    cache: &CacheName,
+) -> ServerResult<&'a SignerConfig> {
+    let signer = signers
+        .get(name)
+        .ok_or_else(|| ErrorKind::RequestError(anyhow!("Signer \"{}\" is not configured", name)))?;
+
+    if !signer.allows_cache(cache) {
+        return Err(ErrorKind::RequestError(anyhow!(
+            "Signer \"{}\" cannot be used by cache \"{}\"",
+            name,
+            cache.as_str()
+        ))
+        .into());
+    }
+
+    Ok(signer)
+}
+
+/// Returns all keys that objects in a cache should be signed with.
+///
+/// This includes the current keypair and retiring keypairs. Retiring
+/// keypairs that can't be used (e.g., their signer was removed from the
+/// configuration) are skipped with a warning.
+pub(crate) fn signing_keys<'a>(
+    state: &'a State,
+    cache: &'a CacheModel,
+) -> ServerResult<Vec<SigningKey<'a>>> {
+    let mut keys = vec![match &cache.signer {
+        Some(name) => external_key(state, cache, name, &cache.keypair)?,
+        None => SigningKey::Local(cache.keypair()?),
+    }];
+
+    for previous in &cache.previous_keypairs.0 {
+        if previous.state != KeypairState::Retiring {
+            continue;
+        }
+
+        let key = if let Some(name) = &previous.signer {
+            external_key(state, cache, name, &previous.public_key)
+        } else if let Some(keypair) = &previous.keypair {
+            NixKeypair::from_str(keypair)
+                .map(SigningKey::Local)
+                .map_err(ServerError::from)
+        } else {
+            continue;
+        };
+
+        match key {
+            Ok(key) => keys.push(key),
+            Err(e) => tracing::warn!(
+                "Skipping retiring key {} of cache {}: {}",
+                previous.public_key,
+                cache.name,
+                e
+            ),
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Signs a narinfo with keys.
+pub(crate) async fn sign_nar_info(
+    keys: &[SigningKey<'_>],
+    narinfo: &mut NarInfo,
+) -> ServerResult<()> {
+    let fingerprint = narinfo.fingerprint();
+
+    for key in keys {
+        let signature = key.sign(&fingerprint).await?;
+        narinfo.add_signature(signature);
+    }
+
+    Ok(())
+}
+
+fn external_key<'a>(
+    state: &'a State,
+    cache: &CacheModel,
+    name: &'a str,
+    public_key: &str,
+) -> ServerResult<SigningKey<'a>> {
+    let cache_name: CacheName = cache.name.parse()?;
+    let signer = state
+        .config
+        .signers
+        .get(name)
+        .filter(|signer| signer.allows_cache(&cache_name))
+        .ok_or_else(|| {
+            ErrorKind::SignerError(anyhow!(
+                "Signer \"{}\" is not configured for cache \"{}\"",
+                name,
+                cache.name
+            ))
+        })?;
+
+    Ok(SigningKey::External {
+        name,
+        signer,
+        public_key: NixPublicKey::from_str(public_key)?,
+        signatures: &state.signatures,
+    })
+}
+
+async fn sign_with_command(command: &[String], message: &[u8]) -> anyhow::Result<String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("The command is empty"))?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(message).await?;
+    drop(stdin);
+
+    let mut signature = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .take(MAX_SIGNATURE_SIZE)
+        .read_to_string(&mut signature)
+        .await?;
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow!("The command exited with {}", status));
+    }
+
+    Ok(signature)
+}
+
+async fn sign_with_socket(path: &Path, message: &[u8]) -> anyhow::Result<String> {
+    let mut stream = UnixStream::connect(path).await?;
+    stream.write_all(message).await?;
+    stream.shutdown().await?;
+
+    let mut signature = String::new();
+    stream
+        .take(MAX_SIGNATURE_SIZE)
+        .read_to_string(&mut signature)
+        .await?;
+
+    Ok(signature)
+}
@@ -0,0 +1,216 @@
+use super::*;
+
+use std::collections::HashMap;
+use std::env;
+
+use tokio::net::UnixListener;
+use uuid::Uuid;
+
+const MESSAGE: &[u8] = b"1;/nix/store/xcp9cav49dmsjbwdjlmkjxj10gkpx553-hello-2.10;sha256:16mvl7v0ylzcg2n3xzjn41qhzbmgcn5iyarx16nn5l2r36n2kqci;206104;";
+
+fn external_key<'a>(
+    signer: &'a SignerConfig,
+    keypair: &NixKeypair,
+    signatures: &'a SignatureCache,
+) -> SigningKey<'a> {
+    SigningKey::External {
+        name: "test",
+        signer,
+        public_key: keypair.to_public_key(),
+        signatures,
+    }
+}
+
+fn echo_command(keypair: &NixKeypair, output: &str) -> SignerConfig {
+    SignerConfig::Command {
+        public_key: keypair.export_public_key(),
+        caches: Vec::new(),
+        command: vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("cat > /dev/null; echo '{}'", output),
+        ],
+    }
+}
+
+#[test]
+fn test_parse_signer_config() {
+    let config: HashMap<String, SignerConfig> = toml::from_str(
+        r#"
+        [hsm]
+        type = "command"
+        public-key = "hsm:C929acssgtJoINkUtLbc81GFJPUW9maR77TxEu9ZpRw="
+        caches = ["release"]
+        command = ["/usr/bin/sign", "--key", "cache"]
+
+        [service]
+        type = "socket"
+        public-key = "service:C929acssgtJoINkUtLbc81GFJPUW9maR77TxEu9ZpRw="
+        caches = ["team-*"]
+        path = "/run/signer.sock"
+        "#,
+    )
+    .expect("Could not parse signer configuration");
+
+    assert!(matches!(
+        &config["hsm"],
+        SignerConfig::Command { command, .. } if command.len() == 3
+    ));
+    assert!(matches!(
+        &config["service"],
+        SignerConfig::Socket { path, .. } if path == Path::new("/run/signer.sock")
+    ));
+    assert_eq!(
+        "service:C929acssgtJoINkUtLbc81GFJPUW9maR77TxEu9ZpRw=",
+        config["service"].public_key()
+    );
+
+    // the caches are required
+    toml::from_str::<HashMap<String, SignerConfig>>(
+        r#"
+        [hsm]
+        type = "command"
+        public-key = "hsm:C929acssgtJoINkUtLbc81GFJPUW9maR77TxEu9ZpRw="
+        command = ["/usr/bin/sign"]
+        "#,
+    )
+    .unwrap_err();
+}
+
+#[test]
+fn test_get_signer() {
+    let keypair = NixKeypair::generate("attic-test").unwrap();
+    let cache = |name: &str| CacheName::new(name.to_string()).unwrap();
+
+    let mut signers = HashMap::new();
+    signers.insert(
+        "release".to_string(),
+        SignerConfig::Command {
+            public_key: keypair.export_public_key(),
+            caches: vec![
+                CacheNamePattern::new("release".to_string()).unwrap(),
+                CacheNamePattern::new("team-*".to_string()).unwrap(),
+            ],
+            command: vec!["/usr/bin/sign".to_string()],
+        },
+    );
+
+    assert!(get_signer(&signers, "release", &cache("release")).is_ok());
+    assert!(get_signer(&signers, "release", &cache("team-a")).is_ok());
+
+    // not allowed to use the signer
+    let e = get_signer(&signers, "release", &cache("throwaway")).unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::RequestError(_)));
+    let e = get_signer(&signers, "release", &cache("releases")).unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::RequestError(_)));
+
+    // not configured
+    let e = get_signer(&signers, "other", &cache("release")).unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::RequestError(_)));
+}
+
+#[tokio::test]
+async fn test_command_signer() {
+    let keypair = NixKeypair::generate("attic-test").unwrap();
+    let signature = keypair.sign(MESSAGE);
+
+    let signer = echo_command(&keypair, &signature);
+    let signatures = SignatureCache::new();
+    let key = external_key(&signer, &keypair, &signatures);
+
+    assert_eq!(signature, key.sign(MESSAGE).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_command_signer_failure() {
+    let keypair = NixKeypair::generate("attic-test").unwrap();
+
+    let signer = SignerConfig::Command {
+        public_key: keypair.export_public_key(),
+        caches: Vec::new(),
+        command: vec!["sh".to_string(), "-c".to_string(), "exit 1".to_string()],
+    };
+    let signatures = SignatureCache::new();
+    let key = external_key(&signer, &keypair, &signatures);
+
+    let e = key.sign(MESSAGE).await.unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::SignerError(_)));
+}
+
+#[tokio::test]
+async fn test_invalid_signature() {
+    let keypair = NixKeypair::generate("attic-test").unwrap();
+    let other = NixKeypair::generate("attic-test").unwrap();
+
+    // signed with a different keypair
+    let signer = echo_command(&keypair, &other.sign(MESSAGE));
+    let signatures = SignatureCache::new();
+    let key = external_key(&signer, &keypair, &signatures);
+
+    let e = key.sign(MESSAGE).await.unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::SignerError(_)));
+}
+
+#[tokio::test]
+async fn test_socket_signer() {
+    let keypair = NixKeypair::generate("attic-test").unwrap();
+    let path = env::temp_dir().join(format!("attic-signer-{}.sock", Uuid::new_v4()));
+    let listener = UnixListener::bind(&path).unwrap();
+
+    // A stand-in signing service that holds the keypair
+    let service_keypair = NixKeypair::from_str(&keypair.export_keypair()).unwrap();
+    let service = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        let mut message = Vec::new();
+        stream.read_to_end(&mut message).await.unwrap();
+
+        let signature = service_keypair.sign(&message);
+        stream.write_all(signature.as_bytes()).await.unwrap();
+    });
+
+    let signer = SignerConfig::Socket {
+        public_key: keypair.export_public_key(),
+        caches: Vec::new(),
+        path: path.clone(),
+    };
+    let signatures = SignatureCache::new();
+    let key = external_key(&signer, &keypair, &signatures);
+
+    let signature = key.sign(MESSAGE).await;
+    service.await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(keypair.sign(MESSAGE), signature.unwrap());
+}
+
+#[tokio::test]
+async fn test_signature_cache() {
+    let keypair = NixKeypair::generate("attic-test").unwrap();
+    let signature = keypair.sign(MESSAGE);
+    let counter = env::temp_dir().join(format!("attic-signer-{}.count", Uuid::new_v4()));
+
+    let signer = SignerConfig::Command {
+        public_key: keypair.export_public_key(),
+        caches: Vec::new(),
+        command: vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!(
+                "cat > /dev/null; echo >> '{}'; echo '{}'",
+                counter.display(),
+                signature
+            ),
+        ],
+    };
+    let signatures = SignatureCache::new();
+    let key = external_key(&signer, &keypair, &signatures);
+
+    assert_eq!(signature, key.sign(MESSAGE).await.unwrap());
+    assert_eq!(signature, key.sign(MESSAGE).await.unwrap());
+
+    let invocations = std::fs::read_to_string(&counter).unwrap().lines().count();
+    std::fs::remove_file(&counter).unwrap();
+
+    assert_eq!(1, invocations);
+}
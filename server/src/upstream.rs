@@ -0,0 +1,428 @@
+//! Pull-through upstream caches.
+//!
+//! A cache can have a list of upstream Nix binary caches that are
+//! consulted in order when a store path doesn't exist in the cache.
+//! The narinfo of the first hit is served with the NAR URL pointing
+//! back to us, and the NAR is streamed through from the upstream. The
+//! URL is distinct from that of NARs in the cache, so clients holding
+//! the narinfo never get the NAR we ingest, which may be compressed
+//! differently.
+//!
+//! Upstream caches must be allowed by `allowed-upstreams` in the server
+//! configuration, and NARs are only fetched from the origin of the
+//! upstream they were found in.
+//!
+//! If ingestion is enabled, the NAR is also fetched in the background
+//! and uploaded through the regular upload pipeline, so subsequent
+//! requests are served locally. The upload pipeline validates the NAR
+//! hash against the upstream narinfo.
+
+use std::collections::HashSet;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use axum::Json;
+use bytes::Bytes;
+use futures::stream::{Stream, TryStreamExt};
+use reqwest::{Client, StatusCode, Url};
+use tokio::task::spawn;
+use tokio_util::io::StreamReader;
+
+use crate::api::v1::upload_path::upload_path_to_cache;
+use crate::database::entity::cache::CacheModel;
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::narinfo::NarInfo;
+use crate::State;
+use attic::api::v1::upload_path::UploadPathNarInfo;
+use attic::nix_store::{StorePath, StorePathHash};
+
+/// State for accessing upstream caches.
+#[derive(Debug)]
+pub(crate) struct Upstreams {
+    /// The HTTP client.
+    client: Client,
+
+    /// Objects being ingested, as `(cache_id, store_path_hash)`.
+    ingesting: Mutex<HashSet<(i64, StorePathHash)>>,
+}
+
+/// A store path found in an upstream cache.
+pub(crate) struct UpstreamHit {
+    /// Base URL of the upstream cache.
+    pub upstream: Url,
+
+    /// The hash of the store path.
+    pub store_path_hash: StorePathHash,
+
+    /// The narinfo served by the upstream cache.
+    pub narinfo: NarInfo,
+}
+
+/// Removes an object from the ingestion set when dropped.
+struct IngestGuard<'a> {
+    upstreams: &'a Upstreams,
+    key: (i64, StorePathHash),
+}
+
+impl Upstreams {
+    pub fn new() -> Self {
+        let client = Client::builder()
+            .user_agent(concat!("attic/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to initialize HTTP client");
+
+        Self {
+            client,
+            ingesting: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Finds a store path in the upstream caches of a cache.
+    ///
+    /// Upstreams that aren't allowed or fail to respond are skipped.
+    pub async fn find_nar_info(
+        &self,
+        allowed_upstreams: &[String],
+        cache: &CacheModel,
+        store_path_hash: &StorePathHash,
+    ) -> ServerResult<Option<UpstreamHit>> {
+        for upstream in &cache.upstream_urls.0 {
+            let upstream = match parse_upstream_url(upstream, allowed_upstreams) {
+                Ok(upstream) => upstream,
+                Err(e) => {
+                    tracing::warn!("Skipping upstream {}: {}", upstream, e);
+                    continue;
+                }
+            };
+
+            match self.fetch_nar_info(&upstream, store_path_hash).await {
+                Ok(Some(narinfo)) => {
+                    if !narinfo_matches(&narinfo, cache, store_path_hash) {
+                        tracing::warn!(
+                            "Upstream {} returned a mismatched narinfo for {}",
+                            upstream,
+                            store_path_hash.as_str()
+                        );
+                        continue;
+                    }
+
+                    let hit = UpstreamHit {
+                        upstream,
+                        store_path_hash: store_path_hash.clone(),
+                        narinfo,
+                    };
+
+                    if let Err(e) = hit.nar_url() {
+                        tracing::warn!(
+                            "Upstream {} returned a narinfo for {} with a bad URL: {}",
+                            hit.upstream,
+                            store_path_hash.as_str(),
+                            e
+                        );
+                        continue;
+                    }
+
+                    return Ok(Some(hit));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to query upstream {}: {}", upstream, e);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns a stream of the compressed NAR from the upstream cache.
+    ///
+    /// The size of the file is also returned if known.
+    pub async fn stream_nar(
+        &self,
+        hit: &UpstreamHit,
+    ) -> ServerResult<(
+        impl Stream<Item = Result<Bytes, IoError>> + Send + 'static,
+        Option<u64>,
+    )> {
+        let url = hit.nar_url()?;
+
+        let res = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(ServerError::storage_error)?;
+
+        let file_size = res.content_length();
+        let stream = res.bytes_stream().map_err(IoError::other);
+
+        Ok((stream, file_size))
+    }
+
+    async fn fetch_nar_info(
+        &self,
+        upstream: &Url,
+        store_path_hash: &StorePathHash,
+    ) -> ServerResult<Option<NarInfo>> {
+        let url = upstream
+            .join(&format!("{}.narinfo", store_path_hash.as_str()))
+            .map_err(ServerError::request_error)?;
+
+        let res = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(ServerError::storage_error)?;
+
+        if matches!(res.status(), StatusCode::NOT_FOUND | StatusCode::FORBIDDEN) {
+            return Ok(None);
+        }
+
+        let manifest = res
+            .error_for_status()
+            .map_err(ServerError::storage_error)?
+            .text()
+            .await
+            .map_err(ServerError::storage_error)?;
+
+        NarInfo::from_str(&manifest).map(Some)
+    }
+
+    fn lock_ingest(
+        &self,
+        cache_id: i64,
+        store_path_hash: StorePathHash,
+    ) -> Option<IngestGuard<'_>> {
+        let key = (cache_id, store_path_hash);
+        if !self.ingesting.lock().unwrap().insert(key.clone()) {
+            return None;
+        }
+
+        Some(IngestGuard {
+            upstreams: self,
+            key,
+        })
+    }
+}
+
+impl UpstreamHit {
+    /// Returns the URL of the NAR in the upstream cache.
+    ///
+    /// The URL comes from the upstream, so it must not leave
+    /// the origin of the upstream.
+    fn nar_url(&self) -> ServerResult<Url> {
+        let url = self
+            .upstream
+            .join(&self.narinfo.url)
+            .map_err(ServerError::request_error)?;
+
+        if url.origin() != self.upstream.origin() {
+            return Err(ErrorKind::RequestError(anyhow!(
+                "NAR URL leaves the origin of the upstream cache"
+            ))
+            .into());
+        }
+
+        Ok(url)
+    }
+
+    /// Returns the narinfo to serve to clients.
+    ///
+    /// The NAR URL is rewritten to point to us. The upstream
+    /// signatures are kept as-is.
+    pub fn to_nar_info(&self) -> NarInfo {
+        NarInfo {
+            url: format!("nar/{}.upstream.nar", self.store_path_hash.as_str()),
+            ..self.narinfo.clone()
+        }
+    }
+}
+
+impl Drop for IngestGuard<'_> {
+    fn drop(&mut self) {
+        self.upstreams.ingesting.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// Parses and validates the URL of an upstream cache.
+///
+/// The upstream must be allowed by one of the URL prefixes in
+/// `allowed_upstreams`. The returned URL always ends with a slash
+/// so that paths can be joined to it.
+pub(crate) fn parse_upstream_url(url: &str, allowed_upstreams: &[String]) -> ServerResult<Url> {
+    let url = normalize_url(url)?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ErrorKind::RequestError(anyhow!(
+            "Upstream caches must be accessed over HTTP(S)"
+        ))
+        .into());
+    }
+
+    let allowed = allowed_upstreams
+        .iter()
+        .filter_map(|prefix| normalize_url(prefix).ok())
+        .any(|prefix| url.origin() == prefix.origin() && url.path().starts_with(prefix.path()));
+
+    if !allowed {
+        return Err(ErrorKind::RequestError(anyhow!(
+            "Upstream cache {} is not allowed by the server",
+            url
+        ))
+        .into());
+    }
+
+    Ok(url)
+}
+
+/// Parses a URL, ensuring that it ends with a slash.
+fn normalize_url(url: &str) -> ServerResult<Url> {
+    Url::parse(&format!("{}/", url.trim_end_matches('/'))).map_err(ServerError::request_error)
+}
+
+/// Returns whether a narinfo from an upstream is for the requested store path.
+fn narinfo_matches(narinfo: &NarInfo, cache: &CacheModel, store_path_hash: &StorePathHash) -> bool {
+    if narinfo.store_dir() != Path::new(&cache.store_dir) {
+        return false;
+    }
+
+    narinfo
+        .store_path
+        .file_name()
+        .and_then(|name| StorePath::from_base_name(PathBuf::from(name)).ok())
+        .map(|store_path| store_path.to_hash() == *store_path_hash)
+        .unwrap_or(false)
+}
+
+/// Ingests a store path found in an upstream cache in the background.
+///
+/// Nothing is done if the store path is already being ingested.
+pub(crate) fn spawn_ingest(state: State, cache: CacheModel, hit: UpstreamHit) {
+    spawn(async move {
+        let store_path_hash = hit.store_path_hash.clone();
+        let Some(_guard) = state
+            .upstreams
+            .lock_ingest(cache.id, store_path_hash.clone())
+        else {
+            return;
+        };
+
+        if let Err(e) = ingest(&state, cache, hit).await {
+            tracing::warn!(
+                "Failed to ingest {} from upstream: {}",
+                store_path_hash.as_str(),
+                e
+            );
+        }
+    });
+}
+
+/// Fetches a NAR from an upstream cache and uploads it to the cache.
+async fn ingest(state: &State, cache: CacheModel, hit: UpstreamHit) -> ServerResult<()> {
+    let narinfo = &hit.narinfo;
+    let (stream, _) = state.upstreams.stream_nar(&hit).await?;
+    let stream = narinfo.compression.decompress(StreamReader::new(stream))?;
+
+    let upload_info = UploadPathNarInfo {
+        cache: cache.name.parse()?,
+        store_path_hash: hit.store_path_hash.clone(),
+        store_path: narinfo.store_path.to_string_lossy().into_owned(),
+        references: narinfo.references.clone(),
+        system: narinfo.system.clone(),
+        deriver: narinfo.deriver.clone(),
+        sigs: narinfo.signatures().to_vec(),
+        ca: narinfo.ca.clone(),
+        nar_hash: narinfo.nar_hash.clone(),
+        nar_size: narinfo.nar_size,
+    };
+
+    let Json(result) = upload_path_to_cache(state, cache, upload_info, stream, None).await?;
+
+    tracing::info!(
+        "Ingested {} from upstream {} ({:?})",
+        narinfo.store_path.display(),
+        hit.upstream,
+        result.kind
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_upstream_url() {
+        let allowed = vec![
+            "https://cache.nixos.org".to_string(),
+            "https://example.com/caches/".to_string(),
+        ];
+
+        assert_eq!(
+            "https://cache.nixos.org/",
+            parse_upstream_url("https://cache.nixos.org", &allowed)
+                .unwrap()
+                .as_str()
+        );
+        assert!(parse_upstream_url("https://example.com/caches/public", &allowed).is_ok());
+
+        // not in the allowlist
+        assert!(parse_upstream_url("http://10.0.0.1", &allowed).is_err());
+        assert!(parse_upstream_url("http://cache.nixos.org", &allowed).is_err());
+        assert!(parse_upstream_url("https://cache.nixos.org:8443", &allowed).is_err());
+        assert!(parse_upstream_url("https://example.com/other", &allowed).is_err());
+        assert!(parse_upstream_url("https://example.com/cachesfoo", &allowed).is_err());
+        assert!(parse_upstream_url("https://cache.nixos.org", &[]).is_err());
+
+        // not HTTP(S)
+        let allowed = vec!["ftp://example.com".to_string()];
+        assert!(parse_upstream_url("ftp://example.com", &allowed).is_err());
+    }
+
+    #[test]
+    fn test_nar_url() {
+        let hit = |url: &str| {
+            let narinfo = NarInfo::from_str(&format!(
+                "StorePath: /nix/store/xcp9cav49dmsjbwdjlmkjxj10gkpx553-hello-2.10\n\
+                 URL: {}\n\
+                 Compression: xz\n\
+                 NarHash: sha256:1mkvday29m2qxg1fnbv8xh9s6151bh8a2xzhh0k86j7lqhyfwibh\n\
+                 NarSize: 226560\n\
+                 References: \n",
+                url
+            ))
+            .unwrap();
+
+            UpstreamHit {
+                upstream: parse_upstream_url(
+                    "https://example.com/cache",
+                    &["https://example.com".to_string()],
+                )
+                .unwrap(),
+                store_path_hash: StorePathHash::new("xcp9cav49dmsjbwdjlmkjxj10gkpx553".to_string())
+                    .unwrap(),
+                narinfo,
+            }
+        };
+
+        assert_eq!(
+            "https://example.com/cache/nar/abc.nar.xz",
+            hit("nar/abc.nar.xz").nar_url().unwrap().as_str()
+        );
+        assert_eq!(
+            "https://example.com/nar/abc.nar.xz",
+            hit("/nar/abc.nar.xz").nar_url().unwrap().as_str()
+        );
+
+        assert!(hit("http://10.0.0.1/nar/abc.nar.xz").nar_url().is_err());
+        assert!(hit("//10.0.0.1/nar/abc.nar.xz").nar_url().is_err());
+        assert!(hit("http://example.com/nar/abc.nar.xz").nar_url().is_err());
+    }
+}
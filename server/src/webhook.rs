@@ -0,0 +1,622 @@
+//! Webhook notifications.
+//!
+//! Events in a cache are turned into one pending delivery per matching
+//! webhook, which are queued in the database. The API server delivers
+//! them in the background, retrying failed deliveries with exponential
+//! backoff. Deliveries are claimed by pushing their next attempt into the
+//! future, so multiple API servers sharing the database don't deliver
+//! the same notification at the same time.
+//!
+//! Queueing never fails the operation that caused the event. Deliveries
+//! can be queued by any process (e.g., the garbage collector), and are
+//! picked up by the API server on its next poll. Pending deliveries to
+//! webhooks that have since been removed from the cache, or whose cache
+//! has been deleted, are dropped.
+//!
+//! Unless `allow-private-webhooks` is set, notifications are never sent
+//! to non-public addresses. Host names are checked when they are
+//! resolved for each delivery, so a name that later resolves to the
+//! internal network is still refused. Redirects aren't followed.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono::{Duration as ChronoDuration, Utc};
+use futures::future::join_all;
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{QueryOrder, QuerySelect};
+use sha2::Sha256;
+use tokio::net::lookup_host;
+use tokio::sync::Notify;
+use tokio::time;
+
+use crate::database::entity::cache::{self, Entity as Cache};
+use crate::database::entity::webhook_delivery::{
+    self, Entity as WebhookDelivery, WebhookDeliveryModel,
+};
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::StateInner;
+use attic::api::v1::cache_config::WebhookConfig;
+use attic::api::v1::webhook::{
+    WebhookEvent, WebhookEventData, WebhookPayload, ATTIC_DELIVERY, ATTIC_EVENT, ATTIC_SIGNATURE,
+};
+use attic::cache::CacheName;
+
+/// Interval to poll for due deliveries.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of deliveries to queue in a single statement.
+const QUEUE_BATCH_SIZE: usize = 100;
+
+/// Maximum number of deliveries to attempt at once.
+const DELIVERY_BATCH_SIZE: u64 = 100;
+
+/// Maximum time a delivery may take.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time a claimed delivery is reserved for us.
+///
+/// This must be longer than `DELIVERY_TIMEOUT`.
+const CLAIM_LEASE: Duration = Duration::from_secs(60);
+
+/// Number of attempts after which a delivery is given up on.
+const MAX_ATTEMPTS: i32 = 10;
+
+/// Delay before the first retry.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Maximum delay between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum length of an error message to keep.
+const MAX_ERROR_LENGTH: usize = 1024;
+
+/// State for delivering webhook notifications.
+#[derive(Debug)]
+pub(crate) struct Webhooks {
+    /// The HTTP client.
+    client: Client,
+
+    /// Wakes the delivery loop when deliveries are queued.
+    wakeup: Notify,
+
+    /// Whether notifications can be sent to non-public addresses.
+    allow_private: bool,
+}
+
+/// A DNS resolver that only returns public addresses.
+struct PublicResolver;
+
+impl Webhooks {
+    pub fn new(allow_private: bool) -> Self {
+        let mut builder = Client::builder()
+            .user_agent(concat!("attic/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(DELIVERY_TIMEOUT)
+            .redirect(Policy::none());
+
+        if !allow_private {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+
+        let client = builder.build().expect("Failed to initialize HTTP client");
+
+        Self {
+            client,
+            wakeup: Notify::new(),
+            allow_private,
+        }
+    }
+
+    /// Queues notifications of an event in a cache.
+    ///
+    /// Failures are logged and otherwise ignored.
+    pub async fn notify(
+        &self,
+        db: &DatabaseConnection,
+        cache_id: i64,
+        cache: &CacheName,
+        webhooks: &[WebhookConfig],
+        data: WebhookEventData,
+    ) {
+        self.notify_many(db, cache_id, cache, webhooks, vec![data])
+            .await
+    }
+
+    /// Queues notifications of several events in a cache.
+    ///
+    /// Failures are logged and otherwise ignored.
+    pub async fn notify_many(
+        &self,
+        db: &DatabaseConnection,
+        cache_id: i64,
+        cache: &CacheName,
+        webhooks: &[WebhookConfig],
+        events: Vec<WebhookEventData>,
+    ) {
+        let now = Utc::now();
+        let mut deliveries = Vec::new();
+
+        for data in events {
+            let event = data.event();
+            let webhooks: Vec<&WebhookConfig> =
+                webhooks.iter().filter(|w| w.wants(event)).collect();
+
+            if webhooks.is_empty() {
+                continue;
+            }
+
+            let payload = WebhookPayload {
+                cache: cache.clone(),
+                timestamp: now,
+                data,
+            };
+
+            let payload = match serde_json::to_string(&payload) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::warn!("Failed to serialize webhook payload: {}", e);
+                    continue;
+                }
+            };
+
+            deliveries.extend(
+                webhooks
+                    .into_iter()
+                    .map(|webhook| webhook_delivery::ActiveModel {
+                        cache_id: Set(Some(cache_id)),
+                        url: Set(webhook.url.clone()),
+                        secret: Set(webhook.secret.clone()),
+                        event: Set(event.as_str().to_string()),
+                        payload: Set(payload.clone()),
+                        attempts: Set(0),
+                        next_attempt_at: Set(now),
+                        created_at: Set(now),
+                        last_error: Set(None),
+                        ..Default::default()
+                    }),
+            );
+        }
+
+        if deliveries.is_empty() {
+            return;
+        }
+
+        for batch in deliveries.chunks(QUEUE_BATCH_SIZE) {
+            if let Err(e) = WebhookDelivery::insert_many(batch.to_vec())
+                .exec_without_returning(db)
+                .await
+            {
+                tracing::warn!(
+                    "Failed to queue notifications for {}: {}",
+                    cache.as_str(),
+                    e
+                );
+                break;
+            }
+        }
+
+        self.wakeup.notify_one();
+    }
+
+    /// Attempts all due deliveries once.
+    async fn deliver_due(&self, db: &DatabaseConnection) -> ServerResult<()> {
+        let now = Utc::now();
+
+        let due = WebhookDelivery::find()
+            .filter(webhook_delivery::Column::NextAttemptAt.lte(now))
+            .order_by_asc(webhook_delivery::Column::NextAttemptAt)
+            .limit(DELIVERY_BATCH_SIZE)
+            .all(db)
+            .await
+            .map_err(ServerError::database_error)?;
+
+        let lease_end = now + ChronoDuration::from_std(CLAIM_LEASE).unwrap();
+        let mut claimed = Vec::new();
+
+        for delivery in due {
+            // Someone else may have claimed it since we looked
+            let claim = WebhookDelivery::update_many()
+                .col_expr(
+                    webhook_delivery::Column::NextAttemptAt,
+                    Expr::value(lease_end),
+                )
+                .filter(webhook_delivery::Column::Id.eq(delivery.id))
+                .filter(webhook_delivery::Column::NextAttemptAt.eq(delivery.next_attempt_at))
+                .exec(db)
+                .await
+                .map_err(ServerError::database_error)?;
+
+            if claim.rows_affected == 1 {
+                claimed.push(delivery);
+            }
+        }
+
+        let results = join_all(
+            claimed
+                .into_iter()
+                .map(|delivery| self.attempt_delivery(db, delivery)),
+        )
+        .await;
+
+        results.into_iter().collect()
+    }
+
+    /// Attempts a claimed delivery and records the outcome.
+    async fn attempt_delivery(
+        &self,
+        db: &DatabaseConnection,
+        delivery: WebhookDeliveryModel,
+    ) -> ServerResult<()> {
+        if !webhook_exists(db, &delivery).await? {
+            tracing::debug!(
+                "Dropping {} notification to {} of a removed webhook",
+                delivery.event,
+                delivery.url
+            );
+            return delete_delivery(db, delivery.id).await;
+        }
+
+        let error = match self.send(&delivery).await {
+            Ok(()) => {
+                tracing::debug!(
+                    "Delivered {} notification to {}",
+                    delivery.event,
+                    delivery.url
+                );
+                return delete_delivery(db, delivery.id).await;
+            }
+            Err(e) => e,
+        };
+
+        let attempts = delivery.attempts + 1;
+        if attempts >= MAX_ATTEMPTS {
+            tracing::warn!(
+                "Giving up on {} notification to {} after {} attempts: {}",
+                delivery.event,
+                delivery.url,
+                attempts,
+                error
+            );
+            return delete_delivery(db, delivery.id).await;
+        }
+
+        tracing::info!(
+            "Failed to deliver {} notification to {} (attempt {}): {}",
+            delivery.event,
+            delivery.url,
+            attempts,
+            error
+        );
+
+        let mut last_error = error.to_string();
+        if last_error.len() > MAX_ERROR_LENGTH {
+            let mut end = MAX_ERROR_LENGTH;
+            while !last_error.is_char_boundary(end) {
+                end -= 1;
+            }
+            last_error.truncate(end);
+        }
+
+        let next_attempt_at = Utc::now() + ChronoDuration::from_std(backoff(attempts)).unwrap();
+
+        WebhookDelivery::update(webhook_delivery::ActiveModel {
+            id: Set(delivery.id),
+            attempts: Set(attempts),
+            next_attempt_at: Set(next_attempt_at),
+            last_error: Set(Some(last_error)),
+            ..Default::default()
+        })
+        .exec(db)
+        .await
+        .map_err(ServerError::database_error)?;
+
+        Ok(())
+    }
+
+    /// Sends a notification.
+    async fn send(&self, delivery: &WebhookDeliveryModel) -> anyhow::Result<()> {
+        // Addresses in the URL aren't resolved, so they are checked here
+        if !self.allow_private {
+            let url = Url::parse(&delivery.url)?;
+            check_host(&url)?;
+        }
+
+        let mut request = self
+            .client
+            .post(&delivery.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ATTIC_EVENT, &delivery.event)
+            .header(ATTIC_DELIVERY, delivery.id.to_string());
+
+        if let Some(secret) = &delivery.secret {
+            request = request.header(
+                ATTIC_SIGNATURE,
+                sign_payload(secret, delivery.payload.as_bytes()),
+            );
+        }
+
+        let response = request.body(delivery.payload.clone()).send().await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("The receiver responded with {}", response.status()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Delivers queued notifications until the server stops.
+pub(crate) async fn run_webhook_delivery(state: &StateInner) -> ServerResult<()> {
+    let db = state.database().await?;
+
+    loop {
+        // We don't stop even if it errors
+        if let Err(e) = state.webhooks.deliver_due(db).await {
+            tracing::warn!("Failed to deliver webhook notifications: {}", e);
+        }
+
+        let _ = time::timeout(POLL_INTERVAL, state.webhooks.wakeup.notified()).await;
+    }
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> = lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_address(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(anyhow!("{} doesn't resolve to a public address", host).into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Validates the configuration of webhooks.
+///
+/// Unless `allow_private` is set, webhooks can't be sent to non-public
+/// addresses or `localhost`. Host names that resolve to non-public
+/// addresses are refused when notifications are delivered.
+pub(crate) fn validate_webhooks(
+    webhooks: &[WebhookConfig],
+    allow_private: bool,
+) -> ServerResult<()> {
+    for webhook in webhooks {
+        let url = Url::parse(&webhook.url).map_err(|e| {
+            ErrorKind::RequestError(anyhow!("Invalid webhook URL \"{}\": {}", webhook.url, e))
+        })?;
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(ErrorKind::RequestError(anyhow!(
+                "Webhook URL \"{}\" must be HTTP or HTTPS",
+                webhook.url
+            ))
+            .into());
+        }
+
+        if !allow_private {
+            check_host(&url).map_err(|e| {
+                ErrorKind::RequestError(anyhow!("Invalid webhook URL \"{}\": {}", webhook.url, e))
+            })?;
+        }
+
+        if webhook.secret.as_deref() == Some("") {
+            return Err(ErrorKind::RequestError(anyhow!("Webhook secrets cannot be empty")).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the host of a URL isn't obviously private.
+fn check_host(url: &Url) -> anyhow::Result<()> {
+    let host = url.host_str().unwrap_or_default();
+    let public = if host.is_empty() {
+        false
+    } else if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse() {
+        is_public_address(ip)
+    } else {
+        let domain = host.trim_end_matches('.').to_ascii_lowercase();
+        domain != "localhost" && !domain.ends_with(".localhost")
+    };
+
+    if !public {
+        return Err(anyhow!("Webhooks can't be sent to private addresses"));
+    }
+
+    Ok(())
+}
+
+/// Returns whether an address is publicly routable.
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ipv4(ip);
+            }
+
+            let segments = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local (fc00::/7)
+                || (segments[0] & 0xfe00) == 0xfc00
+                // Link-local (fe80::/10)
+                || (segments[0] & 0xffc0) == 0xfe80
+                // IPv4-compatible, deprecated (::/96)
+                || (segments[..6] == [0; 6] && ip != Ipv6Addr::UNSPECIFIED))
+        }
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "This network" (0.0.0.0/8)
+        || octets[0] == 0
+        // Shared address space (100.64.0.0/10)
+        || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+        // Reserved (240.0.0.0/4)
+        || octets[0] >= 240)
+}
+
+/// Returns the signature of a payload, as `sha256=<hex>`.
+fn sign_payload(secret: &str, payload: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(payload);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Returns the delay before an attempt after a number of failed attempts.
+fn backoff(attempts: i32) -> Duration {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.pow(exponent))
+        .min(MAX_BACKOFF)
+}
+
+/// Returns whether the webhook of a delivery is still configured on a live cache.
+///
+/// Notifications of destroyed caches are always delivered.
+async fn webhook_exists(
+    db: &DatabaseConnection,
+    delivery: &WebhookDeliveryModel,
+) -> ServerResult<bool> {
+    let Some(cache_id) = delivery.cache_id else {
+        return Ok(true);
+    };
+
+    if delivery.event == WebhookEvent::CacheDestroyed.as_str() {
+        return Ok(true);
+    }
+
+    let cache = Cache::find_by_id(cache_id)
+        .filter(cache::Column::DeletedAt.is_null())
+        .one(db)
+        .await
+        .map_err(ServerError::database_error)?;
+
+    Ok(cache.is_some_and(|cache| {
+        cache
+            .webhooks
+            .0
+            .iter()
+            .any(|webhook| webhook.url == delivery.url)
+    }))
+}
+
+async fn delete_delivery(db: &DatabaseConnection, id: i64) -> ServerResult<()> {
+    WebhookDelivery::delete_by_id(id)
+        .exec(db)
+        .await
+        .map_err(ServerError::database_error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_payload() {
+        // RFC 4231, Test Case 2
+        assert_eq!(
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            sign_payload("Jefe", b"what do ya want for nothing?")
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(Duration::from_secs(10), backoff(1));
+        assert_eq!(Duration::from_secs(20), backoff(2));
+        assert_eq!(Duration::from_secs(2560), backoff(9));
+        assert_eq!(MAX_BACKOFF, backoff(10));
+        assert_eq!(MAX_BACKOFF, backoff(100));
+    }
+
+    #[test]
+    fn test_validate_webhooks() {
+        let webhook = |url: &str| WebhookConfig {
+            url: url.to_string(),
+            secret: None,
+            events: Vec::new(),
+        };
+
+        assert!(validate_webhooks(&[webhook("https://example.com/hook")], false).is_ok());
+        assert!(validate_webhooks(&[webhook("http://93.184.216.34/")], false).is_ok());
+        assert!(validate_webhooks(&[webhook("ftp://example.com/")], false).is_err());
+        assert!(validate_webhooks(&[webhook("not a url")], false).is_err());
+
+        // Private addresses are refused unless allowed
+        for url in [
+            "http://10.0.0.1:8080/",
+            "http://127.0.0.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:192.168.1.1]/",
+            "http://localhost:8080/",
+            "http://foo.localhost./",
+        ] {
+            assert!(
+                validate_webhooks(&[webhook(url)], false).is_err(),
+                "{}",
+                url
+            );
+            assert!(validate_webhooks(&[webhook(url)], true).is_ok(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_is_public_address() {
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700::1111"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in [
+            "0.0.0.0",
+            "10.1.2.3",
+            "100.64.0.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "172.16.0.1",
+            "192.168.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "::127.0.0.1",
+            "fe80::1",
+            "fd12:3456::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}